@@ -0,0 +1,426 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::assets::AssetError;
+
+// Pack layout, all numbers little endian:
+//
+//     magic        b"RFPK"
+//     version      u32
+//     entry count  u32
+//     directory    entry count times:
+//         name length  u16
+//         name         utf-8 bytes
+//         kind         u8, 0 - texture, 1 - file
+//         width        u32, textures only
+//         height       u32, textures only
+//         compression  u8, 0 - raw, 1 - run length encoded texels
+//         offset       u64, from the start of the data section
+//         size         u64, stored size in bytes
+//     data
+//
+// Texture data is `TEXTURE_FORMAT` texels, row by row without padding.
+const MAGIC: &[u8; 4] = b"RFPK";
+const VERSION: u32 = 1;
+const TEXEL_SIZE: usize = 4;
+/// Directory entry of a file with an empty name
+const MIN_ENTRY_SIZE: usize = 2 + 1 + 1 + 8 + 8;
+/// Names longer than this don't fit in the name length
+const MAX_NAME_LEN: usize = u16::MAX as usize;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EntryKind {
+    Texture { width: u32, height: u32 },
+    /// Any other asset, stored as is
+    File,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Raw,
+    /// Runs of identical texels stored as a count byte followed by the texel
+    Rle,
+}
+
+pub struct PackEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    compression: Compression,
+    offset: usize,
+    size: usize,
+}
+
+/// A single file holding many named assets, read into memory whole
+pub struct AssetPack {
+    path: PathBuf,
+    entries: Vec<PackEntry>,
+    data: Vec<u8>,
+}
+
+impl AssetPack {
+    pub fn open(path: &Path) -> Result<AssetPack, AssetError> {
+        let bytes = fs::read(path).map_err(|err| AssetError::Io(path.to_path_buf(), err))?;
+
+        AssetPack::parse(path, bytes)
+            .map_err(|err| AssetError::Pack(path.to_path_buf(), err))
+    }
+
+    fn parse(path: &Path, bytes: Vec<u8>) -> Result<AssetPack, String> {
+        let mut reader = Reader::new(&bytes, 0);
+        if reader.bytes(4)? != MAGIC {
+            return Err(String::from("not an asset pack"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("unsupported version {}", version));
+        }
+
+        let entry_count = reader.u32()?;
+        // A corrupt count mustn't reserve more than the pack could hold
+        let mut entries = Vec::with_capacity((entry_count as usize).min(reader.remaining() / MIN_ENTRY_SIZE));
+        for _ in 0..entry_count {
+            let name_len = reader.u16()? as usize;
+            let name = String::from_utf8(reader.bytes(name_len)?.to_vec())
+                .map_err(|_| String::from("entry name is not valid utf-8"))?;
+            let kind = match reader.u8()? {
+                0 => EntryKind::Texture {
+                    width: reader.u32()?,
+                    height: reader.u32()?,
+                },
+                1 => EntryKind::File,
+                kind => return Err(format!("\"{}\" has unknown kind {}", name, kind)),
+            };
+            let compression = match reader.u8()? {
+                0 => Compression::Raw,
+                1 => Compression::Rle,
+                compression => {
+                    return Err(format!("\"{}\" has unknown compression {}", name, compression))
+                }
+            };
+            let offset = reader.u64()? as usize;
+            let size = reader.u64()? as usize;
+
+            entries.push(PackEntry {
+                name,
+                kind,
+                compression,
+                offset,
+                size,
+            });
+        }
+
+        let data = bytes[reader.pos..].to_vec();
+        for entry in entries.iter() {
+            if entry.offset.checked_add(entry.size).map_or(true, |end| end > data.len()) {
+                return Err(format!("\"{}\" points past the end of the pack", entry.name));
+            }
+        }
+
+        Ok(AssetPack {
+            path: path.to_path_buf(),
+            entries,
+            data,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&PackEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Returns the entry's decompressed contents
+    pub fn read(&self, entry: &PackEntry) -> Result<Vec<u8>, AssetError> {
+        let stored = &self.data[entry.offset..entry.offset + entry.size];
+        match entry.compression {
+            Compression::Raw => Ok(stored.to_vec()),
+            Compression::Rle => rle_decode(stored).ok_or_else(|| {
+                AssetError::Pack(self.path.clone(), format!("\"{}\" is corrupt", entry.name))
+            }),
+        }
+    }
+}
+
+/// Collects assets and writes them out as a pack
+pub struct PackWriter {
+    compression: Compression,
+    entries: Vec<(String, EntryKind, Compression, Vec<u8>)>,
+}
+
+impl PackWriter {
+    /// `compression` is used for textures, other files are always stored raw
+    pub fn new(compression: Compression) -> PackWriter {
+        PackWriter {
+            compression,
+            entries: vec![],
+        }
+    }
+
+    /// `texels` are `TEXTURE_FORMAT` texels, row by row without padding
+    pub fn add_texture(&mut self, name: &str, width: u32, height: u32, texels: &[u8]) -> Result<(), String> {
+        check_name(name)?;
+        let data = match self.compression {
+            Compression::Raw => texels.to_vec(),
+            Compression::Rle => rle_encode(texels),
+        };
+
+        self.entries.push((
+            String::from(name),
+            EntryKind::Texture { width, height },
+            self.compression,
+            data,
+        ));
+
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> Result<(), String> {
+        check_name(name)?;
+        self.entries.push((
+            String::from(name),
+            EntryKind::File,
+            Compression::Raw,
+            contents.to_vec(),
+        ));
+
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        let mut offset = 0;
+        for (name, kind, compression, data) in self.entries.iter() {
+            bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            match kind {
+                EntryKind::Texture { width, height } => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(&width.to_le_bytes());
+                    bytes.extend_from_slice(&height.to_le_bytes());
+                }
+                EntryKind::File => bytes.push(1),
+            }
+            bytes.push(match compression {
+                Compression::Raw => 0,
+                Compression::Rle => 1,
+            });
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            offset += data.len();
+        }
+
+        for (_, _, _, data) in self.entries.iter() {
+            bytes.extend_from_slice(data);
+        }
+
+        fs::write(path, bytes)
+    }
+}
+
+fn rle_encode(texels: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![];
+    let mut texels = texels.chunks(TEXEL_SIZE).peekable();
+    while let Some(texel) = texels.next() {
+        let mut run_len: u8 = 1;
+        while run_len < u8::max_value() && texels.peek() == Some(&texel) {
+            texels.next();
+            run_len += 1;
+        }
+
+        encoded.push(run_len);
+        encoded.extend_from_slice(texel);
+    }
+
+    encoded
+}
+
+fn rle_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = vec![];
+    for run in encoded.chunks(1 + TEXEL_SIZE) {
+        if run.len() != 1 + TEXEL_SIZE {
+            return None;
+        }
+
+        for _ in 0..run[0] {
+            decoded.extend_from_slice(&run[1..]);
+        }
+    }
+
+    Some(decoded)
+}
+
+/// Reads little endian numbers from a byte buffer
+fn check_name(name: &str) -> Result<(), String> {
+    if name.len() > MAX_NAME_LEN {
+        return Err(format!("asset names can be at most {} bytes long, not {}", MAX_NAME_LEN, name.len()));
+    }
+
+    Ok(())
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Starts reading at byte `pos`
+    pub fn new(bytes: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { bytes, pos }
+    }
+
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.pos + count > self.bytes.len() {
+            return Err(String::from("unexpected end of file"));
+        }
+
+        let bytes = &self.bytes[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    /// Bytes left after the current position
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.pos)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    fn texels(colours: &[[u8; 4]], repeats: &[usize]) -> Vec<u8> {
+        let mut texels = vec![];
+        for (colour, repeat) in colours.iter().zip(repeats) {
+            for _ in 0..*repeat {
+                texels.extend_from_slice(colour);
+            }
+        }
+
+        texels
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let red = [255, 0, 0, 255];
+        let clear = [0, 0, 0, 0];
+        let runs = [
+            vec![],
+            texels(&[red], &[1]),
+            texels(&[red, clear, red], &[1, 1, 1]),
+            // Longer than a count byte can hold
+            texels(&[clear, red], &[600, 3]),
+        ];
+
+        for texels in runs.iter() {
+            assert_eq!(rle_decode(&rle_encode(texels)).as_ref(), Some(texels));
+        }
+    }
+
+    #[test]
+    fn rle_encode_splits_long_runs() {
+        let encoded = rle_encode(&texels(&[[1, 2, 3, 4]], &[300]));
+
+        assert_eq!(encoded, vec![255, 1, 2, 3, 4, 45, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rle_decode_rejects_truncated_runs() {
+        assert_eq!(rle_decode(&[2, 1, 2, 3]), None);
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let texture = texels(&[[9, 8, 7, 6], [0, 0, 0, 0]], &[5, 7]);
+        let file = b"stone 0.5 0.0\n".to_vec();
+        for &compression in [Compression::Raw, Compression::Rle].iter() {
+            let mut writer = PackWriter::new(compression);
+            writer.add_texture("stone", 3, 4, &texture).unwrap();
+            writer.add_file("animations.txt", &file).unwrap();
+
+            let path = env::temp_dir().join(format!("rustic-fps-test-{}-{:?}.pak", process::id(), compression));
+            writer.write(&path).unwrap();
+            let pack = AssetPack::open(&path);
+            fs::remove_file(&path).unwrap();
+            let pack = pack.unwrap();
+
+            let names: Vec<&str> = pack.entries().iter().map(|entry| entry.name.as_str()).collect();
+            assert_eq!(names, vec!["stone", "animations.txt"]);
+            let stone = pack.entry("stone").unwrap();
+            assert_eq!(stone.kind, EntryKind::Texture { width: 3, height: 4 });
+            assert_eq!(pack.read(stone).unwrap(), texture);
+            let animations = pack.entry("animations.txt").unwrap();
+            assert_eq!(animations.kind, EntryKind::File);
+            assert_eq!(pack.read(animations).unwrap(), file);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_entries_past_the_end() {
+        let mut writer = PackWriter::new(Compression::Raw);
+        writer.add_file("file", b"contents").unwrap();
+        let path = env::temp_dir().join(format!("rustic-fps-test-{}-truncated.pak", process::id()));
+        writer.write(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        bytes.truncate(bytes.len() - 1);
+
+        assert!(AssetPack::parse(&path, bytes).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_huge_entry_counts() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&u32::max_value().to_le_bytes());
+
+        assert!(AssetPack::parse(Path::new("huge.pak"), bytes).is_err());
+    }
+
+    #[test]
+    fn writer_rejects_names_too_long_to_store() {
+        let mut writer = PackWriter::new(Compression::Raw);
+        let name = "a".repeat(MAX_NAME_LEN + 1);
+
+        assert!(writer.add_file(&name, b"contents").is_err());
+        assert!(writer.add_texture(&name, 1, 1, &[0; TEXEL_SIZE]).is_err());
+        assert!(writer.add_file(&name[1..], b"contents").is_ok());
+    }
+}
@@ -0,0 +1,208 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Environment variable that overrides the asset root
+pub const ASSETS_ENV_VAR: &'static str = "RUSTIC_FPS_ASSETS";
+/// Config file looked up in the working directory and next to the executable
+pub const CONFIG_FILE_NAME: &'static str = "rustic-fps.cfg";
+/// Folder under the asset root that textures live in
+pub const TEXTURES_DIR: &'static str = "textures";
+/// Asset pack under the asset root, used when there's no textures folder
+pub const PACK_FILE_NAME: &'static str = "assets.pak";
+
+#[derive(Debug)]
+pub enum AssetError {
+    /// None of the searched directories contained the assets
+    RootNotFound(Vec<PathBuf>),
+    Io(PathBuf, io::Error),
+    Config(PathBuf, String),
+    Texture(PathBuf, String),
+    Pack(PathBuf, String),
+    /// Data from another game that couldn't be converted
+    Import(PathBuf, String),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetError::RootNotFound(searched) => {
+                write!(
+                    f,
+                    "Could not find the \"{}\" folder or \"{}\", searched in:",
+                    TEXTURES_DIR, PACK_FILE_NAME
+                )?;
+                for path in searched {
+                    write!(f, "\n    {}", path.display())?;
+                }
+                Ok(())
+            }
+            AssetError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            AssetError::Config(path, err) => write!(f, "{}: {}", path.display(), err),
+            AssetError::Texture(path, err) => {
+                write!(f, "Failed loading texture {}: {}", path.display(), err)
+            }
+            AssetError::Pack(path, err) => {
+                write!(f, "Failed reading asset pack {}: {}", path.display(), err)
+            }
+            AssetError::Import(path, err) => {
+                write!(f, "Failed importing {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl Error for AssetError {}
+
+/// Finds the directory assets are loaded from. An explicitly configured root
+/// (command line flag, then environment variable, then config file) is used
+/// as is, otherwise the working directory and the executable's surroundings
+/// are searched for a textures folder or an asset pack.
+pub fn asset_root(cli_root: Option<&Path>) -> Result<PathBuf, AssetError> {
+    if let Some(root) = cli_root {
+        return checked_root(root.to_path_buf());
+    }
+
+    if let Some(root) = env::var_os(ASSETS_ENV_VAR) {
+        return checked_root(PathBuf::from(root));
+    }
+
+    if let Some(root) = config_asset_root()? {
+        return checked_root(root);
+    }
+
+    let mut candidates = vec![];
+    if let Ok(cwd) = env::current_dir() {
+        candidates.push(cwd);
+    }
+    if let Some(exe_dir) = exe_dir() {
+        candidates.push(exe_dir.clone());
+        // Running straight out of target/<profile>
+        if let Some(project_dir) = exe_dir.parent().and_then(Path::parent) {
+            candidates.push(project_dir.to_path_buf());
+        }
+    }
+
+    for candidate in candidates.iter() {
+        if has_assets(candidate) {
+            return Ok(candidate.clone());
+        }
+    }
+
+    Err(AssetError::RootNotFound(candidates))
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+fn has_assets(root: &Path) -> bool {
+    root.join(TEXTURES_DIR).is_dir() || root.join(PACK_FILE_NAME).is_file()
+}
+
+fn checked_root(root: PathBuf) -> Result<PathBuf, AssetError> {
+    if has_assets(&root) {
+        Ok(root)
+    } else {
+        Err(AssetError::RootNotFound(vec![root]))
+    }
+}
+
+/// Reads `assets = <path>` from the first config file found. Relative paths
+/// are relative to the config file.
+fn config_asset_root() -> Result<Option<PathBuf>, AssetError> {
+    let mut config_dirs = vec![];
+    if let Ok(cwd) = env::current_dir() {
+        config_dirs.push(cwd);
+    }
+    if let Some(exe_dir) = exe_dir() {
+        config_dirs.push(exe_dir);
+    }
+
+    for dir in config_dirs {
+        let config_path = dir.join(CONFIG_FILE_NAME);
+        if !config_path.is_file() {
+            continue;
+        }
+
+        let config = fs::read_to_string(&config_path)
+            .map_err(|err| AssetError::Io(config_path.clone(), err))?;
+        for (line_index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut key_value = line.splitn(2, '=');
+            let key = key_value.next().unwrap().trim();
+            let value = match key_value.next() {
+                Some(value) => value.trim(),
+                None => {
+                    return Err(AssetError::Config(
+                        config_path,
+                        format!("line {}: expected \"key = value\"", line_index + 1),
+                    ))
+                }
+            };
+
+            if key == "assets" {
+                return Ok(Some(dir.join(value)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Recursively lists files with the given extension under `dir`, each paired
+/// with its name relative to `dir` without the extension, e.g.
+/// "walls/bluestone" for `dir/walls/bluestone.png`
+pub fn discover(dir: &Path, extension: &str) -> Result<Vec<(String, PathBuf)>, AssetError> {
+    let mut found = vec![];
+    discover_into(dir, "", extension, &mut found)?;
+    found.sort();
+
+    Ok(found)
+}
+
+fn discover_into(
+    dir: &Path,
+    namespace: &str,
+    extension: &str,
+    found: &mut Vec<(String, PathBuf)>,
+) -> Result<(), AssetError> {
+    let entries = fs::read_dir(dir).map_err(|err| AssetError::Io(dir.to_path_buf(), err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| AssetError::Io(dir.to_path_buf(), err))?.path();
+        let is_dir = path.is_dir();
+        if !is_dir && path.extension().map_or(true, |ext| ext != extension) {
+            continue;
+        }
+
+        // Folders keep their whole name, files lose the extension
+        let stem = if is_dir { path.file_name() } else { path.file_stem() };
+        let stem = match stem.and_then(|stem| stem.to_str()) {
+            Some(stem) => stem,
+            None => continue,
+        };
+        let name = if namespace.is_empty() {
+            String::from(stem)
+        } else {
+            format!("{}/{}", namespace, stem)
+        };
+
+        if is_dir {
+            discover_into(&path, &name, extension, found)?;
+        } else {
+            found.push((name, path));
+        }
+    }
+
+    Ok(())
+}
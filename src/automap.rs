@@ -0,0 +1,362 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use crate::font;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::game::GameOpts;
+use crate::map;
+use crate::map::MapView;
+use crate::map::PanZoom;
+use crate::render_precedence::RenderPrecedence;
+use crate::tile_map::ThingKind;
+use crate::vector::Vec2f;
+
+/// Pixels per tile
+const MIN_ZOOM: f32 = 2.0;
+const MAX_ZOOM: f32 = 64.0;
+/// How close to a marker a right click removes it, in pixels
+const MARKER_PICK_DISTANCE: f32 = 8.0;
+const MAX_MARKER_LEN: usize = 24;
+const TEXT_SCALE: u32 = 2;
+const MARGIN: i32 = 4;
+
+const MARKER_COLOR: [u8; 3] = [255, 80, 80];
+
+const HELP_TEXT: &'static str = "TAB CLOSE  ARROWS PAN  +/- ZOOM  HOME CENTER  ENTER MARK";
+
+/// See `map::plot_icon`
+const MARKER_ICON: [u8; 5] = [0b01110, 0b11111, 0b01110, 0b00100, 0b00100];
+
+/// A note the player left on the map
+struct Marker {
+    pos: Vec2f,
+    text: String,
+}
+
+/// Fullscreen map of everything explored so far, toggled with Tab. The
+/// player can't move while it's open.
+pub struct Automap {
+    open: bool,
+    automap_surface: Surface<'static>,
+    pan_zoom: PanZoom,
+    /// Where the map is centred, None to follow the player
+    center: Option<Vec2f>,
+    player_pos: Vec2f,
+    markers: Vec<Marker>,
+    /// Index of the marker whose text is being typed
+    editing: Option<usize>,
+    dragging: bool,
+}
+
+impl Automap {
+    pub fn new(game_opts: &GameOpts) -> Automap {
+        let automap_surface = Surface::new(
+            game_opts.screen_width,
+            game_opts.screen_height,
+            PixelFormatEnum::RGB24,
+        )
+        .unwrap();
+
+        Automap {
+            open: false,
+            automap_surface,
+            pan_zoom: PanZoom::new(MIN_ZOOM, MAX_ZOOM),
+            center: None,
+            player_pos: Vec2f::new(0.0, 0.0),
+            markers: vec![],
+            editing: None,
+            dragging: false,
+        }
+    }
+
+    fn view(&self) -> MapView {
+        MapView::new(
+            self.center.unwrap_or(self.player_pos),
+            Vec2f::new(0.0, -1.0),
+            false,
+            self.pan_zoom.zoom(),
+            self.automap_surface.width() as i32,
+            self.automap_surface.height() as i32,
+        )
+    }
+
+    fn toggle(&mut self) {
+        self.finish_marker();
+        self.open = !self.open;
+        self.dragging = false;
+        self.pan_zoom.release_keys();
+    }
+
+    /// Removes the marker under the pixel or starts a new one there
+    fn place_marker(&mut self, x: i32, y: i32) {
+        self.finish_marker();
+
+        let view = self.view();
+        let under_cursor = self.markers.iter().position(|marker| {
+            let (marker_x, marker_y) = view.to_screen(marker.pos);
+            (marker_x - x as f32).abs() <= MARKER_PICK_DISTANCE && (marker_y - y as f32).abs() <= MARKER_PICK_DISTANCE
+        });
+        match under_cursor {
+            Some(index) => {
+                self.markers.remove(index);
+            }
+            None => {
+                self.markers.push(Marker {
+                    pos: view.to_world(x as f32, y as f32),
+                    text: String::new(),
+                });
+                self.editing = Some(self.markers.len() - 1);
+            }
+        }
+    }
+
+    /// Stops typing, markers left without text are dropped
+    fn finish_marker(&mut self) {
+        if let Some(index) = self.editing.take() {
+            if self.markers[index].text.is_empty() {
+                self.markers.remove(index);
+            }
+        }
+    }
+
+    fn handle_typing(&mut self, index: usize, event: Event) -> Option<Event> {
+        match event {
+            Event::TextInput { text, .. } => {
+                let marker = &mut self.markers[index];
+                for c in text.chars() {
+                    if marker.text.chars().count() < MAX_MARKER_LEN {
+                        marker.text.push(c);
+                    }
+                }
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace),
+                ..
+            } => {
+                self.markers[index].text.pop();
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Return),
+                ..
+            }
+            | Event::KeyDown {
+                keycode: Some(Keycode::KpEnter),
+                ..
+            } => {
+                self.finish_marker();
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Tab),
+                ..
+            } => {
+                self.toggle();
+                None
+            }
+            // Keys typed into the marker aren't commands
+            Event::KeyDown { .. } => None,
+            event => self.handle_open(event),
+        }
+    }
+
+    fn handle_open(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => {
+                match keycode {
+                    Keycode::Tab => self.toggle(),
+                    Keycode::Home => self.center = None,
+                    Keycode::Return | Keycode::KpEnter => {
+                        let (width, height) = (self.automap_surface.width(), self.automap_surface.height());
+                        self.place_marker(width as i32 / 2, height as i32 / 2);
+                    }
+                    _ => self.pan_zoom.key_down(keycode),
+                }
+                // Movement keys included, the player stays put
+                None
+            }
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } if PanZoom::is_pan_key(keycode) => {
+                self.pan_zoom.key_up(keycode);
+                None
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = true;
+                None
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                self.dragging = false;
+                None
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Right,
+                x,
+                y,
+                ..
+            } => {
+                self.place_marker(x, y);
+                None
+            }
+            Event::MouseMotion { xrel, yrel, .. } => {
+                if self.dragging {
+                    let center = self.center.unwrap_or(self.player_pos);
+                    self.center = Some(self.pan_zoom.drag(center, xrel, yrel));
+                }
+                None
+            }
+            Event::MouseWheel { y, .. } => {
+                self.pan_zoom.wheel(y);
+                None
+            }
+            Event::MouseButtonDown { .. } | Event::MouseButtonUp { .. } => None,
+            // Key releases still reach the player so no key stays held down
+            _ => Some(event),
+        }
+    }
+}
+
+impl GameComponent for Automap {
+    fn update(&mut self, game: &Game, dt: u32) {
+        self.player_pos = *game.player().pos();
+        if !self.open {
+            return;
+        }
+
+        game.pause_movement();
+        // Follows the player until panned away from them
+        if let Some(center) = self.center {
+            self.center = Some(self.pan_zoom.pan(center, dt));
+        } else {
+            let center = self.pan_zoom.pan(self.player_pos, dt);
+            if center != self.player_pos {
+                self.center = Some(center);
+            }
+        }
+    }
+
+    fn draw(&mut self, game: &Game, _: u32) -> Option<&Surface> {
+        if !self.open {
+            return None;
+        }
+
+        let tile_map = game.tile_map();
+        let camera_frame = game.camera_frame();
+        let explored = game.explored();
+        let player_dir = game.player().dir().normalize();
+
+        let view = self.view();
+        let width = self.automap_surface.width() as i32;
+        let height = self.automap_surface.height() as i32;
+        let pitch = self.automap_surface.pitch() as usize;
+        let zoom = self.pan_zoom.zoom();
+        let icon_scale = ((zoom / 8.0) as i32).max(1);
+        let player_pos = self.player_pos;
+        let markers = &self.markers;
+
+        self.automap_surface.with_lock_mut(|buffer: &mut [u8]| {
+            let mut put = |x: i32, y: i32, color: [u8; 3]| {
+                if 0 <= x && x < width && 0 <= y && y < height {
+                    let index = y as usize * pitch + x as usize * 3;
+                    buffer[index..index + 3].copy_from_slice(&color);
+                }
+            };
+
+            for y in 0..height {
+                for x in 0..width {
+                    let world_pos = view.to_world(x as f32 + 0.5, y as f32 + 0.5);
+                    put(x, y, map::tile_color(&tile_map, &camera_frame, Some(&explored), world_pos, zoom));
+                }
+            }
+
+            for thing in tile_map.things() {
+                let seen = tile_map
+                    .index_at(thing.pos)
+                    .map_or(false, |map_index| explored.is_explored(map_index));
+                // Enemies move around, so where they were seen isn't worth showing
+                if !seen || thing.kind == ThingKind::Enemy {
+                    continue;
+                }
+
+                let (icon, color) = map::thing_icon(thing.kind);
+                map::plot_icon(view.to_screen(thing.pos), &icon, icon_scale, |x, y| put(x, y, color));
+            }
+            for marker in markers.iter() {
+                map::plot_icon(view.to_screen(marker.pos), &MARKER_ICON, icon_scale, |x, y| {
+                    put(x, y, MARKER_COLOR)
+                });
+            }
+
+            map::plot_player(&view, player_pos, player_dir, |x, y| put(x, y, map::PLAYER_COLOR));
+        });
+
+        let marker_color = Color::RGB(MARKER_COLOR[0], MARKER_COLOR[1], MARKER_COLOR[2]);
+        for (index, marker) in self.markers.iter().enumerate() {
+            let (x, y) = view.to_screen(marker.pos);
+            let text = if self.editing == Some(index) {
+                format!("{}_", marker.text)
+            } else {
+                marker.text.clone()
+            };
+            font::draw_text(
+                &mut self.automap_surface,
+                x.round() as i32 + (map::ICON_SIZE * icon_scale) / 2 + MARGIN,
+                y.round() as i32 - font::text_height(TEXT_SCALE) as i32 / 2,
+                &text,
+                TEXT_SCALE,
+                marker_color,
+            );
+        }
+
+        font::draw_text(
+            &mut self.automap_surface,
+            MARGIN,
+            height - MARGIN - font::text_height(TEXT_SCALE) as i32,
+            HELP_TEXT,
+            TEXT_SCALE,
+            Color::RGB(160, 160, 160),
+        );
+
+        Some(&self.automap_surface)
+    }
+
+    fn handle_event(&mut self, event: Event) -> Option<Event> {
+        if !self.open {
+            return match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => {
+                    self.toggle();
+                    None
+                }
+                _ => Some(event),
+            };
+        }
+
+        match self.editing {
+            Some(index) => self.handle_typing(index, event),
+            None => self.handle_open(event),
+        }
+    }
+
+    fn render_precendce(&self) -> RenderPrecedence {
+        RenderPrecedence::Automap
+    }
+}
@@ -0,0 +1,65 @@
+//! Packs the textures folder into a single asset pack the game can load
+//! instead of the folder:
+//!
+//!     pack_assets [--raw] [<asset root>] [<output file>]
+//!
+//! The asset root is found the same way the game finds it when not given and
+//! the pack is written to `<asset root>/assets.pak` by default.
+
+extern crate sdl2;
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use rustic_fps::asset_pack::Compression;
+use rustic_fps::asset_pack::PackWriter;
+use rustic_fps::assets;
+use rustic_fps::texture_animations;
+use rustic_fps::textures::Texture;
+
+pub fn main() -> Result<(), Box<dyn Error>> {
+    let mut compression = Compression::Rle;
+    let mut paths = vec![];
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--raw" => compression = Compression::Raw,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.len() > 2 {
+        return Err("Usage: pack_assets [--raw] [<asset root>] [<output file>]".into());
+    }
+
+    let asset_root = assets::asset_root(paths.get(0).map(PathBuf::as_path))?;
+    let tex_dir = asset_root.join(assets::TEXTURES_DIR);
+    let pack_path = paths
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| asset_root.join(assets::PACK_FILE_NAME));
+
+    let mut pack = PackWriter::new(compression);
+    for (tex_name, tex_path) in assets::discover(&tex_dir, "png")? {
+        println!("Packing tex \"{}\" from {}", tex_name, tex_path.display());
+
+        let tex = Texture::new(&tex_path)?;
+        let (width, height) = tex.surface().size();
+        pack.add_texture(&tex_name, width, height, &tex.to_rgba())?;
+    }
+
+    let definitions_path = tex_dir.join(texture_animations::ANIMATIONS_FILE_NAME);
+    if definitions_path.is_file() {
+        println!("Packing {}", definitions_path.display());
+        pack.add_file(
+            texture_animations::ANIMATIONS_FILE_NAME,
+            &fs::read(&definitions_path)?,
+        )?;
+    }
+
+    pack.write(Path::new(&pack_path))?;
+    println!("Wrote {}", pack_path.display());
+
+    Ok(())
+}
@@ -1,17 +1,62 @@
+use std::collections::HashMap;
 use std::f32;
+use std::path::Path;
 
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::surface::Surface;
 
+use crate::assets::AssetError;
+use crate::events::GameEvent;
 use crate::game::Game;
 use crate::game::GameComponent;
 use crate::game::GameOpts;
+use crate::hot_reload::FileWatcher;
+use crate::palette::Colormap;
+use crate::palette::Palette;
+use crate::palette::PaletteFlash;
+use crate::palette::PALETTE_FILE_NAME;
 use crate::vector::Vec2f;
+use crate::rays::Ray;
 use crate::rays::RayGenerator;
+use crate::textures::Texel;
+use crate::textures::Texture;
+use crate::textures::TextureFilter;
 use crate::textures::TextureLoader;
+use crate::tile_map::TileMap;
 
 use crate::map;
 
+/// What the camera's last frame covered, for the maps to show
+pub struct CameraFrame {
+    pub origin: Vec2f,
+    /// Where each screen column's ray stopped, at an opaque wall or the view
+    /// distance
+    pub ray_ends: Vec<Vec2f>,
+    /// Average colour of each tile id's texture
+    pub tile_colors: HashMap<u16, [u8; 3]>,
+}
+
+impl CameraFrame {
+    pub fn new() -> CameraFrame {
+        CameraFrame {
+            origin: Vec2f::new(0.0, 0.0),
+            ray_ends: vec![],
+            tile_colors: HashMap::new(),
+        }
+    }
+}
+
+/// How the camera view's colours are produced
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    TrueColor,
+    /// 8-bit look, textures quantized to a 256 colour palette with fog done
+    /// through colormap tables
+    Palette,
+}
+
 pub struct Camera {
     fov_ang: f32,
     view_dst: f32,
@@ -19,14 +64,62 @@ pub struct Camera {
     screen_width: u32,
 
     tex_loader: TextureLoader,
+    mipmapping: bool,
+    tex_filter: TextureFilter,
+    /// Watches the texture files for changes in development mode
+    tex_watcher: Option<FileWatcher>,
+    map_revision: u32,
+    /// Tile colours have to be shared again after the textures changed
+    tile_colors_changed: bool,
+
+    render_mode: RenderMode,
+    palette: Palette,
+    /// Built the first time palette mode is used, it takes a moment
+    colormap: Option<Colormap>,
+    /// Palette indices of the camera view in palette mode
+    indexed_view: Vec<u8>,
+    /// Active flash and how long it has been shown for, in microseconds
+    palette_flash: Option<(PaletteFlash, u32)>,
+    /// 0.0 is pitch black, 1.0 fully lit, set by map triggers
+    light: f32,
 
     camera_view: Surface<'static>,
     last_position_drawn_from: Vec2f,
     last_dir_drawn_from: Vec2f,
+    last_eye_height_drawn_from: f32,
+    last_pitch_drawn_from: f32,
 }
 
 impl Camera {
-    pub fn new(fov_ang: f32, view_dst: f32, game_opts: &GameOpts) -> Camera {
+    pub fn new(
+        fov_ang: f32,
+        view_dst: f32,
+        game_opts: &GameOpts,
+        asset_root: &Path,
+        tex_loader: TextureLoader,
+        tile_map: &TileMap,
+    ) -> Result<Camera, AssetError> {
+        let tex_watcher = if game_opts.hot_reload {
+            let mut tex_watcher = FileWatcher::new();
+            for tex_path in tex_loader.source_files()? {
+                tex_watcher.watch(&tex_path);
+            }
+            // Textures added while the game runs are loaded too
+            if let Some(tex_dir) = tex_loader.directory() {
+                tex_watcher.watch_dir(tex_dir, "png");
+            }
+            Some(tex_watcher)
+        } else {
+            None
+        };
+
+        let palette_path = asset_root.join(PALETTE_FILE_NAME);
+        let palette = if palette_path.is_file() {
+            Palette::load(&palette_path)?
+        } else {
+            Palette::generated()
+        };
+
         let camera_view = Surface::new(
             game_opts.screen_width,
             game_opts.screen_height,
@@ -34,109 +127,495 @@ impl Camera {
         )
         .unwrap();
 
-        Camera {
+        let mut camera = Camera {
             fov_ang,
             view_dst,
             sqr_view_dst: view_dst.powf(2.0),
             screen_width: game_opts.screen_width,
 
-            tex_loader: TextureLoader::new_eager(),
+            tex_loader,
+            mipmapping: true,
+            tex_filter: TextureFilter::Nearest,
+            tex_watcher,
+            map_revision: tile_map.revision(),
+            tile_colors_changed: true,
+
+            render_mode: RenderMode::TrueColor,
+            palette,
+            colormap: None,
+            indexed_view: vec![0; (game_opts.screen_width * game_opts.screen_height) as usize],
+            palette_flash: None,
+            light: 1.0,
 
             camera_view,
             last_position_drawn_from: Vec2f::new(f32::MAX, f32::MAX),
             last_dir_drawn_from: Vec2f::new(f32::MAX, f32::MAX),
+            last_eye_height_drawn_from: f32::MAX,
+            last_pitch_drawn_from: f32::MAX,
+        };
+        camera.load_map_textures(tile_map);
+        camera.set_render_mode(game_opts.render_mode);
+
+        Ok(camera)
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        if render_mode == RenderMode::Palette && self.colormap.is_none() {
+            self.colormap = Some(Colormap::new(self.palette.clone()));
         }
+
+        self.render_mode = render_mode;
+        self.invalidate();
+    }
+
+    /// Tints the palette for a moment. Only shows in palette mode.
+    pub fn flash(&mut self, flash: PaletteFlash) {
+        self.palette_flash = Some((flash, 0));
     }
+
+    /// The palette the indexed view is shown with, tinted by the active flash
+    fn display_palette(&self, colormap: &Colormap) -> Palette {
+        match self.palette_flash {
+            Some((flash, elapsed_us)) => {
+                let fade = 1.0 - elapsed_us as f32 / flash.duration_us() as f32;
+                colormap.palette().tinted(flash.tint(), flash.strength() * fade)
+            }
+            None => colormap.palette().clone(),
+        }
+    }
+
+    /// Loads every texture the map uses, or a placeholder in its place
+    fn load_map_textures(&mut self, tile_map: &TileMap) {
+        for tile in tile_map.tiles().iter() {
+            self.tex_loader.load_texture(tile_map.texture_name(*tile));
+        }
+        self.tile_colors_changed = true;
+    }
+
+    /// Reloads textures whose files changed since the last check
+    fn reload_textures(&mut self, game: &Game, dt: u32) {
+        let changed = match self.tex_watcher.as_mut() {
+            Some(tex_watcher) => tex_watcher.poll(dt),
+            None => return,
+        };
+        if changed.is_empty() {
+            return;
+        }
+
+        for tex_path in changed {
+            match self.tex_loader.reload_file(&tex_path) {
+                Ok(()) => game.show_message(&format!("Reloaded {}", tex_path.display())),
+                Err(err) => game.show_error(&err.to_string()),
+            }
+        }
+
+        // Reloading an asset pack replaces all the textures, placeholders included
+        self.load_map_textures(&game.tile_map());
+        self.invalidate();
+    }
+
+    /// Loads the textures of the tiles the map changed to since the last
+    /// check. Events like scripts setting tiles change the map after the
+    /// components update, so this runs before drawing too.
+    fn load_changed_map_textures(&mut self, game: &Game) {
+        let map_revision = game.tile_map().revision();
+        if map_revision != self.map_revision {
+            self.map_revision = map_revision;
+            self.load_map_textures(&game.tile_map());
+            self.invalidate();
+        }
+    }
+
+    /// Forces the next frame to be redrawn even if the player hasn't moved
+    fn invalidate(&mut self) {
+        self.last_position_drawn_from = Vec2f::new(f32::MAX, f32::MAX);
+    }
+}
+
+/// A single tile the ray passed through on its way to the first opaque wall
+struct WallHit<'a> {
+    /// Perpendicular distance to the face the ray entered the tile through
+    dst: f32,
+    /// Perpendicular distance to the point the ray leaves the tile
+    exit_dst: f32,
+    heights: map::TileHeights,
+    tex: &'a Texture,
+    /// Horizontal texture coordinate of the hit, 0.0 to 1.0
+    tex_u: f32,
 }
 
 impl GameComponent for Camera {
+    fn update(&mut self, game: &Game, dt: u32) {
+        self.reload_textures(game, dt);
+        self.load_changed_map_textures(game);
+
+        if let Some(flash) = game.take_palette_flash() {
+            self.flash(flash);
+        }
+        if let Some((flash, elapsed_us)) = self.palette_flash {
+            let elapsed_us = elapsed_us + dt;
+            self.palette_flash = if elapsed_us < flash.duration_us() {
+                Some((flash, elapsed_us))
+            } else {
+                None
+            };
+            self.invalidate();
+        }
+
+        self.tex_loader.advance(dt);
+        if self.tex_loader.is_animated() {
+            self.invalidate();
+        }
+    }
+
+    fn handle_game_event(&mut self, _: &Game, event: &GameEvent) {
+        if let GameEvent::SetLight(light) = event {
+            self.light = *light;
+            self.invalidate();
+        }
+    }
+
     fn draw(&mut self, game: &Game, _: u32) -> Option<&Surface> {
+        self.load_changed_map_textures(game);
         if self.last_position_drawn_from == *game.player().pos()
             && self.last_dir_drawn_from == *game.player().dir()
+            && self.last_eye_height_drawn_from == game.player().view_height()
+            && self.last_pitch_drawn_from == game.player().pitch()
         {
             return Some(&self.camera_view);
         }
         self.last_position_drawn_from = *game.player().pos();
         self.last_dir_drawn_from = *game.player().dir();
+        self.last_eye_height_drawn_from = game.player().view_height();
+        self.last_pitch_drawn_from = game.player().pitch();
+
+        let player_pos = *game.player().pos();
+        let player_dir = *game.player().dir();
+        let tile_map = game.tile_map();
+        let mut ray_ends = Vec::with_capacity(self.screen_width as usize);
+        let mut explored = game.explored_mut();
+        explored.fit(&tile_map);
+        if let Some(player_index) = tile_map.index_at(player_pos) {
+            explored.mark(player_index);
+        }
+
+        if self.tile_colors_changed {
+            let mut tile_colors = HashMap::new();
+            for tile in tile_map.tiles().iter() {
+                tile_colors.entry(*tile).or_insert_with(|| {
+                    let color = texture(&self.tex_loader, tile_map.texture_name(*tile)).average_color();
+                    [color[0], color[1], color[2]]
+                });
+            }
+            game.camera_frame_mut().tile_colors = tile_colors;
+            self.tile_colors_changed = false;
+        }
+
+        let camera_view_width = self.camera_view.width() as i32;
+        let camera_view_height = self.camera_view.height() as i32;
+        // Looking up or down shears the view vertically instead of rotating it
+        let horizon = camera_view_height as f32 * (0.5 + self.last_pitch_drawn_from);
+        let eye_height = self.last_eye_height_drawn_from;
+        let max_view_dst = self.view_dst;
 
         for ray in RayGenerator::new(self.last_dir_drawn_from, self.screen_width, self.fov_ang) {
-            let mut tex = self.tex_loader.texture("bluestone");
-            let mut ray_pos = *game.player().pos();
+            let mut ray_pos = player_pos;
+            let mut hits = vec![];
 
+            // Keep walking the grid past walls that leave an opening, Build-style,
+            // so that whatever is behind low walls and windows gets drawn too
             loop {
-                if (game.player().pos() - ray_pos).sqr_len() >= self.sqr_view_dst {
+                if (player_pos - ray_pos).sqr_len() >= self.sqr_view_dst {
                     break;
                 }
 
-                let ray_continuation_coeff = ray
-                    .dst_to_grid_line(&ray_pos)
-                    .div_coeffs(&ray.dir)
-                    .get_smaller_abs_coeff();
+                ray_pos = next_grid_crossing(&ray, &ray_pos);
 
-                ray_pos = ray_pos + ray.dir * ray_continuation_coeff;
+                // Maps without a closed outer wall let rays escape
+                let (column, row) = match ray.to_tile_coords(&ray_pos) {
+                    Some(tile_coords) => tile_coords,
+                    None => break,
+                };
+                let (map_index, tile) = match (tile_map.index(column, row), tile_map.tile(column, row)) {
+                    (Some(map_index), Some(tile)) => (map_index, tile),
+                    _ => break,
+                };
+                explored.mark(map_index);
+                if tile == 0 {
+                    continue;
+                }
+
+                if let Some(thin_wall) = map::thin_wall(tile) {
+                    let tile_pos = Vec2f::new(column as f32, row as f32);
+                    if let Some(hit_pos) = intersect_thin_wall(&ray, &ray_pos, &tile_pos, &thin_wall) {
+                        let dst = (hit_pos - player_pos).project_onto(&player_dir);
+                        if dst < max_view_dst {
+                            let tex = texture(&self.tex_loader, tile_map.texture_name(tile));
+                            let tex_u = match thin_wall.axis {
+                                map::ThinWallAxis::X => hit_pos.y().fract(),
+                                map::ThinWallAxis::Y => hit_pos.x().fract(),
+                            };
 
-                let map_index = ray.to_map_index(&ray_pos, 20);
-                if map::MAP[map_index] != 0 {
-                    if map::MAP[map_index] == 2 {
-                        tex = self.tex_loader.texture("eagle")
-                    } else if map::MAP[map_index] == 3 {
-                        tex = self.tex_loader.texture("redbrick");
+                            hits.push(WallHit {
+                                dst,
+                                exit_dst: dst,
+                                heights: map::TileHeights {
+                                    floor: 1.0,
+                                    ceiling: 1.0,
+                                },
+                                tex,
+                                tex_u,
+                            });
+                        }
                     }
 
+                    continue;
+                }
+
+                let dst = (ray_pos - player_pos).project_onto(&player_dir);
+                if dst >= max_view_dst {
                     break;
                 }
-            }
 
-            let camera_view_width = self.camera_view.width() as i32;
-            let camera_view_height = self.camera_view.height() as i32;
-            let dst = (ray_pos - game.player().pos()).project_onto(game.player().dir());
-            let block_size = ((camera_view_height as f32) / dst) as i32;
+                let exit_pos = next_grid_crossing(&ray, &ray_pos);
+                let exit_dst = (exit_pos - player_pos).project_onto(&player_dir);
 
-            let dst_for_fogging = if dst < self.view_dst / 2.0 {
-                0.0
-            } else {
-                dst - (self.view_dst / 2.0)
-            };
-            let fogging = (dst_for_fogging / self.view_dst * 2.0 * 255.0) as i32;
-            let fogging = clamp(fogging, 0, 255);
+                let tex = texture(&self.tex_loader, tile_map.texture_name(tile));
+                let tex_u = if ray_pos.x().fract() == 0.0 {
+                    ray_pos.y().fract()
+                } else {
+                    ray_pos.x().fract()
+                };
 
-            let block_line_bot = camera_view_height / 2 - block_size;
-            let block_line_top = camera_view_height / 2 + block_size;
+                let heights = map::tile_heights(tile);
+                hits.push(WallHit {
+                    dst,
+                    exit_dst,
+                    heights,
+                    tex,
+                    tex_u,
+                });
 
-            let tex_x_index = if ray_pos.x().fract() == 0.0 {
-                ray_pos.y().fract() 
-            } else { 
-                ray_pos.x().fract() 
-            };
-            //let tex = self.tex_loader.texture("eagle").unwrap();
-            let tex = tex.unwrap();
-            let tex_x_index = tex_x_index * tex.surface().width() as f32;
-            let tex_x_index = tex_x_index as u32;
+                if heights.is_opaque() {
+                    break;
+                }
+            }
 
-            let max_view_dst = self.view_dst;
+            let to_ray_end = ray_pos - player_pos;
+            ray_ends.push(if to_ray_end.sqr_len() > self.sqr_view_dst {
+                player_pos + to_ray_end * (self.view_dst / to_ray_end.len())
+            } else {
+                ray_pos
+            });
 
+            let view_dst = self.view_dst;
+            let darkness = ((1.0 - self.light) * 255.0) as i32;
+            let mipmapping = self.mipmapping;
+            let tex_filter = self.tex_filter;
+            let colormap = match self.render_mode {
+                RenderMode::TrueColor => None,
+                RenderMode::Palette => self.colormap.as_ref(),
+            };
+            let indexed_view = &mut self.indexed_view;
             self.camera_view.with_lock_mut(|buf| {
+                let mut put = |y: i32, texel: Texel, fogging: i32| {
+                    let pixel = (y * camera_view_width + ray.column_index as i32) as usize;
+                    match colormap {
+                        Some(colormap) => put_indexed_pixel(indexed_view, pixel, texel, fogging, colormap),
+                        None => put_pixel(buf, pixel * 3, texel, fogging),
+                    }
+                };
+
                 for y in 0..camera_view_height {
-                    let index = (y * camera_view_width * 3 + ray.column_index as i32 * 3) as usize;
-                    if block_line_bot < y && y < block_line_top && dst < max_view_dst {
-                        let tex_y_index = (y - block_line_bot) as f32 / (block_line_top - block_line_bot) as f32;
-                        let tex_y_index = (tex_y_index * tex.surface().height() as f32) as u32;
-                        let color = tex.pixel(tex_x_index, tex_y_index);
-
-                        buf[index] = clamp(color.r as i32 - fogging, 0, 255) as u8;
-                        buf[index + 1] = clamp(color.g as i32 - fogging, 0, 255) as u8;
-                        buf[index + 2] = clamp(color.b as i32 - fogging, 0, 255) as u8;
+                    put(y, [0, 0, 0, 255], 0);
+                }
+
+                // Painter's algorithm - nearer walls are composited over the ones behind them
+                for hit in hits.iter().rev() {
+                    let fogging = clamp(fogging(hit.dst, view_dst) + darkness, 0, 255);
+                    let pixels_per_unit = 2.0 * camera_view_height as f32 / hit.dst;
+                    let to_screen_y = |height: f32, dst: f32| {
+                        horizon + (eye_height - height) * 2.0 * camera_view_height as f32 / dst
+                    };
+                    let mip_level = if mipmapping {
+                        hit.tex.mip_level_for(pixels_per_unit)
                     } else {
-                        buf[index] = 0;
-                        buf[index + 1] = 0;
-                        buf[index + 2] = 0;
+                        0
+                    };
+
+                    let tex_column = hit.tex.column(hit.tex_u, mip_level);
+
+                    for (bot, top) in hit.heights.solid_spans() {
+                        let span_top = clamp(to_screen_y(top, hit.dst) as i32, 0, camera_view_height);
+                        let span_bot = clamp(to_screen_y(bot, hit.dst) as i32, 0, camera_view_height);
+
+                        for y in span_top..span_bot {
+                            let height = eye_height - (y as f32 - horizon) / pixels_per_unit;
+                            let tex_v = (1.0 - height).rem_euclid(1.0);
+                            let texel = match tex_filter {
+                                TextureFilter::Nearest => tex_column.at(tex_v),
+                                TextureFilter::Bilinear => hit.tex.sample(hit.tex_u, tex_v, mip_level, tex_filter),
+                            };
+
+                            put(y, texel, fogging);
+                        }
+
+                        // Horizontal faces of the span that can be seen from the eye
+                        let cap_height = if top < eye_height {
+                            top
+                        } else if bot > eye_height {
+                            bot
+                        } else {
+                            continue;
+                        };
+                        let near = clamp(to_screen_y(cap_height, hit.dst) as i32, 0, camera_view_height);
+                        let far = clamp(to_screen_y(cap_height, hit.exit_dst) as i32, 0, camera_view_height);
+                        let (cap_start, cap_end) = if near < far { (near, far) } else { (far, near) };
+
+                        let texel = tex_column.at(0.0);
+                        let texel = [texel[0] / 2, texel[1] / 2, texel[2] / 2, 255];
+                        for y in cap_start..cap_end {
+                            put(y, texel, fogging);
+                        }
                     }
                 }
             })
         }
 
+        let mut camera_frame = game.camera_frame_mut();
+        camera_frame.origin = player_pos;
+        camera_frame.ray_ends = ray_ends;
+
+        if let (RenderMode::Palette, Some(colormap)) = (self.render_mode, self.colormap.as_ref()) {
+            let display_palette = self.display_palette(colormap);
+            let indexed_view = &self.indexed_view;
+            self.camera_view.with_lock_mut(|buf| {
+                for (pixel, palette_index) in indexed_view.iter().enumerate() {
+                    buf[pixel * 3..pixel * 3 + 3].copy_from_slice(&display_palette.color(*palette_index));
+                }
+            });
+        }
+
         Some(&self.camera_view)
     }
+
+    fn handle_event(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::M),
+                ..
+            } => {
+                self.mipmapping = !self.mipmapping;
+                println!("\nMipmapping: {}", self.mipmapping);
+                self.invalidate();
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::B),
+                ..
+            } => {
+                self.tex_filter = match self.tex_filter {
+                    TextureFilter::Nearest => TextureFilter::Bilinear,
+                    TextureFilter::Bilinear => TextureFilter::Nearest,
+                };
+                println!("\nTexture filter: {:?}", self.tex_filter);
+                self.invalidate();
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::P),
+                ..
+            } => {
+                self.set_render_mode(match self.render_mode {
+                    RenderMode::TrueColor => RenderMode::Palette,
+                    RenderMode::Palette => RenderMode::TrueColor,
+                });
+                println!("\nRender mode: {:?}", self.render_mode);
+                None
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+fn next_grid_crossing(ray: &Ray, ray_pos: &Vec2f) -> Vec2f {
+    let ray_continuation_coeff = ray
+        .dst_to_grid_line(ray_pos)
+        .div_coeffs(&ray.dir)
+        .get_smaller_abs_coeff();
+
+    ray_pos + ray.dir * ray_continuation_coeff
+}
+
+/// Returns the point where the ray, having entered the tile at `ray_pos`,
+/// crosses the thin wall's plane, if it does so before leaving the tile
+fn intersect_thin_wall(ray: &Ray, ray_pos: &Vec2f, tile_pos: &Vec2f, thin_wall: &map::ThinWall) -> Option<Vec2f> {
+    let (plane, pos, dir) = match thin_wall.axis {
+        map::ThinWallAxis::X => (tile_pos.x() + thin_wall.offset, ray_pos.x(), ray.dir.x()),
+        map::ThinWallAxis::Y => (tile_pos.y() + thin_wall.offset, ray_pos.y(), ray.dir.y()),
+    };
+    if dir == 0.0 {
+        return None;
+    }
+
+    let coeff = (plane - pos) / dir;
+    if coeff < 0.0 {
+        return None;
+    }
+
+    let hit_pos = ray_pos + ray.dir * coeff;
+    let inside = |value: f32, tile_value: f32| tile_value <= value && value <= tile_value + 1.0;
+    if inside(hit_pos.x(), tile_pos.x()) && inside(hit_pos.y(), tile_pos.y()) {
+        Some(hit_pos)
+    } else {
+        None
+    }
+}
+
+/// Every texture the map uses is loaded, or replaced with a placeholder,
+/// when the camera is created and whenever the map changes, before drawing
+fn texture<'a>(tex_loader: &'a TextureLoader, tex_name: &str) -> &'a Texture {
+    tex_loader
+        .texture(tex_name)
+        .expect("Map texture wasn't loaded up front")
+}
+
+fn fogging(dst: f32, view_dst: f32) -> i32 {
+    let dst_for_fogging = if dst < view_dst / 2.0 {
+        0.0
+    } else {
+        dst - (view_dst / 2.0)
+    };
+    let fogging = (dst_for_fogging / view_dst * 2.0 * 255.0) as i32;
+
+    clamp(fogging, 0, 255)
+}
+
+/// Blends the fogged texel over whatever is already in the buffer
+fn put_pixel(buf: &mut [u8], index: usize, texel: Texel, fogging: i32) {
+    if texel[3] == 0 {
+        return;
+    }
+
+    let alpha = texel[3] as i32;
+    let blend = |src: u8, dst: u8| {
+        let src = clamp(src as i32 - fogging, 0, 255);
+        ((src * alpha + dst as i32 * (255 - alpha)) / 255) as u8
+    };
+
+    buf[index] = blend(texel[0], buf[index]);
+    buf[index + 1] = blend(texel[1], buf[index + 1]);
+    buf[index + 2] = blend(texel[2], buf[index + 2]);
+}
+
+/// Palette mode counterpart of `put_pixel`. There is no blending with a
+/// palette, texels are either drawn or not.
+fn put_indexed_pixel(indexed_view: &mut [u8], pixel: usize, texel: Texel, fogging: i32, colormap: &Colormap) {
+    if texel[3] < 128 {
+        return;
+    }
+
+    indexed_view[pixel] = colormap.fog(colormap.quantize(texel), fogging);
 }
 
 fn clamp<T>(value: T, min: T, max: T) -> T
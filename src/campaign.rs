@@ -0,0 +1,211 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::assets::AssetError;
+use crate::level_generator;
+use crate::level_generator::GeneratorParams;
+use crate::level_generator::Layout;
+use crate::textures::TextureLoader;
+use crate::tile_map::TileMap;
+use crate::wolf3d;
+
+/// Where a level's tile map comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum LevelSource {
+    Builtin,
+    Map(PathBuf),
+    /// 1 based level of the Wolfenstein 3D data in the directory
+    Wolf(PathBuf, usize),
+    Generated(GeneratorParams),
+}
+
+impl LevelSource {
+    /// Builds the level's tile map, adding any textures it brings along to the
+    /// loader
+    pub fn load(&self, tex_loader: &mut TextureLoader) -> Result<TileMap, AssetError> {
+        match self {
+            LevelSource::Builtin => Ok(TileMap::builtin()),
+            LevelSource::Map(map_path) => TileMap::load(map_path),
+            LevelSource::Wolf(wolf_dir, level) => wolf3d::import_level(wolf_dir, *level, tex_loader),
+            LevelSource::Generated(params) => {
+                println!("Generating {} level with seed {}", params.layout.name(), params.seed);
+                Ok(level_generator::generate(params))
+            }
+        }
+    }
+}
+
+/// Levels played one after another, read from a campaign file:
+///
+///     # Comments start with a hash
+///     name Operation Eisenfaust
+///     map maps/first.map
+///     generate rooms 48x48 1234
+///     wolf wolf3d 2
+///     builtin
+///
+/// Map and Wolfenstein 3D paths are relative to the campaign file.
+pub struct Campaign {
+    pub name: String,
+    pub levels: Vec<LevelSource>,
+}
+
+impl Campaign {
+    /// A campaign of just the one level
+    pub fn single(level: LevelSource) -> Campaign {
+        Campaign {
+            name: String::new(),
+            levels: vec![level],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Campaign, AssetError> {
+        let text = fs::read_to_string(path).map_err(|err| AssetError::Io(path.to_path_buf(), err))?;
+        Campaign::parse(path, &text)
+    }
+
+    /// Paths in the text are relative to `path`'s directory
+    pub fn parse(path: &Path, text: &str) -> Result<Campaign, AssetError> {
+        let campaign_dir = path.parent().unwrap_or(Path::new("."));
+
+        let mut name = String::new();
+        let mut levels = vec![];
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let config_error = |err: &str| {
+                AssetError::Config(path.to_path_buf(), format!("line {}: {}", line_index + 1, err))
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["name", ..] => name = String::from(line["name".len()..].trim()),
+                ["builtin"] => levels.push(LevelSource::Builtin),
+                ["map", map_path] => levels.push(LevelSource::Map(campaign_dir.join(map_path))),
+                ["wolf", wolf_dir, level] => {
+                    let level = level
+                        .parse::<usize>()
+                        .map_err(|_| config_error(&format!("\"{}\" is not a level number", level)))?;
+                    levels.push(LevelSource::Wolf(campaign_dir.join(wolf_dir), level));
+                }
+                ["generate", layout, size, seed] => {
+                    let layout = Layout::from_name(layout)
+                        .ok_or_else(|| config_error(&format!("\"{}\" is not rooms or caves", layout)))?;
+                    let (width, height) = size
+                        .split_once('x')
+                        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                        .ok_or_else(|| config_error(&format!("\"{}\" is not <width>x<height>", size)))?;
+                    let seed = seed
+                        .parse::<u64>()
+                        .map_err(|_| config_error(&format!("\"{}\" is not a seed", seed)))?;
+                    levels.push(LevelSource::Generated(GeneratorParams {
+                        layout,
+                        width,
+                        height,
+                        seed,
+                    }));
+                }
+                _ => {
+                    return Err(config_error(
+                        "expected \"name\", \"builtin\", \"map\", \"wolf\" or \"generate\" with their arguments",
+                    ))
+                }
+            }
+        }
+
+        if levels.is_empty() {
+            return Err(AssetError::Config(path.to_path_buf(), String::from("the campaign has no levels")));
+        }
+
+        Ok(Campaign { name, levels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Campaign, AssetError> {
+        Campaign::parse(Path::new("campaigns/main.txt"), text)
+    }
+
+    fn error(text: &str) -> String {
+        match parse(text) {
+            Err(AssetError::Config(path, err)) => {
+                assert_eq!(path, Path::new("campaigns/main.txt"));
+                err
+            }
+            Err(err) => panic!("Unexpected error {}", err),
+            Ok(_) => panic!("{:?} parsed", text),
+        }
+    }
+
+    #[test]
+    fn parses_every_kind_of_level() {
+        let campaign = parse(
+            "# Comments and blank lines are skipped\n\
+             \n\
+             name  Operation Eisenfaust \n\
+             map maps/first.map\n\
+             generate rooms 48x32 1234\n\
+             \twolf ../wolf3d 2\n\
+             builtin\n\
+             generate caves 20x20 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(campaign.name, "Operation Eisenfaust");
+        assert_eq!(
+            campaign.levels,
+            vec![
+                LevelSource::Map(PathBuf::from("campaigns/maps/first.map")),
+                LevelSource::Generated(GeneratorParams {
+                    layout: Layout::Rooms,
+                    width: 48,
+                    height: 32,
+                    seed: 1234,
+                }),
+                LevelSource::Wolf(PathBuf::from("campaigns/../wolf3d"), 2),
+                LevelSource::Builtin,
+                LevelSource::Generated(GeneratorParams {
+                    layout: Layout::Caves,
+                    width: 20,
+                    height: 20,
+                    seed: 0,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn paths_are_relative_to_the_campaign_file() {
+        let campaign = Campaign::parse(Path::new("main.txt"), "map first.map").unwrap();
+        assert_eq!(campaign.levels, vec![LevelSource::Map(PathBuf::from("first.map"))]);
+        assert_eq!(campaign.name, "");
+
+        let campaign = Campaign::parse(Path::new("/games/main.txt"), "map /maps/first.map").unwrap();
+        assert_eq!(campaign.levels, vec![LevelSource::Map(PathBuf::from("/maps/first.map"))]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(error("builtin\nwolf wolf3d two"), "line 2: \"two\" is not a level number");
+        assert_eq!(error("generate mazes 8x8 1"), "line 1: \"mazes\" is not rooms or caves");
+        assert_eq!(error("# Big\ngenerate rooms 8by8 1"), "line 2: \"8by8\" is not <width>x<height>");
+        assert_eq!(error("generate rooms 8x 1"), "line 1: \"8x\" is not <width>x<height>");
+        assert_eq!(error("generate caves 8x8 -1"), "line 1: \"-1\" is not a seed");
+        for unknown in ["level 1", "map", "map a b", "builtin 2", "wolf wolf3d", "generate rooms 8x8"].iter() {
+            assert!(error(unknown).starts_with("line 1: expected"), "{} parsed", unknown);
+        }
+        assert_eq!(error("# Nothing\nname Empty\n"), "the campaign has no levels");
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let path = Path::new("campaigns/missing.txt");
+        assert!(matches!(Campaign::load(path), Err(AssetError::Io(err_path, _)) if err_path == path));
+    }
+}
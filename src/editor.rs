@@ -0,0 +1,657 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use crate::font;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::game::GameOpts;
+use crate::map;
+use crate::map::MapView;
+use crate::map::PanZoom;
+use crate::render_precedence::RenderPrecedence;
+use crate::tile_map::Thing;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
+use crate::vector::Vec2f;
+
+/// Maps that weren't read from a file are saved here, in the working directory
+pub const EDITED_MAP_FILE_NAME: &'static str = "edited.map";
+
+/// Pixels per tile
+const MIN_ZOOM: f32 = 4.0;
+const MAX_ZOOM: f32 = 64.0;
+/// Grid lines are left out below this zoom
+const GRID_ZOOM: f32 = 6.0;
+/// Strokes that can be undone
+const MAX_UNDO: usize = 100;
+
+const PALETTE_HEIGHT: i32 = 32;
+const SWATCH_SIZE: i32 = 24;
+const SWATCH_GAP: i32 = 4;
+const MARGIN: i32 = 4;
+
+const GRID_COLOR: [u8; 3] = [90, 90, 90];
+const HOVER_COLOR: [u8; 3] = [255, 255, 255];
+const PALETTE_COLOR: [u8; 3] = [16, 16, 16];
+const SELECTED_COLOR: [u8; 3] = [255, 255, 255];
+const TEXT_COLOR: [u8; 3] = [200, 200, 200];
+
+const HELP_TEXT: &'static str =
+    "F2 PLAY  LEFT PAINT  RIGHT ERASE  MIDDLE/ARROWS PAN  [ ] BRUSH  CTRL+Z UNDO  CTRL+Y REDO  CTRL+S SAVE";
+
+/// See `map::plot_icon`
+const SPAWN_ICON: [u8; 5] = [0b00100, 0b01110, 0b11111, 0b00100, 0b00100];
+
+/// What painting puts on a tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Brush {
+    Tile(u16),
+    Spawn,
+    Thing(ThingKind),
+}
+
+impl Brush {
+    fn label(&self, tile_map: &TileMap) -> String {
+        match self {
+            Brush::Tile(0) => String::from("EMPTY"),
+            Brush::Tile(tile) => format!("TILE {} {}", tile, tile_map.texture_name(*tile)),
+            Brush::Spawn => String::from("SPAWN"),
+            Brush::Thing(kind) => kind.name().to_uppercase(),
+        }
+    }
+}
+
+/// Top down level editor, toggled with F2 or opened from the start with
+/// `--edit`. It edits the level as authored and the game's tile map is
+/// replaced with a copy after every edit, so closing it previews them in 3D.
+pub struct Editor {
+    open: bool,
+    tile_map: Rc<RefCell<TileMap>>,
+    /// The level as it was made, without what playing it changed, like
+    /// picked up keys or opened doors. This is what gets saved.
+    authored: TileMap,
+    editor_surface: Surface<'static>,
+    pan_zoom: PanZoom,
+    center: Vec2f,
+    player_pos: Vec2f,
+
+    brushes: Vec<Brush>,
+    brush: usize,
+    /// First brush shown in the palette, when they don't all fit
+    palette_scroll: usize,
+
+    /// Button held down while painting, the right one erases
+    stroke: Option<MouseButton>,
+    stroke_changed: bool,
+    /// Maps as they were before each stroke
+    undo: Vec<TileMap>,
+    redo: Vec<TileMap>,
+    unsaved: bool,
+    save_requested: bool,
+    /// Set when the editor opens, to read the authored level from its file
+    load_requested: bool,
+
+    mouse: (i32, i32),
+    panning: bool,
+}
+
+impl Editor {
+    pub fn new(game_opts: &GameOpts, tile_map: Rc<RefCell<TileMap>>) -> Editor {
+        let editor_surface = Surface::new(
+            game_opts.screen_width,
+            game_opts.screen_height,
+            PixelFormatEnum::RGB24,
+        )
+        .unwrap();
+
+        // Levels are only played once the editor is in place
+        let authored = tile_map.borrow().clone();
+        let center = {
+            let tile_map = tile_map.borrow();
+            tile_map
+                .spawn()
+                .unwrap_or(Vec2f::new(tile_map.width() as f32 / 2.0, tile_map.height() as f32 / 2.0))
+        };
+
+        let mut editor = Editor {
+            open: game_opts.edit,
+            tile_map,
+            authored,
+            editor_surface,
+            pan_zoom: PanZoom::new(MIN_ZOOM, MAX_ZOOM),
+            center,
+            player_pos: center,
+
+            brushes: vec![],
+            brush: 0,
+            palette_scroll: 0,
+
+            stroke: None,
+            stroke_changed: false,
+            undo: vec![],
+            redo: vec![],
+            unsaved: false,
+            save_requested: false,
+            load_requested: false,
+
+            mouse: (0, 0),
+            panning: false,
+        };
+        editor.refresh_brushes();
+
+        editor
+    }
+
+    /// Height of the grid above the palette
+    fn grid_height(&self) -> i32 {
+        self.editor_surface.height() as i32 - PALETTE_HEIGHT
+    }
+
+    fn view(&self) -> MapView {
+        MapView::new(
+            self.center,
+            Vec2f::new(0.0, -1.0),
+            false,
+            self.pan_zoom.zoom(),
+            self.editor_surface.width() as i32,
+            self.grid_height(),
+        )
+    }
+
+    /// Map column and row under the pixel, None outside the map
+    fn tile_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        if y >= self.grid_height() {
+            return None;
+        }
+
+        let pos = self.view().to_world(x as f32 + 0.5, y as f32 + 0.5);
+        let tile_map = &self.authored;
+        if pos.x() < 0.0 || pos.y() < 0.0 || pos.x() >= tile_map.width() as f32 || pos.y() >= tile_map.height() as f32 {
+            return None;
+        }
+
+        Some((pos.x() as usize, pos.y() as usize))
+    }
+
+    fn toggle(&mut self) {
+        self.end_stroke();
+        self.open = !self.open;
+        self.panning = false;
+        self.pan_zoom.release_keys();
+        if self.open {
+            self.center = self.player_pos;
+            self.load_requested = true;
+            self.refresh_brushes();
+        }
+    }
+
+    /// Reads the authored level from the map file again, in case it changed
+    /// on disk. Unsaved edits are kept instead, and levels that don't come
+    /// from a file keep the copy taken when they started.
+    fn load(&mut self, game: &Game) {
+        let path = match self.tile_map.borrow().path() {
+            Some(path) if !self.unsaved => path.to_path_buf(),
+            _ => return,
+        };
+
+        match TileMap::load(&path) {
+            Ok(authored) => {
+                // Undoing would bring back the map as it was before the change
+                if authored.to_text() != self.authored.to_text() {
+                    self.undo.clear();
+                    self.redo.clear();
+                }
+                self.authored = authored;
+                self.refresh_brushes();
+            }
+            Err(err) => game.show_error(&err.to_string()),
+        }
+    }
+
+    /// Has the game play the authored level, as edited
+    fn play_authored(&self) {
+        self.tile_map.borrow_mut().replace(self.authored.clone());
+    }
+
+    /// Offers the built in tile ids up to the exit and every id the map uses,
+    /// plus spawns and things
+    fn refresh_brushes(&mut self) {
+        let selected = self.brushes.get(self.brush).cloned();
+
+        let mut tiles: Vec<u16> = (0..=map::EXIT_TILE).collect();
+        tiles.extend(self.authored.tiles().iter());
+        tiles.sort();
+        tiles.dedup();
+
+        self.brushes = tiles.into_iter().map(Brush::Tile).collect();
+        self.brushes.push(Brush::Spawn);
+        for kind in [ThingKind::Door, ThingKind::Key, ThingKind::Item, ThingKind::Enemy].iter() {
+            self.brushes.push(Brush::Thing(*kind));
+        }
+
+        self.select_brush(
+            selected
+                .and_then(|selected| self.brushes.iter().position(|brush| *brush == selected))
+                .unwrap_or(1),
+        );
+    }
+
+    fn select_brush(&mut self, brush: usize) {
+        self.brush = brush.min(self.brushes.len() - 1);
+
+        // Keep the selected brush in view
+        let visible = ((self.editor_surface.width() as i32 - MARGIN) / (SWATCH_SIZE + SWATCH_GAP)).max(1) as usize;
+        if self.brush < self.palette_scroll {
+            self.palette_scroll = self.brush;
+        } else if self.brush >= self.palette_scroll + visible {
+            self.palette_scroll = self.brush + 1 - visible;
+        }
+    }
+
+    /// Brush of the palette swatch under the pixel
+    fn swatch_at(&self, x: i32, y: i32) -> Option<usize> {
+        if y < self.grid_height() || x < MARGIN {
+            return None;
+        }
+
+        let swatch = ((x - MARGIN) / (SWATCH_SIZE + SWATCH_GAP)) as usize + self.palette_scroll;
+        if swatch < self.brushes.len() {
+            Some(swatch)
+        } else {
+            None
+        }
+    }
+
+    fn begin_stroke(&mut self, button: MouseButton) {
+        self.end_stroke();
+
+        self.undo.push(self.authored.clone());
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.stroke = Some(button);
+        self.stroke_changed = false;
+        self.paint();
+    }
+
+    /// Strokes that didn't change anything aren't worth undoing
+    fn end_stroke(&mut self) {
+        if self.stroke.take().is_none() {
+            return;
+        }
+
+        if self.stroke_changed {
+            self.redo.clear();
+            self.unsaved = true;
+        } else {
+            self.undo.pop();
+        }
+    }
+
+    /// Paints or erases the tile under the mouse
+    fn paint(&mut self) {
+        let (column, row) = match (self.stroke, self.tile_at(self.mouse.0, self.mouse.1)) {
+            (Some(_), Some(tile)) => tile,
+            _ => return,
+        };
+        let tile_center = Vec2f::new(column as f32 + 0.5, row as f32 + 0.5);
+
+        let tile_map = &mut self.authored;
+        let revision = tile_map.revision();
+        let mut changed = false;
+        if self.stroke == Some(MouseButton::Right) {
+            changed |= tile_map.remove_things_at(column, row);
+            tile_map.set_tile(column, row, 0);
+        } else {
+            match self.brushes[self.brush] {
+                Brush::Tile(tile) => {
+                    tile_map.set_tile(column, row, tile);
+                    // Spawns are on empty tiles
+                    if tile != 0 && tile_map.spawn() == Some(tile_center) {
+                        tile_map.set_spawn(None);
+                        changed = true;
+                    }
+                }
+                Brush::Spawn => {
+                    tile_map.set_tile(column, row, 0);
+                    changed |= tile_map.spawn() != Some(tile_center);
+                    tile_map.set_spawn(Some(tile_center));
+                }
+                Brush::Thing(kind) => {
+                    let thing = Thing {
+                        kind,
+                        pos: tile_center,
+                    };
+                    if !tile_map.things().contains(&thing) {
+                        // One thing per tile
+                        tile_map.remove_things_at(column, row);
+                        tile_map.add_thing(thing);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let changed = changed || tile_map.revision() != revision;
+        self.stroke_changed |= changed;
+        if changed {
+            self.play_authored();
+        }
+    }
+
+    fn undo(&mut self) {
+        self.end_stroke();
+        if let Some(tile_map) = self.undo.pop() {
+            self.redo.push(self.authored.clone());
+            self.authored.replace(tile_map);
+            self.play_authored();
+            self.unsaved = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        self.end_stroke();
+        if let Some(tile_map) = self.redo.pop() {
+            self.undo.push(self.authored.clone());
+            self.authored.replace(tile_map);
+            self.play_authored();
+            self.unsaved = true;
+        }
+    }
+
+    /// Writes the authored level back to its file, or to
+    /// `EDITED_MAP_FILE_NAME` if it didn't come from one
+    fn save(&mut self, game: &Game) {
+        let path = self
+            .authored
+            .path()
+            .or(self.tile_map.borrow().path())
+            .map(|path| path.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from(EDITED_MAP_FILE_NAME));
+
+        match self.authored.save(&path) {
+            Ok(()) => {
+                self.authored.set_path(&path);
+                self.tile_map.borrow_mut().set_path(&path);
+                self.unsaved = false;
+                game.show_message(&format!("Saved {}", path.display()));
+            }
+            Err(err) => game.show_error(&err.to_string()),
+        }
+    }
+
+    fn handle_key(&mut self, keycode: Keycode, keymod: Mod) {
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        match keycode {
+            Keycode::F2 => self.toggle(),
+            Keycode::Z if ctrl && shift => self.redo(),
+            Keycode::Z if ctrl => self.undo(),
+            Keycode::Y if ctrl => self.redo(),
+            Keycode::S if ctrl => self.save_requested = true,
+            Keycode::LeftBracket => self.select_brush(self.brush.saturating_sub(1)),
+            Keycode::RightBracket => self.select_brush(self.brush + 1),
+            Keycode::Home => self.center = self.player_pos,
+            _ => self.pan_zoom.key_down(keycode),
+        }
+    }
+}
+
+impl GameComponent for Editor {
+    fn update(&mut self, game: &Game, dt: u32) {
+        self.player_pos = *game.player().pos();
+        if self.save_requested {
+            self.save_requested = false;
+            self.save(game);
+        }
+        if self.load_requested {
+            self.load_requested = false;
+            self.load(game);
+        }
+        if !self.open {
+            return;
+        }
+
+        game.pause_movement();
+        self.center = self.pan_zoom.pan(self.center, dt);
+    }
+
+    fn draw(&mut self, game: &Game, _: u32) -> Option<&Surface> {
+        if !self.open {
+            return None;
+        }
+
+        let tile_map = &self.authored;
+        let camera_frame = game.camera_frame();
+        let player_dir = game.player().dir().normalize();
+
+        let view = self.view();
+        let width = self.editor_surface.width() as i32;
+        let height = self.editor_surface.height() as i32;
+        let grid_height = self.grid_height();
+        let pitch = self.editor_surface.pitch() as usize;
+        let zoom = self.pan_zoom.zoom();
+        let icon_scale = ((zoom / 8.0) as i32).max(1);
+        let hovered = self.tile_at(self.mouse.0, self.mouse.1);
+        let player_pos = self.player_pos;
+        let brushes = &self.brushes;
+        let (brush, palette_scroll) = (self.brush, self.palette_scroll);
+
+        self.editor_surface.with_lock_mut(|buffer: &mut [u8]| {
+            let mut put = |x: i32, y: i32, color: [u8; 3]| {
+                if 0 <= x && x < width && 0 <= y && y < height {
+                    let index = y as usize * pitch + x as usize * 3;
+                    buffer[index..index + 3].copy_from_slice(&color);
+                }
+            };
+
+            for y in 0..grid_height {
+                for x in 0..width {
+                    let world_pos = view.to_world(x as f32 + 0.5, y as f32 + 0.5);
+                    let mut color = map::tile_color(&tile_map, &camera_frame, None, world_pos, zoom);
+
+                    let inside = world_pos.x() >= 0.0
+                        && world_pos.y() >= 0.0
+                        && world_pos.x() < tile_map.width() as f32
+                        && world_pos.y() < tile_map.height() as f32;
+                    let on_grid_line = zoom >= GRID_ZOOM
+                        && (world_pos.x().fract() * zoom < 1.0 || world_pos.y().fract() * zoom < 1.0);
+                    if inside && on_grid_line {
+                        color = map::mix(color, GRID_COLOR, 0.5);
+                    }
+                    if inside && hovered == Some((world_pos.x() as usize, world_pos.y() as usize)) {
+                        color = map::mix(color, HOVER_COLOR, 0.3);
+                    }
+                    put(x, y, color);
+                }
+            }
+
+            for thing in tile_map.things() {
+                let (icon, color) = map::thing_icon(thing.kind);
+                map::plot_icon(view.to_screen(thing.pos), &icon, icon_scale, |x, y| {
+                    if y < grid_height {
+                        put(x, y, color)
+                    }
+                });
+            }
+            if let Some(spawn) = tile_map.spawn() {
+                map::plot_icon(view.to_screen(spawn), &SPAWN_ICON, icon_scale, |x, y| {
+                    if y < grid_height {
+                        put(x, y, map::PLAYER_COLOR)
+                    }
+                });
+            }
+            map::plot_player(&view, player_pos, player_dir, |x, y| {
+                if y < grid_height {
+                    put(x, y, map::PLAYER_COLOR)
+                }
+            });
+
+            for y in grid_height..height {
+                for x in 0..width {
+                    put(x, y, PALETTE_COLOR);
+                }
+            }
+            let top = grid_height + (PALETTE_HEIGHT - SWATCH_SIZE) / 2;
+            for (index, swatch) in brushes.iter().enumerate().skip(palette_scroll) {
+                let left = MARGIN + (index - palette_scroll) as i32 * (SWATCH_SIZE + SWATCH_GAP);
+                if left >= width {
+                    break;
+                }
+
+                let fill = match swatch {
+                    Brush::Tile(tile) => map::tile_swatch_color(&camera_frame, *tile),
+                    _ => PALETTE_COLOR,
+                };
+                for y in -1..=SWATCH_SIZE {
+                    for x in -1..=SWATCH_SIZE {
+                        let on_border = x < 0 || y < 0 || x == SWATCH_SIZE || y == SWATCH_SIZE;
+                        if !on_border {
+                            put(left + x, top + y, fill);
+                        } else if index == brush {
+                            put(left + x, top + y, SELECTED_COLOR);
+                        }
+                    }
+                }
+
+                let swatch_center = (
+                    (left + SWATCH_SIZE / 2) as f32,
+                    (top + SWATCH_SIZE / 2) as f32,
+                );
+                match swatch {
+                    Brush::Spawn => map::plot_icon(swatch_center, &SPAWN_ICON, 3, |x, y| {
+                        put(x, y, map::PLAYER_COLOR)
+                    }),
+                    Brush::Thing(kind) => {
+                        let (icon, color) = map::thing_icon(*kind);
+                        map::plot_icon(swatch_center, &icon, 3, |x, y| put(x, y, color));
+                    }
+                    Brush::Tile(_) => (),
+                }
+            }
+        });
+
+        let text_color = Color::RGB(TEXT_COLOR[0], TEXT_COLOR[1], TEXT_COLOR[2]);
+        let top = grid_height + (PALETTE_HEIGHT - SWATCH_SIZE) / 2;
+        for (index, swatch) in self.brushes.iter().enumerate().skip(self.palette_scroll) {
+            let left = MARGIN + (index - self.palette_scroll) as i32 * (SWATCH_SIZE + SWATCH_GAP);
+            if left >= width {
+                break;
+            }
+            if let Brush::Tile(tile) = swatch {
+                let label = tile.to_string();
+                font::draw_text(&mut self.editor_surface, left + 2, top + 2, &label, 1, Color::RGB(0, 0, 0));
+                font::draw_text(&mut self.editor_surface, left + 1, top + 1, &label, 1, Color::RGB(255, 255, 255));
+            }
+        }
+
+        let mut status = format!(
+            "{}{}  {}",
+            tile_map.name(),
+            if self.unsaved { " *" } else { "" },
+            self.brushes[self.brush].label(&tile_map)
+        );
+        if let Some((column, row)) = hovered {
+            status.push_str(&format!("  {},{}", column, row));
+        }
+        let line_height = font::text_height(1) as i32 + 2;
+        font::draw_text(
+            &mut self.editor_surface,
+            MARGIN,
+            grid_height - MARGIN - line_height,
+            &status,
+            1,
+            text_color,
+        );
+        font::draw_text(
+            &mut self.editor_surface,
+            MARGIN,
+            grid_height - MARGIN - 2 * line_height,
+            HELP_TEXT,
+            1,
+            text_color,
+        );
+
+        Some(&self.editor_surface)
+    }
+
+    fn handle_event(&mut self, event: Event) -> Option<Event> {
+        if !self.open {
+            return match event {
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    self.toggle();
+                    None
+                }
+                _ => Some(event),
+            };
+        }
+
+        match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => {
+                self.handle_key(keycode, keymod);
+                // Movement keys included, the player stays put
+                None
+            }
+            Event::KeyUp {
+                keycode: Some(keycode),
+                ..
+            } if PanZoom::is_pan_key(keycode) => {
+                self.pan_zoom.key_up(keycode);
+                None
+            }
+            Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                self.mouse = (x, y);
+                if let Some(swatch) = self.swatch_at(x, y) {
+                    self.select_brush(swatch);
+                } else if mouse_btn == MouseButton::Middle {
+                    self.panning = true;
+                } else if mouse_btn == MouseButton::Left || mouse_btn == MouseButton::Right {
+                    self.begin_stroke(mouse_btn);
+                }
+                None
+            }
+            Event::MouseButtonUp { mouse_btn, .. } => {
+                if mouse_btn == MouseButton::Middle {
+                    self.panning = false;
+                } else if self.stroke == Some(mouse_btn) {
+                    self.end_stroke();
+                }
+                None
+            }
+            Event::MouseMotion { x, y, xrel, yrel, .. } => {
+                self.mouse = (x, y);
+                if self.panning {
+                    self.center = self.pan_zoom.drag(self.center, xrel, yrel);
+                }
+                self.paint();
+                None
+            }
+            Event::MouseWheel { y, .. } => {
+                self.pan_zoom.wheel(y);
+                None
+            }
+            // Key releases still reach the player so no key stays held down
+            _ => Some(event),
+        }
+    }
+
+    fn render_precendce(&self) -> RenderPrecedence {
+        RenderPrecedence::Editor
+    }
+}
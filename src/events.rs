@@ -0,0 +1,77 @@
+/// Something that happened or should happen in the game. Events are posted
+/// with `Game::post_event` and handed to every component's
+/// `GameComponent::handle_game_event` at the end of the frame.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// The player pressed the use key
+    Use,
+    /// Removes the doors on the tile, along with its wall if it has one
+    OpenDoor { column: usize, row: usize },
+    SpawnEnemy { column: usize, row: usize },
+    /// Removes the enemies on the tile, each one counting as a kill
+    KillEnemy { column: usize, row: usize },
+    /// Light level of the camera view, 0.0 is pitch black and 1.0 fully lit
+    SetLight(f32),
+    Message(String),
+    SetHealth(u32),
+    /// Calls the named function of the level script
+    RunScript(String),
+    EndLevel,
+}
+
+impl GameEvent {
+    /// Reads a trigger action, the words of map file lines like
+    /// `open-door 4 2`, `spawn-enemy 4 2`, `kill-enemy 4 2`, `light 0.5`,
+    /// `message Hello there`, `health 50`, `script open_vault` or `end-level`
+    pub fn parse_action(words: &[&str]) -> Result<GameEvent, String> {
+        let coordinate = |word: &str| {
+            word.parse::<usize>()
+                .map_err(|_| format!("\"{}\" is not a column or row", word))
+        };
+
+        match words {
+            ["open-door", column, row] => Ok(GameEvent::OpenDoor {
+                column: coordinate(column)?,
+                row: coordinate(row)?,
+            }),
+            ["spawn-enemy", column, row] => Ok(GameEvent::SpawnEnemy {
+                column: coordinate(column)?,
+                row: coordinate(row)?,
+            }),
+            ["kill-enemy", column, row] => Ok(GameEvent::KillEnemy {
+                column: coordinate(column)?,
+                row: coordinate(row)?,
+            }),
+            ["light", level] => level
+                .parse::<f32>()
+                .map(|level| GameEvent::SetLight(level.max(0.0).min(1.0)))
+                .map_err(|_| format!("\"{}\" is not a light level", level)),
+            ["message", ..] if words.len() > 1 => Ok(GameEvent::Message(words[1..].join(" "))),
+            ["health", health] => health
+                .parse::<u32>()
+                .map(GameEvent::SetHealth)
+                .map_err(|_| format!("\"{}\" is not an amount of health", health)),
+            ["script", function] => Ok(GameEvent::RunScript(String::from(*function))),
+            ["end-level"] => Ok(GameEvent::EndLevel),
+            _ => Err(String::from(
+                "expected \"open-door\", \"spawn-enemy\", \"kill-enemy\", \"light\", \"message\", \"health\", \"script\" or \"end-level\" with their arguments",
+            )),
+        }
+    }
+
+    /// The action in the format `parse_action` reads, None for events that
+    /// can't be trigger actions
+    pub fn to_action_text(&self) -> Option<String> {
+        match self {
+            GameEvent::Use => None,
+            GameEvent::OpenDoor { column, row } => Some(format!("open-door {} {}", column, row)),
+            GameEvent::SpawnEnemy { column, row } => Some(format!("spawn-enemy {} {}", column, row)),
+            GameEvent::KillEnemy { column, row } => Some(format!("kill-enemy {} {}", column, row)),
+            GameEvent::SetLight(level) => Some(format!("light {}", level)),
+            GameEvent::Message(text) => Some(format!("message {}", text)),
+            GameEvent::SetHealth(health) => Some(format!("health {}", health)),
+            GameEvent::RunScript(function) => Some(format!("script {}", function)),
+            GameEvent::EndLevel => Some(String::from("end-level")),
+        }
+    }
+}
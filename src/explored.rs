@@ -0,0 +1,77 @@
+use crate::tile_map::TileMap;
+
+/// Tiles the player has seen, which are the only ones the maps show
+#[derive(Clone)]
+pub struct ExploredTiles {
+    width: usize,
+    height: usize,
+    explored: Vec<bool>,
+}
+
+impl ExploredTiles {
+    pub fn new(width: usize, height: usize) -> ExploredTiles {
+        ExploredTiles {
+            width,
+            height,
+            explored: vec![false; width * height],
+        }
+    }
+
+    /// Starts over if the map's size changed, e.g. when it was reloaded
+    pub fn fit(&mut self, tile_map: &TileMap) {
+        if self.width != tile_map.width() || self.height != tile_map.height() {
+            *self = ExploredTiles::new(tile_map.width(), tile_map.height());
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// `index` is row by row, like the tile map's
+    pub fn mark(&mut self, index: usize) {
+        if let Some(explored) = self.explored.get_mut(index) {
+            *explored = true;
+        }
+    }
+
+    pub fn is_explored(&self, index: usize) -> bool {
+        self.explored.get(index).cloned().unwrap_or(false)
+    }
+
+    /// One line per row, `#` for explored tiles and `.` for the rest
+    pub fn to_rows(&self) -> Vec<String> {
+        self.explored
+            .chunks(self.width.max(1))
+            .map(|row| row.iter().map(|explored| if *explored { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    /// Reverses `to_rows`, None if the rows don't make a rectangle
+    pub fn from_rows(rows: &[&str]) -> Option<ExploredTiles> {
+        let width = rows.first()?.chars().count();
+        let mut explored = Vec::with_capacity(width * rows.len());
+        for row in rows {
+            if row.chars().count() != width {
+                return None;
+            }
+            for tile in row.chars() {
+                match tile {
+                    '#' => explored.push(true),
+                    '.' => explored.push(false),
+                    _ => return None,
+                }
+            }
+        }
+
+        Some(ExploredTiles {
+            width,
+            height: rows.len(),
+            explored,
+        })
+    }
+}
@@ -1,7 +1,11 @@
+use std::cell::Cell;
 use std::cell::Ref;
+use std::cell::RefMut;
 use std::cell::RefCell;
 use std::collections::LinkedList;
 use std::error::Error;
+use std::path::Path;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::{thread, time};
 
@@ -14,16 +18,101 @@ use sdl2::surface::Surface;
 use sdl2::video::Window;
 use sdl2::EventPump;
 
+use crate::assets;
+use crate::assets::AssetError;
+use crate::automap::Automap;
+use crate::campaign::Campaign;
+use crate::campaign::LevelSource;
 use crate::camera::Camera;
+use crate::camera::CameraFrame;
+use crate::camera::RenderMode;
+use crate::editor::Editor;
+use crate::events::GameEvent;
+use crate::explored::ExploredTiles;
+use crate::hot_reload::FileWatcher;
+use crate::intermission::Intermission;
+use crate::intermission::LevelStats;
+use crate::level_generator::GeneratorParams;
 use crate::map::Map;
+use crate::messages::Messages;
+use crate::palette::PaletteFlash;
+use crate::player;
 use crate::player::Player;
+use crate::procedural_textures;
+use crate::push_walls::PushWalls;
 use crate::render_precedence;
+use crate::save_game;
+use crate::scripting::Scripts;
+use crate::save_game::SaveGame;
+use crate::textures::TextureLoader;
+use crate::tile_map::Thing;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
+use crate::triggers::Triggers;
 use crate::vector::Vec2f;
+use crate::weapon::Weapon;
+
+/// Where the player starts on maps without a spawn, if it's on the floor
+const DEFAULT_SPAWN: Vec2f = Vec2f::new(6.6, 5.0);
+/// How close the player has to get to an exit wall to use it, in tiles
+const EXIT_REACH: f32 = 0.05;
 
 pub struct GameOpts {
     pub title: &'static str,
     pub screen_width: u32,
     pub screen_height: u32,
+    pub head_bob: HeadBobOpts,
+    pub minimap: MinimapOpts,
+    /// Overrides where assets are loaded from, see `assets::asset_root`
+    pub asset_root: Option<PathBuf>,
+    /// Map file to play instead of the built in map
+    pub map_path: Option<PathBuf>,
+    /// Directory with Wolfenstein 3D data files to import `level` from,
+    /// takes precedence over `map_path`
+    pub wolf_dir: Option<PathBuf>,
+    /// 1 based
+    pub level: usize,
+    /// Plays a generated level, takes precedence over `map_path`
+    pub generate: Option<GeneratorParams>,
+    /// Campaign file listing the levels to play, takes precedence over all
+    /// of the above
+    pub campaign_path: Option<PathBuf>,
+    /// Development mode, reloads textures and the map file when they change
+    pub hot_reload: bool,
+    /// Start in the level editor
+    pub edit: bool,
+    pub render_mode: RenderMode,
+}
+
+/// Walking motion applied to the view. Can be turned off for players prone to
+/// motion sickness.
+#[derive(Clone, Copy, Debug)]
+pub struct HeadBobOpts {
+    pub enabled: bool,
+    /// Peak vertical eye offset, in wall heights
+    pub amplitude: f32,
+    /// Bob cycles per unit of distance walked
+    pub frequency: f32,
+    /// Peak view weapon offset, in wall heights
+    pub weapon_sway: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MinimapOpts {
+    /// Pixels per tile
+    pub zoom: f32,
+    /// Turn the map so the player always faces up
+    pub rotating: bool,
+}
+
+/// Everything that's torn down and built again for every level
+struct Level {
+    player: Rc<RefCell<Player>>,
+    tile_map: Rc<RefCell<TileMap>>,
+    map_watcher: Option<FileWatcher>,
+    explored: ExploredTiles,
+    stats: LevelStats,
+    components: Vec<Rc<RefCell<dyn GameComponent>>>,
 }
 
 struct GameSdlCtx {
@@ -33,8 +122,32 @@ struct GameSdlCtx {
 
 pub struct Game {
     player: Rc<RefCell<Player>>,
+    tile_map: Rc<RefCell<TileMap>>,
+    messages: Rc<RefCell<Messages>>,
+    map_watcher: Option<FileWatcher>,
+    /// Flash requested this frame, picked up by the camera
+    palette_flash: Cell<Option<PaletteFlash>>,
+    camera_frame: RefCell<CameraFrame>,
+    explored: RefCell<ExploredTiles>,
+    /// Whether a component that takes over the controls, like the automap,
+    /// asked for movement to pause last frame
+    movement_paused: Cell<bool>,
+    /// Asked for this frame, becomes `movement_paused` once every component
+    /// has updated
+    pause_requested: Cell<bool>,
+    /// Posted this frame, handed to the components at the end of it
+    events: RefCell<Vec<GameEvent>>,
     components: Vec<Rc<RefCell<dyn GameComponent>>>,
 
+    asset_root: PathBuf,
+    campaign: Campaign,
+    /// Index of the level being played in the campaign
+    level: usize,
+    level_stats: RefCell<LevelStats>,
+    /// Set once the player walked into an exit, until the next level starts
+    level_finished: bool,
+    next_level_requested: Cell<bool>,
+
     game_opts: GameOpts,
     game_sdl_ctx: GameSdlCtx,
 }
@@ -51,6 +164,10 @@ pub trait GameComponent {
         Some(event)
     }
 
+    /// Called for every event posted on the game's event bus, see
+    /// `Game::post_event`
+    fn handle_game_event(&mut self, _game: &Game, _event: &GameEvent) {}
+
     /// Handles some subset of events and returns a new event vector
     /// with the handled events removed from it
     fn handle_events(&mut self, events: Vec<Event>) -> Vec<Event> {
@@ -93,28 +210,48 @@ impl Game {
 
         let canvas = window.into_canvas().build()?;
 
-        let player = Rc::new(RefCell::new(Player::new(Vec2f::new(6.6, 5.0))));
+        let asset_root = match assets::asset_root(game_opts.asset_root.as_deref()) {
+            Ok(asset_root) => asset_root,
+            // A fresh clone has no assets at all, the game still runs on placeholder textures
+            Err(err @ AssetError::RootNotFound(_)) if game_opts.asset_root.is_none() => {
+                println!("Warning: {}", err);
+                PathBuf::from(".")
+            }
+            Err(err) => return Err(err.into()),
+        };
+        println!("Loading assets from {}", asset_root.display());
+
+        let campaign = match &game_opts.campaign_path {
+            Some(campaign_path) => Campaign::load(campaign_path)?,
+            None => Campaign::single(level_source(&game_opts)),
+        };
+        let messages = Rc::new(RefCell::new(Messages::new(&game_opts)));
+        let level = build_level(&game_opts, &asset_root, &campaign.levels[0], &messages)?;
 
         let mut game = Game {
-            player: Rc::clone(&player),
-            components: vec![
-                Rc::new(RefCell::new(Map::new(
-                    game_opts.screen_width,
-                    game_opts.screen_height,
-                ))),
-                Rc::new(RefCell::new(Camera::new(33.0, 18.0, &game_opts))),
-                Rc::clone(&(player as Rc<RefCell<dyn GameComponent>>)),
-            ],
+            player: level.player,
+            tile_map: level.tile_map,
+            messages,
+            map_watcher: level.map_watcher,
+            palette_flash: Cell::new(None),
+            camera_frame: RefCell::new(CameraFrame::new()),
+            explored: RefCell::new(level.explored),
+            movement_paused: Cell::new(false),
+            pause_requested: Cell::new(false),
+            events: RefCell::new(vec![]),
+            components: level.components,
+
+            asset_root,
+            campaign,
+            level: 0,
+            level_stats: RefCell::new(level.stats),
+            level_finished: false,
+            next_level_requested: Cell::new(false),
 
             game_opts,
             game_sdl_ctx: GameSdlCtx { event_pump, canvas },
         };
-
-        game.components.sort_by(|a, b| {
-            a.borrow()
-                .numeric_render_precedence()
-                .cmp(&b.borrow().numeric_render_precedence())
-        });
+        game.sort_components();
 
         Ok(game)
     }
@@ -159,16 +296,304 @@ impl Game {
         self.player.borrow()
     }
 
+    pub fn tile_map(&self) -> Ref<TileMap> {
+        self.tile_map.borrow()
+    }
+
+    /// Shows a message on screen for a few seconds
+    pub fn show_message(&self, text: &str) {
+        self.messages.borrow_mut().show(text);
+    }
+
+    /// Reports a recoverable error on screen
+    pub fn show_error(&self, text: &str) {
+        self.messages.borrow_mut().show_error(text);
+    }
+
+    /// Tints the screen for a moment, e.g. when the player gets hurt
+    pub fn flash_palette(&self, flash: PaletteFlash) {
+        self.palette_flash.set(Some(flash));
+    }
+
+    pub fn take_palette_flash(&self) -> Option<PaletteFlash> {
+        self.palette_flash.take()
+    }
+
+    /// What the camera drew last
+    pub fn camera_frame(&self) -> Ref<CameraFrame> {
+        self.camera_frame.borrow()
+    }
+
+    pub fn camera_frame_mut(&self) -> RefMut<CameraFrame> {
+        self.camera_frame.borrow_mut()
+    }
+
+    /// Tiles the player has seen so far
+    pub fn explored(&self) -> Ref<ExploredTiles> {
+        self.explored.borrow()
+    }
+
+    pub fn explored_mut(&self) -> RefMut<ExploredTiles> {
+        self.explored.borrow_mut()
+    }
+
+    /// Keeps the player from moving, e.g. while a fullscreen map is open.
+    /// Has to be asked for every frame and takes effect the frame after, so
+    /// the order components update in doesn't matter.
+    pub fn pause_movement(&self) {
+        self.pause_requested.set(true);
+    }
+
+    /// Whether movement was paused last frame
+    pub fn movement_paused(&self) -> bool {
+        self.movement_paused.get()
+    }
+
+    /// Moves on from the intermission to the next level
+    pub fn request_next_level(&self) {
+        self.next_level_requested.set(true);
+    }
+
+    pub fn level_stats_mut(&self) -> RefMut<LevelStats> {
+        self.level_stats.borrow_mut()
+    }
+
+    /// Hands the event to every component once this frame's updates are
+    /// done. Events posted while handling one are handed out next frame.
+    pub fn post_event(&self, event: GameEvent) {
+        self.events.borrow_mut().push(event);
+    }
+
+    fn sort_components(&mut self) {
+        self.components.sort_by(|a, b| {
+            a.borrow()
+                .numeric_render_precedence()
+                .cmp(&b.borrow().numeric_render_precedence())
+        });
+    }
+
+    /// Map column and row the player is on, None when they're off the map
+    pub fn player_tile(&self) -> Option<(usize, usize)> {
+        self.tile_map().tile_coords(*self.player().pos())
+    }
+
+    fn pick_up_things(&self) {
+        let (column, row) = match self.player_tile() {
+            Some(player_tile) => player_tile,
+            None => return,
+        };
+
+        let taken = self.tile_map.borrow_mut().take_things_at(column, row, |thing| {
+            thing.kind == ThingKind::Key || thing.kind == ThingKind::Item
+        });
+        for thing in taken {
+            self.player.borrow_mut().pick_up(thing.kind);
+            if thing.kind == ThingKind::Item {
+                self.level_stats.borrow_mut().treasure += 1;
+            }
+            self.show_message(&format!("Picked up a {}", thing.kind.name()));
+            self.flash_palette(PaletteFlash::Pickup);
+        }
+    }
+
+    /// Whether the player walked up against an exit wall
+    fn on_exit(&self) -> bool {
+        let tile_map = self.tile_map();
+        let touching = tile_map.tiles_touching(*self.player().pos(), player::PLAYER_RADIUS + EXIT_REACH);
+        touching
+            .into_iter()
+            .filter_map(|(column, row)| tile_map.tile(column, row))
+            .any(|tile| tile_map.is_exit(tile))
+    }
+
+    /// Shows how the player did until they move on
+    fn finish_level(&mut self) {
+        self.level_finished = true;
+        let intermission = Intermission::new(
+            &self.game_opts,
+            &self.campaign.name,
+            self.level,
+            &self.level_stats.borrow(),
+            self.player().health(),
+            self.level + 1 == self.campaign.levels.len(),
+        );
+        self.components.push(Rc::new(RefCell::new(intermission)));
+        self.sort_components();
+    }
+
+    /// Tears down the level's components and builds the next level's, or the
+    /// first one's once the campaign is done
+    fn next_level(&mut self) {
+        let next = (self.level + 1) % self.campaign.levels.len();
+        let level = match build_level(&self.game_opts, &self.asset_root, &self.campaign.levels[next], &self.messages) {
+            Ok(level) => level,
+            Err(err) => return self.show_error(&err.to_string()),
+        };
+        // Starting the campaign over starts from scratch
+        if next != 0 {
+            level.player.borrow_mut().carry_over(&self.player());
+        }
+
+        self.level = next;
+        self.player = level.player;
+        self.tile_map = level.tile_map;
+        self.map_watcher = level.map_watcher;
+        *self.explored.borrow_mut() = level.explored;
+        *self.level_stats.borrow_mut() = level.stats;
+        *self.camera_frame.borrow_mut() = CameraFrame::new();
+        self.events.borrow_mut().clear();
+        self.components = level.components;
+        self.level_finished = false;
+        self.sort_components();
+    }
+
+    fn dispatch_events(&mut self) {
+        let events = std::mem::take(&mut *self.events.borrow_mut());
+        for event in events.iter() {
+            for component in self.components.iter() {
+                component.borrow_mut().handle_game_event(self, event);
+            }
+            self.apply_event(event);
+        }
+    }
+
+    /// The game's own part in handling events, the ones that change the map
+    /// or the level
+    fn apply_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::OpenDoor { column, row } => {
+                let mut tile_map = self.tile_map.borrow_mut();
+                tile_map.take_things_at(*column, *row, |thing| thing.kind == ThingKind::Door);
+                tile_map.set_tile(*column, *row, 0);
+            }
+            GameEvent::SpawnEnemy { column, row } => {
+                let mut tile_map = self.tile_map.borrow_mut();
+                if *column < tile_map.width() && *row < tile_map.height() {
+                    tile_map.add_thing(Thing {
+                        kind: ThingKind::Enemy,
+                        pos: Vec2f::new(*column as f32 + 0.5, *row as f32 + 0.5),
+                    });
+                    self.level_stats.borrow_mut().enemies += 1;
+                }
+            }
+            GameEvent::KillEnemy { column, row } => {
+                let killed = self
+                    .tile_map
+                    .borrow_mut()
+                    .take_things_at(*column, *row, |thing| thing.kind == ThingKind::Enemy);
+                self.level_stats.borrow_mut().kills += killed.len() as u32;
+            }
+            GameEvent::EndLevel => {
+                if !self.level_finished {
+                    self.finish_level();
+                }
+            }
+            GameEvent::Use
+            | GameEvent::SetLight(_)
+            | GameEvent::Message(_)
+            | GameEvent::SetHealth(_)
+            | GameEvent::RunScript(_) => (),
+        }
+    }
+
+    fn quicksave(&self) {
+        let save_path = PathBuf::from(save_game::QUICKSAVE_FILE_NAME);
+        let save = SaveGame {
+            map_name: String::from(self.tile_map().name()),
+            player_pos: *self.player().pos(),
+            player_dir: *self.player().dir(),
+            explored: self.explored().clone(),
+        };
+
+        match save.write(&save_path) {
+            Ok(()) => self.show_message("Game saved"),
+            Err(err) => self.show_error(&format!("{}: {}", save_path.display(), err)),
+        }
+    }
+
+    fn quickload(&self) {
+        let save_path = PathBuf::from(save_game::QUICKSAVE_FILE_NAME);
+        let save = match SaveGame::read(&save_path) {
+            Ok(save) => save,
+            Err(err) => return self.show_error(&err.to_string()),
+        };
+        if save.map_name != self.tile_map().name() {
+            return self.show_error(&format!("The save is for map {}", save.map_name));
+        }
+        let map_size = (self.tile_map().width(), self.tile_map().height());
+        if (save.explored.width(), save.explored.height()) != map_size {
+            return self.show_error(&format!(
+                "The save is for a {}x{} map, not {}x{}",
+                save.explored.width(),
+                save.explored.height(),
+                map_size.0,
+                map_size.1
+            ));
+        }
+
+        self.player.borrow_mut().place(save.player_pos, save.player_dir);
+        *self.explored.borrow_mut() = save.explored;
+        self.show_message("Game loaded");
+    }
+
     fn handle_events(&mut self, mut events: Vec<Event>, _event_dt: u32) {
+        events.retain(|event| match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::F5),
+                ..
+            } => {
+                self.quicksave();
+                false
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::F9),
+                ..
+            } => {
+                self.quickload();
+                false
+            }
+            _ => true,
+        });
+
         for component in self.components.iter_mut() {
             events = component.borrow_mut().handle_events(events);
         }
     }
 
-    fn update(&self, dt: u32) {
+    fn update(&mut self, dt: u32) {
+        self.reload_map(dt);
+
         for component in self.components.iter() {
             component.borrow_mut().update(&self, dt);
         }
+        self.movement_paused.set(self.pause_requested.replace(false));
+        self.dispatch_events();
+
+        if self.next_level_requested.take() {
+            self.next_level();
+        } else if !self.level_finished {
+            self.level_stats.borrow_mut().time_us += dt as u64;
+            self.pick_up_things();
+            if self.on_exit() {
+                self.finish_level();
+            }
+        }
+    }
+
+    /// Reloads the map file if it changed, the player stays where they are
+    fn reload_map(&mut self, dt: u32) {
+        let changed = match self.map_watcher.as_mut() {
+            Some(map_watcher) => map_watcher.poll(dt),
+            None => return,
+        };
+
+        for map_path in changed {
+            let reloaded = self.tile_map.borrow_mut().reload();
+            match reloaded {
+                Ok(()) => self.show_message(&format!("Reloaded {}", map_path.display())),
+                Err(err) => self.show_error(&err.to_string()),
+            }
+        }
     }
 
     fn draw(&mut self, dt: u32) {
@@ -217,3 +642,68 @@ impl Game {
         false
     }
 }
+
+/// The level the options ask for when there's no campaign
+fn level_source(game_opts: &GameOpts) -> LevelSource {
+    match (&game_opts.wolf_dir, &game_opts.generate, &game_opts.map_path) {
+        (Some(wolf_dir), _, _) => LevelSource::Wolf(wolf_dir.clone(), game_opts.level),
+        (None, Some(params), _) => LevelSource::Generated(*params),
+        (None, None, Some(map_path)) => LevelSource::Map(map_path.clone()),
+        (None, None, None) => LevelSource::Builtin,
+    }
+}
+
+fn build_level(
+    game_opts: &GameOpts,
+    asset_root: &Path,
+    source: &LevelSource,
+    messages: &Rc<RefCell<Messages>>,
+) -> Result<Level, Box<dyn Error>> {
+    let mut tex_loader = TextureLoader::new_eager(asset_root)?;
+    procedural_textures::register(&mut tex_loader);
+    let tile_map = source.load(&mut tex_loader)?;
+    let map_watcher = match tile_map.path() {
+        Some(map_path) if game_opts.hot_reload => {
+            let mut map_watcher = FileWatcher::new();
+            map_watcher.watch(map_path);
+            Some(map_watcher)
+        }
+        _ => None,
+    };
+
+    let spawn = match tile_map.spawn() {
+        Some(spawn) => spawn,
+        None if tile_map.tile_at(DEFAULT_SPAWN) == Some(0) => DEFAULT_SPAWN,
+        None => tile_map
+            .nearest_walkable(DEFAULT_SPAWN)
+            .map_or(DEFAULT_SPAWN, |(column, row)| Vec2f::new(column as f32 + 0.5, row as f32 + 0.5)),
+    };
+    let player = Rc::new(RefCell::new(Player::new(spawn, game_opts.head_bob)));
+    let explored = ExploredTiles::new(tile_map.width(), tile_map.height());
+    let stats = LevelStats::new(&tile_map);
+    let camera = Camera::new(33.0, 18.0, game_opts, asset_root, tex_loader, &tile_map)?;
+    let tile_map = Rc::new(RefCell::new(tile_map));
+    let scripts = Scripts::new(Rc::clone(&tile_map))?;
+
+    let components: Vec<Rc<RefCell<dyn GameComponent>>> = vec![
+        Rc::new(RefCell::new(Editor::new(game_opts, Rc::clone(&tile_map)))),
+        Rc::new(RefCell::new(PushWalls::new(Rc::clone(&tile_map)))),
+        Rc::new(RefCell::new(Triggers::new())),
+        Rc::new(RefCell::new(scripts)),
+        Rc::new(RefCell::new(Automap::new(game_opts))),
+        Rc::new(RefCell::new(Map::new(game_opts))),
+        Rc::new(RefCell::new(camera)),
+        Rc::new(RefCell::new(Weapon::new(game_opts))),
+        Rc::clone(&player) as Rc<RefCell<dyn GameComponent>>,
+        Rc::clone(messages) as Rc<RefCell<dyn GameComponent>>,
+    ];
+
+    Ok(Level {
+        player,
+        tile_map,
+        map_watcher,
+        explored,
+        stats,
+        components,
+    })
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::assets;
+
+/// How often files are checked for changes, in microseconds
+const POLL_INTERVAL_US: u32 = 500_000;
+
+/// Notices changes to files by polling their modification times, used to
+/// reload assets while the game runs
+pub struct FileWatcher {
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    /// Directories scanned for new files on every poll, with the extension
+    /// of the files to watch in them
+    dirs: Vec<(PathBuf, String)>,
+    since_poll_us: u32,
+}
+
+impl FileWatcher {
+    pub fn new() -> FileWatcher {
+        FileWatcher {
+            files: HashMap::new(),
+            dirs: vec![],
+            since_poll_us: 0,
+        }
+    }
+
+    /// Starts watching the file. Files that don't exist yet are watched for
+    /// being created.
+    pub fn watch(&mut self, path: &Path) {
+        self.files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| modified(path));
+    }
+
+    /// Starts watching every file with the extension in the directory and its
+    /// subdirectories, including the ones created later
+    pub fn watch_dir(&mut self, dir: &Path, extension: &str) {
+        for (_, path) in assets::discover(dir, extension).unwrap_or_default() {
+            self.watch(&path);
+        }
+        self.dirs.push((dir.to_path_buf(), String::from(extension)));
+    }
+
+    /// Returns the files that changed since they were last reported, checking
+    /// at most every `POLL_INTERVAL_US` of the `dt`s passed in
+    pub fn poll(&mut self, dt: u32) -> Vec<PathBuf> {
+        self.since_poll_us += dt;
+        if self.since_poll_us < POLL_INTERVAL_US {
+            return vec![];
+        }
+        self.since_poll_us = 0;
+
+        // New files count as changed, from not existing to existing
+        for (dir, extension) in self.dirs.iter() {
+            for (_, path) in assets::discover(dir, extension).unwrap_or_default() {
+                self.files.entry(path).or_insert(None);
+            }
+        }
+
+        let mut changed = vec![];
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed.sort();
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn reports_files_created_in_watched_directories() {
+        let dir = env::temp_dir().join(format!("rustic-fps-test-{}-watch", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("walls")).unwrap();
+        fs::write(dir.join("old.png"), b"old").unwrap();
+
+        let mut watcher = FileWatcher::new();
+        watcher.watch_dir(&dir, "png");
+        watcher.watch(&dir.join("animations.txt"));
+        assert_eq!(watcher.poll(POLL_INTERVAL_US), Vec::<PathBuf>::new());
+
+        fs::write(dir.join("walls/new.png"), b"new").unwrap();
+        fs::write(dir.join("notes.txt"), b"notes").unwrap();
+        fs::write(dir.join("animations.txt"), b"animations").unwrap();
+        // Changes are only looked for once the poll interval has passed
+        assert_eq!(watcher.poll(POLL_INTERVAL_US - 1), Vec::<PathBuf>::new());
+        assert_eq!(
+            watcher.poll(1),
+            vec![dir.join("animations.txt"), dir.join("walls/new.png")]
+        );
+        assert_eq!(watcher.poll(POLL_INTERVAL_US), Vec::<PathBuf>::new());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use crate::font;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::game::GameOpts;
+use crate::render_precedence::RenderPrecedence;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
+
+const TITLE_SCALE: u32 = 4;
+const TEXT_SCALE: u32 = 2;
+const BACKGROUND_COLOR: Color = Color::RGB(0, 40, 40);
+const TITLE_COLOR: Color = Color::RGB(255, 255, 255);
+const TEXT_COLOR: Color = Color::RGB(200, 200, 200);
+
+/// How the player did on a level
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LevelStats {
+    pub kills: u32,
+    pub enemies: u32,
+    pub secrets: u32,
+    pub total_secrets: u32,
+    pub treasure: u32,
+    pub total_treasure: u32,
+    pub time_us: u64,
+}
+
+impl LevelStats {
+    /// Nothing done yet, out of what's on the map
+    pub fn new(tile_map: &TileMap) -> LevelStats {
+        let count = |kind: ThingKind| tile_map.things().iter().filter(|thing| thing.kind == kind).count() as u32;
+
+        LevelStats {
+            enemies: count(ThingKind::Enemy),
+            total_secrets: tile_map.push_walls().len() as u32,
+            total_treasure: count(ThingKind::Item),
+            ..LevelStats::default()
+        }
+    }
+}
+
+/// Stats shown between levels, until the player presses Enter
+pub struct Intermission {
+    intermission_surface: Surface<'static>,
+    lines: Vec<String>,
+    title: String,
+    /// Name of the campaign, empty outside of campaigns
+    subtitle: String,
+    continue_requested: bool,
+}
+
+impl Intermission {
+    /// `campaign_done` says whether that was the last level
+    pub fn new(
+        game_opts: &GameOpts,
+        campaign_name: &str,
+        level: usize,
+        stats: &LevelStats,
+        health: u32,
+        campaign_done: bool,
+    ) -> Intermission {
+        let intermission_surface = Surface::new(
+            game_opts.screen_width,
+            game_opts.screen_height,
+            PixelFormatEnum::RGB24,
+        )
+        .unwrap();
+
+        let time_s = stats.time_us / 1_000_000;
+        let lines = vec![
+            format!("KILLS      {}/{}", stats.kills, stats.enemies),
+            format!("SECRETS    {}/{}", stats.secrets, stats.total_secrets),
+            format!("TREASURE   {}/{}", stats.treasure, stats.total_treasure),
+            format!("TIME       {}:{:02}", time_s / 60, time_s % 60),
+            format!("HEALTH     {}", health),
+            String::new(),
+            String::from(if campaign_done {
+                "PRESS ENTER TO PLAY AGAIN"
+            } else {
+                "PRESS ENTER TO CONTINUE"
+            }),
+        ];
+        let title = if campaign_done {
+            String::from("CAMPAIGN COMPLETE")
+        } else {
+            format!("LEVEL {} COMPLETE", level + 1)
+        };
+
+        Intermission {
+            intermission_surface,
+            lines,
+            title,
+            subtitle: String::from(campaign_name),
+            continue_requested: false,
+        }
+    }
+}
+
+impl GameComponent for Intermission {
+    fn update(&mut self, game: &Game, _: u32) {
+        game.pause_movement();
+        if self.continue_requested {
+            self.continue_requested = false;
+            game.request_next_level();
+        }
+    }
+
+    fn draw(&mut self, _: &Game, _: u32) -> Option<&Surface> {
+        let width = self.intermission_surface.width() as i32;
+        self.intermission_surface
+            .fill_rect(None, BACKGROUND_COLOR)
+            .expect("Failed clearing the intermission");
+
+        let mut y = font::text_height(TITLE_SCALE) as i32;
+        let title_x = (width - font::text_width(&self.title, TITLE_SCALE) as i32) / 2;
+        font::draw_text(&mut self.intermission_surface, title_x, y, &self.title, TITLE_SCALE, TITLE_COLOR);
+        y += 2 * font::text_height(TITLE_SCALE) as i32;
+        if !self.subtitle.is_empty() {
+            let subtitle_x = (width - font::text_width(&self.subtitle, TEXT_SCALE) as i32) / 2;
+            font::draw_text(&mut self.intermission_surface, subtitle_x, y, &self.subtitle, TEXT_SCALE, TITLE_COLOR);
+            y += 3 * font::text_height(TEXT_SCALE) as i32;
+        }
+
+        // Lined up on the longest line so the numbers form a column
+        let longest = self.lines.iter().map(|line| font::text_width(line, TEXT_SCALE)).max().unwrap_or(0);
+        let x = (width - longest as i32) / 2;
+        for line in self.lines.iter() {
+            font::draw_text(&mut self.intermission_surface, x, y, line, TEXT_SCALE, TEXT_COLOR);
+            y += 2 * font::text_height(TEXT_SCALE) as i32;
+        }
+
+        Some(&self.intermission_surface)
+    }
+
+    fn handle_event(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::Return),
+                ..
+            }
+            | Event::KeyDown {
+                keycode: Some(Keycode::KpEnter),
+                ..
+            } => {
+                self.continue_requested = true;
+                None
+            }
+            // Nothing else happens until the player moves on
+            Event::KeyDown { .. } => None,
+            _ => Some(event),
+        }
+    }
+
+    fn render_precendce(&self) -> RenderPrecedence {
+        RenderPrecedence::Intermission
+    }
+}
@@ -0,0 +1,579 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+
+use crate::events::GameEvent;
+use crate::map;
+use crate::tile_map::Thing;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
+use crate::triggers::Trigger;
+use crate::triggers::TriggerKind;
+use crate::vector::Vec2f;
+
+/// Width and height of generated levels unless asked otherwise
+pub const DEFAULT_SIZE: usize = 48;
+pub const MIN_SIZE: usize = 16;
+pub const MAX_SIZE: usize = 256;
+
+const WALL_TILE: u16 = 1;
+/// Wall tiles rooms are lined with, with the textures of the ones that aren't
+/// built in tiles
+const ROOM_WALL_TILES: [u16; 5] = [1, 3, 13, 14, 15];
+const ROOM_WALL_TEXTURES: [(u16, &'static str); 3] =
+    [(13, "procedural/brick"), (14, "procedural/wood"), (15, "procedural/metal")];
+const CAVE_WALL_TILE: u16 = 13;
+const CAVE_WALL_TEXTURE: &'static str = "procedural/stone";
+/// Closed doors are walls until the player uses them
+const DOOR_TILE: u16 = 17;
+const DOOR_TEXTURE: &'static str = "procedural/wood";
+
+/// BSP leaves aren't split below this size, rooms are at least `MIN_ROOM`
+/// across and leave a wall on every side of them
+const MIN_LEAF: usize = 8;
+const MAX_LEAF: usize = 16;
+const MIN_ROOM: usize = 4;
+
+/// Chance of a cave tile starting out as a wall, before smoothing
+const CAVE_FILL: f32 = 0.45;
+const CAVE_SMOOTHING_STEPS: u32 = 5;
+
+/// One item for every this many reachable tiles, and so on
+const TILES_PER_ITEM: usize = 40;
+const TILES_PER_ENEMY: usize = 60;
+/// Enemies are kept this many steps away from the spawn
+const SAFE_DISTANCE: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// Rooms from binary space partitioning, joined by corridors with doors
+    Rooms,
+    /// Caves grown by a cellular automaton
+    Caves,
+}
+
+impl Layout {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Rooms => "rooms",
+            Layout::Caves => "caves",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "rooms" => Some(Layout::Rooms),
+            "caves" => Some(Layout::Caves),
+            _ => None,
+        }
+    }
+}
+
+/// Everything a level is generated from. The same parameters always give the
+/// same level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeneratorParams {
+    pub layout: Layout,
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+}
+
+/// Small xorshift generator, so levels don't depend on any random number
+/// crate's implementation
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Xorshift gets stuck on zero
+        Rng((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// `min` to `max`, excluding `max`
+    fn range(&mut self, min: usize, max: usize) -> usize {
+        if max <= min {
+            return min;
+        }
+
+        min + (self.next() % (max - min) as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        (self.next() % 1_000_000) as f32 / 1_000_000.0 < probability
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    fn center(&self) -> (usize, usize) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+}
+
+/// Tiles of a level being generated
+struct Grid {
+    width: usize,
+    height: usize,
+    tiles: Vec<u16>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize, tile: u16) -> Grid {
+        Grid {
+            width,
+            height,
+            tiles: vec![tile; width * height],
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    fn is_open(&self, x: usize, y: usize) -> bool {
+        self.tiles[self.index(x, y)] == 0
+    }
+
+    fn set(&mut self, x: usize, y: usize, tile: u16) {
+        let index = self.index(x, y);
+        self.tiles[index] = tile;
+    }
+
+    /// Carves an L shaped corridor between the two tiles
+    fn carve_corridor(&mut self, from: (usize, usize), to: (usize, usize), horizontal_first: bool) {
+        let corner = if horizontal_first { (to.0, from.1) } else { (from.0, to.1) };
+        for (a, b) in [(from, corner), (corner, to)].iter() {
+            for x in a.0.min(b.0)..=a.0.max(b.0) {
+                for y in a.1.min(b.1)..=a.1.max(b.1) {
+                    self.set(x, y, 0);
+                }
+            }
+        }
+    }
+
+    /// Open tiles reachable from the start, with their distance in steps
+    fn distances_from(&self, start: (usize, usize)) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.tiles.len()];
+        let mut queue = VecDeque::new();
+        distances[self.index(start.0, start.1)] = Some(0);
+        queue.push_back(start);
+
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = distances[self.index(x, y)].unwrap_or(0);
+            let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+            for (nx, ny) in neighbours.iter().cloned() {
+                if nx >= self.width || ny >= self.height || !self.is_open(nx, ny) {
+                    continue;
+                }
+                let index = self.index(nx, ny);
+                if distances[index].is_none() {
+                    distances[index] = Some(distance + 1);
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+
+        distances
+    }
+}
+
+/// Generates a level with a spawn every open tile can be reached from and an
+/// exit far away from it
+pub fn generate(params: &GeneratorParams) -> TileMap {
+    let width = params.width.max(MIN_SIZE).min(MAX_SIZE);
+    let height = params.height.max(MIN_SIZE).min(MAX_SIZE);
+    let mut rng = Rng::new(params.seed);
+
+    let (mut grid, spawn, textures, doors) = match params.layout {
+        Layout::Rooms => generate_rooms(width, height, &mut rng),
+        Layout::Caves => generate_caves(width, height, &mut rng),
+    };
+
+    // Whatever can't be reached from the spawn is walled up
+    let distances = grid.distances_from(spawn);
+    for index in 0..grid.tiles.len() {
+        if grid.tiles[index] == 0 && distances[index].is_none() {
+            grid.tiles[index] = if params.layout == Layout::Caves {
+                CAVE_WALL_TILE
+            } else {
+                WALL_TILE
+            };
+        }
+    }
+
+    let tile_center = |index: usize| Vec2f::new((index % width) as f32 + 0.5, (index / width) as f32 + 0.5);
+    let spawn_index = grid.index(spawn.0, spawn.1);
+    let mut tile_map = TileMap::new(width, height, grid.tiles, Some(tile_center(spawn_index)));
+    tile_map.set_name(&format!(
+        "generated {} {}x{} seed {}",
+        params.layout.name(),
+        width,
+        height,
+        params.seed
+    ));
+    for (tile, tex_name) in textures {
+        tile_map.set_texture(tile, tex_name);
+    }
+    if !doors.is_empty() {
+        tile_map.set_texture(DOOR_TILE, DOOR_TEXTURE);
+    }
+    for door in doors.iter() {
+        let (column, row) = (door % width, door / width);
+        tile_map.set_tile(column, row, DOOR_TILE);
+        tile_map.add_thing(Thing {
+            kind: ThingKind::Door,
+            pos: tile_center(*door),
+        });
+        tile_map.add_trigger(Trigger {
+            kind: TriggerKind::Use,
+            region: Some((column, row, 1, 1)),
+            action: GameEvent::OpenDoor { column, row },
+        });
+    }
+
+    // Things go on reachable tiles, one per tile and never on the spawn
+    let mut free: Vec<usize> = (0..width * height)
+        .filter(|index| distances[*index].is_some() && *index != spawn_index && !doors.contains(index))
+        .collect();
+
+    // The key and the exit are as far from the spawn as it gets
+    let key_at = free.iter().cloned().max_by_key(|index| distances[*index]);
+    if let Some(key_at) = key_at {
+        tile_map.add_thing(Thing {
+            kind: ThingKind::Key,
+            pos: tile_center(key_at),
+        });
+        free.retain(|index| *index != key_at);
+    }
+
+    // The exit goes in a wall next to the key, or next to the furthest tile
+    // that has one. Every reachable area is walled in, so there always is one.
+    let mut by_distance: Vec<usize> = (0..width * height)
+        .filter(|index| distances[*index].is_some())
+        .collect();
+    by_distance.sort_by_key(|index| Reverse(distances[*index]));
+    let is_wall = |column: usize, row: usize| {
+        tile_map
+            .tile(column, row)
+            .map_or(false, |tile| tile != 0 && tile != DOOR_TILE)
+    };
+    let exit = key_at.into_iter().chain(by_distance).find_map(|index| {
+        let (x, y) = (index % width, index / width);
+        let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+        neighbours.iter().cloned().find(|(nx, ny)| is_wall(*nx, *ny))
+    });
+    if let Some((exit_x, exit_y)) = exit {
+        tile_map.set_tile(exit_x, exit_y, map::EXIT_TILE);
+    }
+
+    let reachable = free.len();
+    for _ in 0..reachable / TILES_PER_ITEM {
+        if free.is_empty() {
+            break;
+        }
+        let item_at = free.swap_remove(rng.range(0, free.len()));
+        tile_map.add_thing(Thing {
+            kind: ThingKind::Item,
+            pos: tile_center(item_at),
+        });
+    }
+
+    let mut enemy_spots: Vec<usize> = free
+        .iter()
+        .cloned()
+        .filter(|index| distances[*index].map_or(false, |distance| distance >= SAFE_DISTANCE))
+        .collect();
+    for _ in 0..reachable / TILES_PER_ENEMY {
+        if enemy_spots.is_empty() {
+            break;
+        }
+        let enemy_at = enemy_spots.swap_remove(rng.range(0, enemy_spots.len()));
+        tile_map.add_thing(Thing {
+            kind: ThingKind::Enemy,
+            pos: tile_center(enemy_at),
+        });
+    }
+
+    tile_map
+}
+
+type Generated = (Grid, (usize, usize), Vec<(u16, &'static str)>, Vec<usize>);
+
+/// Splits the map into leaves, puts a room in each and joins sibling
+/// subtrees with corridors, so every room is connected
+fn generate_rooms(width: usize, height: usize, rng: &mut Rng) -> Generated {
+    let mut grid = Grid::new(width, height, WALL_TILE);
+    let mut rooms = vec![];
+    split(
+        &mut grid,
+        &mut rooms,
+        rng,
+        Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        },
+    );
+
+    let mut room_of = vec![None; width * height];
+    for (room_index, room) in rooms.iter().enumerate() {
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                room_of[grid.index(x, y)] = Some(room_index);
+            }
+        }
+    }
+
+    // Line each room's walls, walls shared with an earlier room keep its style
+    for room in rooms.iter() {
+        let style = ROOM_WALL_TILES[rng.range(0, ROOM_WALL_TILES.len())];
+        for y in room.y - 1..=room.y + room.height {
+            for x in room.x - 1..=room.x + room.width {
+                if grid.tiles[grid.index(x, y)] == WALL_TILE {
+                    grid.set(x, y, style);
+                }
+            }
+        }
+    }
+
+    // Doorways are corridor tiles next to a room, between two walls
+    let mut doors = vec![];
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let index = grid.index(x, y);
+            if !grid.is_open(x, y) || room_of[index].is_some() {
+                continue;
+            }
+
+            let walled_x = !grid.is_open(x - 1, y) && !grid.is_open(x + 1, y);
+            let walled_y = !grid.is_open(x, y - 1) && !grid.is_open(x, y + 1);
+            let enters_room = if walled_x && !walled_y {
+                room_of[grid.index(x, y - 1)].is_some() || room_of[grid.index(x, y + 1)].is_some()
+            } else if walled_y && !walled_x {
+                room_of[grid.index(x - 1, y)].is_some() || room_of[grid.index(x + 1, y)].is_some()
+            } else {
+                false
+            };
+            // Rooms a tile apart share a door
+            let next_to_door = doors.contains(&(index - 1)) || doors.contains(&(index - width));
+            if enters_room && !next_to_door {
+                doors.push(index);
+            }
+        }
+    }
+
+    let spawn = rooms[0].center();
+    (grid, spawn, ROOM_WALL_TEXTURES.to_vec(), doors)
+}
+
+/// Returns the rooms in the subtree after connecting them
+fn split(grid: &mut Grid, rooms: &mut Vec<Rect>, rng: &mut Rng, rect: Rect) -> Vec<usize> {
+    let can_split_x = rect.width >= 2 * MIN_LEAF;
+    let can_split_y = rect.height >= 2 * MIN_LEAF;
+    let small_enough = rect.width <= MAX_LEAF && rect.height <= MAX_LEAF;
+    if (!can_split_x && !can_split_y) || (small_enough && rng.chance(0.25)) {
+        let room_width = rng.range(MIN_ROOM, rect.width - 1);
+        let room_height = rng.range(MIN_ROOM, rect.height - 1);
+        let room = Rect {
+            x: rect.x + 1 + rng.range(0, rect.width - 1 - room_width),
+            y: rect.y + 1 + rng.range(0, rect.height - 1 - room_height),
+            width: room_width,
+            height: room_height,
+        };
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                grid.set(x, y, 0);
+            }
+        }
+
+        rooms.push(room);
+        return vec![rooms.len() - 1];
+    }
+
+    let split_x = can_split_x && (!can_split_y || rect.width > rect.height || (rect.width == rect.height && rng.chance(0.5)));
+    let (first, second) = if split_x {
+        let at = rng.range(MIN_LEAF, rect.width - MIN_LEAF + 1);
+        (
+            Rect { width: at, ..rect },
+            Rect {
+                x: rect.x + at,
+                width: rect.width - at,
+                ..rect
+            },
+        )
+    } else {
+        let at = rng.range(MIN_LEAF, rect.height - MIN_LEAF + 1);
+        (
+            Rect { height: at, ..rect },
+            Rect {
+                y: rect.y + at,
+                height: rect.height - at,
+                ..rect
+            },
+        )
+    };
+
+    let mut first_rooms = split(grid, rooms, rng, first);
+    let second_rooms = split(grid, rooms, rng, second);
+    let from = rooms[first_rooms[rng.range(0, first_rooms.len())]].center();
+    let to = rooms[second_rooms[rng.range(0, second_rooms.len())]].center();
+    grid.carve_corridor(from, to, rng.chance(0.5));
+
+    first_rooms.extend(second_rooms);
+    first_rooms
+}
+
+/// Smooths random noise into caves, then tunnels from every cave to the
+/// biggest one
+fn generate_caves(width: usize, height: usize, rng: &mut Rng) -> Generated {
+    let is_border = |x: usize, y: usize| x == 0 || y == 0 || x == width - 1 || y == height - 1;
+    let mut walls: Vec<bool> = (0..width * height)
+        .map(|index| is_border(index % width, index / width) || rng.chance(CAVE_FILL))
+        .collect();
+
+    for _ in 0..CAVE_SMOOTHING_STEPS {
+        let mut smoothed = walls.clone();
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let mut wall_neighbours = 0;
+                for ny in y - 1..=y + 1 {
+                    for nx in x - 1..=x + 1 {
+                        if (nx, ny) != (x, y) && walls[ny * width + nx] {
+                            wall_neighbours += 1;
+                        }
+                    }
+                }
+                if wall_neighbours > 4 {
+                    smoothed[y * width + x] = true;
+                } else if wall_neighbours < 4 {
+                    smoothed[y * width + x] = false;
+                }
+            }
+        }
+        walls = smoothed;
+    }
+
+    let mut grid = Grid::new(width, height, CAVE_WALL_TILE);
+    for (index, wall) in walls.iter().enumerate() {
+        if !wall {
+            grid.tiles[index] = 0;
+        }
+    }
+    if grid.tiles.iter().all(|tile| *tile != 0) {
+        grid.set(width / 2, height / 2, 0);
+    }
+
+    // Split the open tiles into caves, biggest first
+    let mut caves: Vec<Vec<usize>> = vec![];
+    let mut assigned = vec![false; width * height];
+    for index in 0..grid.tiles.len() {
+        if grid.tiles[index] != 0 || assigned[index] {
+            continue;
+        }
+
+        let distances = grid.distances_from((index % width, index / width));
+        let cave: Vec<usize> = (0..distances.len()).filter(|index| distances[*index].is_some()).collect();
+        for tile in cave.iter() {
+            assigned[*tile] = true;
+        }
+        caves.push(cave);
+    }
+    caves.sort_by(|a, b| b.len().cmp(&a.len()));
+
+    let mut connected = caves[0].clone();
+    for cave in caves.iter().skip(1) {
+        let to_xy = |index: usize| (index % width, index / width);
+        let mut closest = (usize::MAX, cave[0], connected[0]);
+        for from in cave.iter() {
+            for to in connected.iter() {
+                let ((fx, fy), (tx, ty)) = (to_xy(*from), to_xy(*to));
+                let distance = (fx as i64 - tx as i64).abs() as usize + (fy as i64 - ty as i64).abs() as usize;
+                if distance < closest.0 {
+                    closest = (distance, *from, *to);
+                }
+            }
+        }
+
+        grid.carve_corridor(to_xy(closest.1), to_xy(closest.2), rng.chance(0.5));
+        connected.extend(cave.iter());
+    }
+
+    // Spawn in the open as close to the middle as possible
+    let middle = (width / 2, height / 2);
+    let spawn_index = caves[0]
+        .iter()
+        .cloned()
+        .min_by_key(|index| {
+            let (x, y) = (index % width, index / width);
+            (x as i64 - middle.0 as i64).abs() + (y as i64 - middle.1 as i64).abs()
+        })
+        .unwrap_or(grid.index(middle.0, middle.1));
+
+    (
+        grid,
+        (spawn_index % width, spawn_index / width),
+        vec![(CAVE_WALL_TILE, CAVE_WALL_TEXTURE)],
+        vec![],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels() -> Vec<TileMap> {
+        let mut levels = vec![];
+        for layout in [Layout::Rooms, Layout::Caves].iter() {
+            for seed in 0..20 {
+                levels.push(generate(&GeneratorParams {
+                    layout: *layout,
+                    width: MIN_SIZE,
+                    height: MIN_SIZE,
+                    seed,
+                }));
+            }
+        }
+
+        levels
+    }
+
+    #[test]
+    fn levels_always_have_an_exit() {
+        for level in levels() {
+            assert!(level.tiles().contains(&map::EXIT_TILE), "no exit in {}", level.name());
+        }
+    }
+
+    #[test]
+    fn doors_are_walls_that_use_triggers_open() {
+        let levels = levels();
+        assert!(levels.iter().any(|level| level.tiles().contains(&DOOR_TILE)));
+
+        for level in levels.iter() {
+            for door in level.things().iter().filter(|thing| thing.kind == ThingKind::Door) {
+                let (column, row) = (door.pos.x() as usize, door.pos.y() as usize);
+                assert_eq!(level.tile(column, row), Some(DOOR_TILE));
+                let opens = |trigger: &Trigger| {
+                    trigger.kind == TriggerKind::Use
+                        && trigger.contains(column, row)
+                        && trigger.action == GameEvent::OpenDoor { column, row }
+                };
+                assert!(level.triggers().iter().any(opens));
+            }
+        }
+    }
+}
@@ -0,0 +1,6 @@
+//! Asset handling shared by the game and the `pack_assets` tool
+
+pub mod asset_pack;
+pub mod assets;
+pub mod texture_animations;
+pub mod textures;
@@ -1,18 +1,50 @@
 extern crate sdl2;
 
+use std::env;
 use std::error::Error;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+mod automap;
 mod camera;
+mod campaign;
+mod editor;
+mod events;
+mod explored;
+mod font;
 mod game;
+mod hot_reload;
+mod intermission;
+mod level_generator;
 mod map;
+mod map_query;
+mod messages;
+mod palette;
 mod player;
+mod procedural_textures;
+mod push_walls;
 mod rays;
 mod render_precedence;
-mod textures;
+mod save_game;
+mod scripting;
+mod tile_map;
+mod triggers;
 mod vector;
+mod weapon;
+mod wolf3d;
 
+use rustic_fps::asset_pack;
+use rustic_fps::assets;
+use rustic_fps::textures;
+
+use camera::RenderMode;
 use game::Game;
 use game::GameOpts;
+use game::HeadBobOpts;
+use game::MinimapOpts;
+use level_generator::GeneratorParams;
+use level_generator::Layout;
 
 const TITLE: &'static str = "Rustic FPS";
 const SCREEN_WIDTH: u32 = 640;
@@ -22,10 +54,102 @@ const GAME_OPTS: GameOpts = GameOpts {
     title: TITLE,
     screen_width: SCREEN_WIDTH,
     screen_height: SCREEN_HEIGHT,
+    head_bob: HeadBobOpts {
+        enabled: true,
+        amplitude: 0.03,
+        frequency: 0.25,
+        weapon_sway: 0.008,
+    },
+    minimap: MinimapOpts {
+        zoom: 4.0,
+        rotating: false,
+    },
+    asset_root: None,
+    map_path: None,
+    wolf_dir: None,
+    level: 1,
+    generate: None,
+    campaign_path: None,
+    hot_reload: false,
+    edit: false,
+    render_mode: RenderMode::TrueColor,
 };
 
 pub fn main() -> Result<(), Box<dyn Error>> {
-    let mut game = Game::new(GAME_OPTS)?;
+    let mut game_opts = GAME_OPTS;
+    let mut args = env::args().skip(1);
+    let mut layout_to_generate = None;
+    let mut seed = None;
+    let mut size = (level_generator::DEFAULT_SIZE, level_generator::DEFAULT_SIZE);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-head-bob" => game_opts.head_bob.enabled = false,
+            "--assets" => {
+                let asset_root = args.next().ok_or("--assets expects a directory")?;
+                game_opts.asset_root = Some(PathBuf::from(asset_root));
+            }
+            "--map" => {
+                let map_path = args.next().ok_or("--map expects a map file")?;
+                game_opts.map_path = Some(PathBuf::from(map_path));
+            }
+            "--wolf" => {
+                let wolf_dir = args.next().ok_or("--wolf expects a directory")?;
+                game_opts.wolf_dir = Some(PathBuf::from(wolf_dir));
+            }
+            "--level" => {
+                let level = args.next().ok_or("--level expects a level number")?;
+                game_opts.level = level
+                    .parse()
+                    .map_err(|_| format!("\"{}\" is not a level number", level))?;
+            }
+            "--generate" => {
+                let layout = args.next().ok_or("--generate expects rooms or caves")?;
+                layout_to_generate =
+                    Some(Layout::from_name(&layout).ok_or(format!("\"{}\" is not rooms or caves", layout))?);
+            }
+            "--seed" => {
+                let seed_arg = args.next().ok_or("--seed expects a number")?;
+                seed = Some(
+                    seed_arg
+                        .parse()
+                        .map_err(|_| format!("\"{}\" is not a seed", seed_arg))?,
+                );
+            }
+            "--size" => {
+                let size_arg = args.next().ok_or("--size expects <width>x<height>")?;
+                size = size_arg
+                    .split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .ok_or(format!("\"{}\" is not <width>x<height>", size_arg))?;
+            }
+            "--campaign" => {
+                let campaign_path = args.next().ok_or("--campaign expects a campaign file")?;
+                game_opts.campaign_path = Some(PathBuf::from(campaign_path));
+            }
+            "--dev" => game_opts.hot_reload = true,
+            "--edit" => game_opts.edit = true,
+            "--palette" => game_opts.render_mode = RenderMode::Palette,
+            _ => return Err(format!("Unknown argument \"{}\"", arg).into()),
+        }
+    }
+
+    if let Some(layout) = layout_to_generate {
+        // Without a seed every run is a new level, the seed is printed so it can be played again
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or(0)
+        });
+        game_opts.generate = Some(GeneratorParams {
+            layout,
+            width: size.0,
+            height: size.1,
+            seed,
+        });
+    }
+
+    let mut game = Game::new(game_opts)?;
 
     game.run();
 
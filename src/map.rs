@@ -1,16 +1,26 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 use sdl2::surface::Surface;
 
+use crate::camera::CameraFrame;
+use crate::explored::ExploredTiles;
 use crate::game::Game;
 use crate::game::GameComponent;
+use crate::game::GameOpts;
 use crate::render_precedence::RenderPrecedence;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
 use crate::vector::Vec2f;
 
+/// Walking into this tile ends the level
+pub const EXIT_TILE: u16 = 16;
+
 pub const MAP: [u16; 400] = [
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 12, 12, 12, 12, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 0, 0, 0, 0, 0, 1,
     1, 0, 0, 2, 0, 2, 0, 2, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 1,
     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 0, 0, 1,
     1, 0, 0, 2, 0, 2, 0, 2, 0, 0, 0, 0, 3, 0, 0, 0, 3, 0, 0, 1,
@@ -18,81 +28,260 @@ pub const MAP: [u16; 400] = [
     1, 0, 0, 2, 0, 2, 0, 2, 0, 0, 0, 0, 0, 2, 0, 2, 0, 0, 0, 1,
     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 8, 8, 8, 0, 0, 0, 0, 0, 0, 0, 0, 1,
     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 0, 0, 4, 4, 4, 4, 0, 0, 0, 0, 3, 5, 5, 3, 0, 0, 0, 0, 1,
     1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10, 10, 10, 0, 0, 0, 0, 0, 0, 1,
+    1, 11, 11, 11, 0, 6, 6, 6, 0, 0, 0, 0, 0, 0, 0, 0, 7, 7, 0, 1,
+    1, 0, 0, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7, 7, 0, 1,
+    1, 11, 11, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 16, 1, 1, 1, 1, 1, 1, 1, 1, 1,
 ];
 
+/// Vertical extent of the open space within a tile, in wall heights where
+/// 0.0 is the floor and 1.0 is the ceiling. Everything below `floor` and
+/// above `ceiling` is solid, so a tile whose floor reaches its ceiling is a
+/// regular wall that is `floor` units tall.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileHeights {
+    pub floor: f32,
+    pub ceiling: f32,
+}
+
+impl TileHeights {
+    /// A tile is opaque if no ray can see past it
+    pub fn is_opaque(&self) -> bool {
+        self.floor >= self.ceiling || self.floor >= 1.0
+    }
+
+    /// Solid vertical spans of the tile as (bottom, top) pairs
+    pub fn solid_spans(&self) -> Vec<(f32, f32)> {
+        if self.floor >= self.ceiling {
+            return vec![(0.0, self.floor)];
+        }
+
+        let mut spans = vec![];
+        if self.floor > 0.0 {
+            spans.push((0.0, self.floor));
+        }
+        if self.ceiling < 1.0 {
+            spans.push((self.ceiling, 1.0));
+        }
+
+        spans
+    }
+}
+
+pub fn tile_heights(tile: u16) -> TileHeights {
+    let (floor, ceiling) = match tile {
+        // Thin walls leave the rest of their tile open
+        0 | 8 | 9 | 10 => (0.0, 1.0),
+        // Step up onto a raised platform
+        4 => (0.25, 1.0),
+        // Window with a sill and a lintel
+        5 => (0.4, 0.8),
+        // Low overhang
+        6 => (0.0, 0.7),
+        // Tall pillar sticking out above the rest of the walls
+        7 => (1.6, 1.6),
+        _ => (1.0, 1.0),
+    };
+
+    TileHeights { floor, ceiling }
+}
+
+/// Texture of the tile unless the map says otherwise
+pub fn texture_name(tile: u16) -> &'static str {
+    match tile {
+        2 => "eagle",
+        3 | 5 => "redbrick",
+        8 | 9 => "procedural/grate",
+        10 => "procedural/glass",
+        11 => "lava",
+        12 => "conveyor",
+        EXIT_TILE => "procedural/elevator",
+        _ => "bluestone",
+    }
+}
+
+/// Which world axis a thin wall's plane is perpendicular to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThinWallAxis {
+    X,
+    Y,
+}
+
+/// A wall plane inside a tile, e.g. a grate, fence or glass pane, that rays
+/// can see through wherever its texture is transparent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThinWall {
+    pub axis: ThinWallAxis,
+    /// Plane offset from the tile's lower edge along `axis`, 0.0 to 1.0
+    pub offset: f32,
+}
+
+pub fn thin_wall(tile: u16) -> Option<ThinWall> {
+    let (axis, offset) = match tile {
+        // Grate running along x through the middle of the tile
+        8 => (ThinWallAxis::Y, 0.5),
+        // Grate running along y through the middle of the tile
+        9 => (ThinWallAxis::X, 0.5),
+        // Glass pane on the tile's edge
+        10 => (ThinWallAxis::Y, 0.0),
+        _ => return None,
+    };
+
+    Some(ThinWall { axis, offset })
+}
+
+/// Pixels per tile the minimap's zoom is kept between
+const MIN_ZOOM: f32 = 1.0;
+const MAX_ZOOM: f32 = 16.0;
+const ZOOM_STEP: f32 = 1.25;
+/// Pixels per tile fullscreen maps start at, see `PanZoom`
+const DEFAULT_PAN_ZOOM: f32 = 16.0;
+/// How fast the arrow keys pan fullscreen maps, pixels/s
+const PAN_SPEED: f32 = 400.0;
+
+/// Outside the map and tiles that haven't been explored yet
+const OUTSIDE_COLOR: [u8; 3] = [0, 0, 0];
+const FLOOR_COLOR: [u8; 3] = [24, 24, 24];
+/// Walls whose texture isn't known yet
+const UNKNOWN_WALL_COLOR: [u8; 3] = [128, 128, 128];
+const VIEW_CONE_COLOR: [u8; 3] = [255, 255, 160];
+pub const PLAYER_COLOR: [u8; 3] = [255, 0, 255];
+
+/// Icons are 5x5 pixels, one row per entry with the leftmost pixel in the
+/// highest bit
+pub const ICON_SIZE: i32 = 5;
+const DOOR_ICON: [u8; 5] = [0b11111, 0b10001, 0b10101, 0b10001, 0b11111];
+const KEY_ICON: [u8; 5] = [0b01110, 0b01010, 0b01110, 0b00100, 0b00110];
+const ITEM_ICON: [u8; 5] = [0b00100, 0b01110, 0b11111, 0b01110, 0b00100];
+const ENEMY_ICON: [u8; 5] = [0b01110, 0b10101, 0b11111, 0b01110, 0b01010];
+const DOOR_COLOR: [u8; 3] = [200, 140, 60];
+const KEY_COLOR: [u8; 3] = [255, 215, 0];
+const ITEM_COLOR: [u8; 3] = [80, 200, 255];
+const ENEMY_COLOR: [u8; 3] = [255, 60, 60];
+
+/// Corner overlay showing the map around the player
 pub struct Map {
     screen_width: u32,
     screen_height: u32,
     map_surface: Surface<'static>,
-    last_player_pos: Vec2f,
+    /// Pixels per tile
+    zoom: f32,
+    /// Turns the map with the player so they always face up
+    rotating: bool,
 }
 
 impl Map {
-    pub fn new(screen_width: u32, screen_height: u32) -> Map {
-        let map_surface = sdl2::surface::Surface::new(20, 20, PixelFormatEnum::RGB24).unwrap();
+    pub fn new(game_opts: &GameOpts) -> Map {
+        let map_surface = Surface::new(
+            game_opts.screen_width / 5,
+            game_opts.screen_height / 5,
+            PixelFormatEnum::RGB24,
+        )
+        .unwrap();
 
         Map {
-            screen_width,
-            screen_height,
+            screen_width: game_opts.screen_width,
+            screen_height: game_opts.screen_height,
             map_surface,
-            last_player_pos: Vec2f::new(0.0, 0.0),
+            zoom: clamp_zoom(game_opts.minimap.zoom),
+            rotating: game_opts.minimap.rotating,
         }
     }
 }
 
 impl GameComponent for Map {
     fn draw(&mut self, game: &Game, _: u32) -> Option<&Surface> {
-        if *game.player().pos() == self.last_player_pos {
-            return Some(&self.map_surface);
-        }
+        let tile_map = game.tile_map();
+        let camera_frame = game.camera_frame();
+        let explored = game.explored();
+        let player_pos = *game.player().pos();
+        let player_dir = game.player().dir().normalize();
+
+        let width = self.map_surface.width() as i32;
+        let height = self.map_surface.height() as i32;
+        let pitch = self.map_surface.pitch() as usize;
+        let zoom = self.zoom;
+        let view = MapView::new(player_pos, player_dir, self.rotating, zoom, width, height);
 
-        let last_player_pos = game.player().pos().clone();
+        // Field of view, traced along the camera's rays up to where they stopped
+        let mut in_view = vec![false; (width * height) as usize];
+        let origin = view.to_screen(camera_frame.origin);
+        for ray_end in camera_frame.ray_ends.iter() {
+            for_line_pixels(origin, view.to_screen(*ray_end), |x, y| {
+                if 0 <= x && x < width && 0 <= y && y < height {
+                    in_view[(y * width + x) as usize] = true;
+                }
+            });
+        }
 
         self.map_surface.with_lock_mut(|buffer: &mut [u8]| {
-            for y in 0..20 {
-                for x in 0..20 {
-                    let index = y * 20 + x;
-                    let buffer_index = index * 3;
-                    if x == last_player_pos.x() as usize && y == last_player_pos.y() as usize {
-                        buffer[buffer_index] = 255 as u8;
-                        buffer[buffer_index + 1] = 0 as u8;
-                        buffer[buffer_index + 2] = 255;
-                    } else if MAP[index] == 1 {
-                        buffer[buffer_index] = 255 as u8;
-                        buffer[buffer_index + 1] = 0 as u8;
-                        buffer[buffer_index + 2] = 0;
-                    } else if MAP[index] == 2 {
-                        buffer[buffer_index] = 0 as u8;
-                        buffer[buffer_index + 1] = 255 as u8;
-                        buffer[buffer_index + 2] = 0;
-                    } else if MAP[index] == 3 {
-                        buffer[buffer_index] = 0 as u8;
-                        buffer[buffer_index + 1] = 0 as u8;
-                        buffer[buffer_index + 2] = 255 as u8;
+            let mut put = |x: i32, y: i32, color: [u8; 3]| {
+                if 0 <= x && x < width && 0 <= y && y < height {
+                    let index = y as usize * pitch + x as usize * 3;
+                    buffer[index..index + 3].copy_from_slice(&color);
+                }
+            };
+
+            for y in 0..height {
+                for x in 0..width {
+                    let world_pos = view.to_world(x as f32 + 0.5, y as f32 + 0.5);
+                    let color = tile_color(&tile_map, &camera_frame, Some(&explored), world_pos, zoom);
+                    if in_view[(y * width + x) as usize] {
+                        put(x, y, mix(color, VIEW_CONE_COLOR, 0.3));
                     } else {
-                        buffer[buffer_index] = 0 as u8;
-                        buffer[buffer_index + 1] = 0 as u8;
-                        buffer[buffer_index + 2] = 0 as u8;
+                        put(x, y, color);
                     }
                 }
             }
-        });
 
-        self.last_player_pos = last_player_pos;
+            plot_player(&view, player_pos, player_dir, |x, y| put(x, y, PLAYER_COLOR));
+        });
 
         Some(&self.map_surface)
     }
 
+    fn handle_event(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::Equals),
+                ..
+            }
+            | Event::KeyDown {
+                keycode: Some(Keycode::KpPlus),
+                ..
+            } => {
+                self.zoom = clamp_zoom(self.zoom * ZOOM_STEP);
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Minus),
+                ..
+            }
+            | Event::KeyDown {
+                keycode: Some(Keycode::KpMinus),
+                ..
+            } => {
+                self.zoom = clamp_zoom(self.zoom / ZOOM_STEP);
+                None
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::N),
+                ..
+            } => {
+                self.rotating = !self.rotating;
+                println!("\nRotating minimap: {}", self.rotating);
+                None
+            }
+            _ => Some(event),
+        }
+    }
+
     fn target_rect(&self) -> Option<Rect> {
         Some(Rect::new(
             self.screen_width as i32 - self.screen_width as i32 / 5,
@@ -106,3 +295,261 @@ impl GameComponent for Map {
         RenderPrecedence::Map
     }
 }
+
+/// Mapping between world positions and pixels of a top down view centred on
+/// a point, either north up or turned so `dir` points up
+pub struct MapView {
+    center: Vec2f,
+    /// World directions of the view's x and y axes
+    right: Vec2f,
+    down: Vec2f,
+    zoom: f32,
+    half_width: f32,
+    half_height: f32,
+}
+
+impl MapView {
+    pub fn new(center: Vec2f, dir: Vec2f, rotating: bool, zoom: f32, width: i32, height: i32) -> MapView {
+        let (right, down) = if rotating {
+            (Vec2f::new(-dir.y(), dir.x()), -dir)
+        } else {
+            (Vec2f::new(1.0, 0.0), Vec2f::new(0.0, 1.0))
+        };
+
+        MapView {
+            center,
+            right,
+            down,
+            zoom,
+            half_width: width as f32 / 2.0,
+            half_height: height as f32 / 2.0,
+        }
+    }
+
+    /// Pixels per tile
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn to_world(&self, x: f32, y: f32) -> Vec2f {
+        self.center
+            + self.right * ((x - self.half_width) / self.zoom)
+            + self.down * ((y - self.half_height) / self.zoom)
+    }
+
+    pub fn to_screen(&self, world_pos: Vec2f) -> (f32, f32) {
+        let offset = world_pos - self.center;
+
+        (
+            self.half_width + offset.dot(&self.right) * self.zoom,
+            self.half_height + offset.dot(&self.down) * self.zoom,
+        )
+    }
+}
+
+/// How a fullscreen map, like the automap or the editor's grid, is panned
+/// and zoomed: the arrow keys pan it, the +/- keys and the mouse wheel zoom
+/// it and dragging moves it along with the mouse. The owner keeps the centre.
+pub struct PanZoom {
+    /// Pixels per tile
+    zoom: f32,
+    min_zoom: f32,
+    max_zoom: f32,
+    /// Arrow keys held down
+    pan_keys: Vec<Keycode>,
+}
+
+impl PanZoom {
+    pub fn new(min_zoom: f32, max_zoom: f32) -> PanZoom {
+        PanZoom {
+            zoom: DEFAULT_PAN_ZOOM.max(min_zoom).min(max_zoom),
+            min_zoom,
+            max_zoom,
+            pan_keys: vec![],
+        }
+    }
+
+    /// Pixels per tile
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).max(self.min_zoom).min(self.max_zoom);
+    }
+
+    pub fn is_pan_key(keycode: Keycode) -> bool {
+        match keycode {
+            Keycode::Left | Keycode::Right | Keycode::Up | Keycode::Down => true,
+            _ => false,
+        }
+    }
+
+    /// Zooms on +/- and starts panning on the arrow keys, other keys are
+    /// ignored
+    pub fn key_down(&mut self, keycode: Keycode) {
+        match keycode {
+            Keycode::Equals | Keycode::KpPlus => self.zoom_by(ZOOM_STEP),
+            Keycode::Minus | Keycode::KpMinus => self.zoom_by(1.0 / ZOOM_STEP),
+            _ if PanZoom::is_pan_key(keycode) && !self.pan_keys.contains(&keycode) => self.pan_keys.push(keycode),
+            _ => (),
+        }
+    }
+
+    pub fn key_up(&mut self, keycode: Keycode) {
+        self.pan_keys.retain(|key| *key != keycode);
+    }
+
+    /// Lets go of the arrow keys, for when the map closes
+    pub fn release_keys(&mut self) {
+        self.pan_keys.clear();
+    }
+
+    pub fn wheel(&mut self, notches: i32) {
+        self.zoom_by(ZOOM_STEP.powi(notches));
+    }
+
+    /// Where the arrow keys held down move `center` in `dt` µs
+    pub fn pan(&self, center: Vec2f, dt: u32) -> Vec2f {
+        let mut pan = Vec2f::new(0.0, 0.0);
+        for key in self.pan_keys.iter() {
+            pan = pan
+                + match key {
+                    Keycode::Left => Vec2f::new(-1.0, 0.0),
+                    Keycode::Right => Vec2f::new(1.0, 0.0),
+                    Keycode::Up => Vec2f::new(0.0, -1.0),
+                    _ => Vec2f::new(0.0, 1.0),
+                };
+        }
+        let dt_s = dt as f32 / 1_000_000.0;
+
+        center + pan * (PAN_SPEED * dt_s / self.zoom)
+    }
+
+    /// Where dragging the mouse by `xrel`, `yrel` pixels moves `center`, so
+    /// the map follows the mouse
+    pub fn drag(&self, center: Vec2f, xrel: i32, yrel: i32) -> Vec2f {
+        center - Vec2f::new(xrel as f32, yrel as f32) * (1.0 / self.zoom)
+    }
+}
+
+/// Calls `plot` for every pixel on the line between the two points
+pub fn for_line_pixels<F>(from: (f32, f32), to: (f32, f32), mut plot: F)
+where
+    F: FnMut(i32, i32),
+{
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as i32;
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        plot((from.0 + dx * t).floor() as i32, (from.1 + dy * t).floor() as i32);
+    }
+}
+
+/// Colour of the map at a world position, as the minimap and automap draw it.
+/// Only explored tiles are shown, unless `explored` is None.
+pub fn tile_color(
+    tile_map: &TileMap,
+    camera_frame: &CameraFrame,
+    explored: Option<&ExploredTiles>,
+    world_pos: Vec2f,
+    zoom: f32,
+) -> [u8; 3] {
+    let map_index = match tile_map.index_at(world_pos) {
+        Some(map_index) => map_index,
+        None => return OUTSIDE_COLOR,
+    };
+    let (tile_x, tile_y) = (world_pos.x().floor(), world_pos.y().floor());
+    if !explored.map_or(true, |explored| explored.is_explored(map_index)) {
+        return OUTSIDE_COLOR;
+    }
+
+    let tile = tile_map.tiles()[map_index];
+    let wall_color = tile_swatch_color(camera_frame, tile);
+    if tile == 0 {
+        FLOOR_COLOR
+    } else if let Some(thin_wall) = thin_wall(tile) {
+        let offset = match thin_wall.axis {
+            ThinWallAxis::X => world_pos.x() - tile_x,
+            ThinWallAxis::Y => world_pos.y() - tile_y,
+        };
+        // At least a pixel wide at any zoom
+        if (offset - thin_wall.offset).abs() * zoom < 1.0 {
+            wall_color
+        } else {
+            FLOOR_COLOR
+        }
+    } else if tile_heights(tile).is_opaque() {
+        wall_color
+    } else {
+        // Steps, windows and the like can be seen past
+        mix(FLOOR_COLOR, wall_color, 0.5)
+    }
+}
+
+/// The colour a whole tile stands for, its texture's average colour if known
+pub fn tile_swatch_color(camera_frame: &CameraFrame, tile: u16) -> [u8; 3] {
+    if tile == 0 {
+        return FLOOR_COLOR;
+    }
+
+    *camera_frame.tile_colors.get(&tile).unwrap_or(&UNKNOWN_WALL_COLOR)
+}
+
+/// Calls `plot` for the pixels of the player's dot and a line showing which
+/// way they face
+pub fn plot_player<F>(view: &MapView, pos: Vec2f, dir: Vec2f, mut plot: F)
+where
+    F: FnMut(i32, i32),
+{
+    let player = view.to_screen(pos);
+    let facing = view.to_screen(pos + dir * (6.0 / view.zoom()).max(1.0));
+    for_line_pixels(player, facing, &mut plot);
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            plot(player.0.round() as i32 + dx, player.1.round() as i32 + dy);
+        }
+    }
+}
+
+pub fn thing_icon(kind: ThingKind) -> ([u8; 5], [u8; 3]) {
+    match kind {
+        ThingKind::Door => (DOOR_ICON, DOOR_COLOR),
+        ThingKind::Key => (KEY_ICON, KEY_COLOR),
+        ThingKind::Item => (ITEM_ICON, ITEM_COLOR),
+        ThingKind::Enemy => (ENEMY_ICON, ENEMY_COLOR),
+    }
+}
+
+/// Calls `plot` for the pixels of an icon centred on the point, each icon
+/// pixel `scale` pixels wide
+pub fn plot_icon<F>(center: (f32, f32), icon: &[u8; 5], scale: i32, mut plot: F)
+where
+    F: FnMut(i32, i32),
+{
+    let left = center.0.round() as i32 - ICON_SIZE * scale / 2;
+    let top = center.1.round() as i32 - ICON_SIZE * scale / 2;
+    for (row, bits) in icon.iter().enumerate() {
+        for column in 0..ICON_SIZE {
+            if bits & (1 << (ICON_SIZE - 1 - column)) == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    plot(left + column * scale + dx, top + row as i32 * scale + dy);
+                }
+            }
+        }
+    }
+}
+
+/// Blends colour `a` towards `b` by `t`, 0.0 to 1.0
+pub fn mix(a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+    [channel(a[0], b[0]), channel(a[1], b[1]), channel(a[2], b[2])]
+}
+
+fn clamp_zoom(zoom: f32) -> f32 {
+    zoom.max(MIN_ZOOM).min(MAX_ZOOM)
+}
@@ -0,0 +1,410 @@
+use crate::map;
+use crate::tile_map::TileMap;
+use crate::vector::Vec2f;
+
+/// Positions are clamped this far from the origin before working out tiles,
+/// so the tile arithmetic can't overflow
+const MAX_COORD: f32 = 1.0e9;
+
+/// Where a ray cast with `TileMap::raycast` stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// Where the ray entered the tile
+    pub pos: Vec2f,
+    pub column: usize,
+    pub row: usize,
+    pub tile: u16,
+    /// Distance from the ray's origin to `pos`
+    pub dst: f32,
+}
+
+/// Spatial queries over the tile grid. World positions are in tiles, with
+/// tile (column, row) covering x from column to column + 1 and y from row to
+/// row + 1. Everything outside the map gives None instead of panicking.
+impl TileMap {
+    /// Index into `tiles` of the tile in the column and row
+    pub fn index(&self, column: usize, row: usize) -> Option<usize> {
+        if column >= self.width() || row >= self.height() {
+            return None;
+        }
+
+        Some(row * self.width() + column)
+    }
+
+    pub fn tile(&self, column: usize, row: usize) -> Option<u16> {
+        self.index(column, row).map(|index| self.tiles()[index])
+    }
+
+    /// Column and row of the tile a world position is in
+    pub fn tile_coords(&self, pos: Vec2f) -> Option<(usize, usize)> {
+        // Written so NaN coordinates are outside too
+        let within = |coord: f32, len: usize| coord >= 0.0 && coord < len as f32;
+        if !within(pos.x(), self.width()) || !within(pos.y(), self.height()) {
+            return None;
+        }
+
+        Some((pos.x() as usize, pos.y() as usize))
+    }
+
+    /// Index into `tiles` of the tile a world position is in
+    pub fn index_at(&self, pos: Vec2f) -> Option<usize> {
+        let (column, row) = self.tile_coords(pos)?;
+        self.index(column, row)
+    }
+
+    pub fn tile_at(&self, pos: Vec2f) -> Option<u16> {
+        self.index_at(pos).map(|index| self.tiles()[index])
+    }
+
+    /// Whether the player can stand on the tile, false outside the map
+    pub fn is_walkable(&self, column: usize, row: usize) -> bool {
+        self.tile(column, row) == Some(0)
+    }
+
+    /// Walks the grid from `from` along `dir` until reaching a tile `hits`
+    /// accepts, the tile `from` is in included. None if the ray leaves the
+    /// map or goes further than `max_dst` first.
+    pub fn raycast<F>(&self, from: Vec2f, dir: Vec2f, max_dst: f32, hits: F) -> Option<RayHit>
+    where
+        F: Fn(u16) -> bool,
+    {
+        let len = dir.len();
+        if len == 0.0 {
+            return None;
+        }
+        let dir = dir / len;
+
+        let (mut column, mut row) = (from.x().floor() as i64, from.y().floor() as i64);
+        let (step_column, step_row) = (if dir.x() > 0.0 { 1 } else { -1 }, if dir.y() > 0.0 { 1 } else { -1 });
+        // Distance along the ray between two vertical and two horizontal grid lines
+        let delta_x = if dir.x() == 0.0 { f32::INFINITY } else { (1.0 / dir.x()).abs() };
+        let delta_y = if dir.y() == 0.0 { f32::INFINITY } else { (1.0 / dir.y()).abs() };
+        // Distance along the ray to the next vertical and horizontal grid line
+        let mut next_x = if dir.x() > 0.0 {
+            (column as f32 + 1.0 - from.x()) * delta_x
+        } else {
+            (from.x() - column as f32) * delta_x
+        };
+        let mut next_y = if dir.y() > 0.0 {
+            (row as f32 + 1.0 - from.y()) * delta_y
+        } else {
+            (from.y() - row as f32) * delta_y
+        };
+
+        let mut dst = 0.0;
+        while dst <= max_dst {
+            if column < 0 || row < 0 {
+                return None;
+            }
+            let tile = self.tile(column as usize, row as usize)?;
+            if hits(tile) {
+                return Some(RayHit {
+                    pos: from + dir * dst,
+                    column: column as usize,
+                    row: row as usize,
+                    tile,
+                    dst,
+                });
+            }
+
+            if next_x < next_y {
+                dst = next_x;
+                next_x += delta_x;
+                column += step_column;
+            } else {
+                dst = next_y;
+                next_y += delta_y;
+                row += step_row;
+            }
+        }
+
+        None
+    }
+
+    /// Whether nothing opaque stands between the two positions. Thin walls
+    /// and walls with openings don't block the view.
+    pub fn line_of_sight(&self, from: Vec2f, to: Vec2f) -> bool {
+        let to_target = to - from;
+        let blocks_sight = |tile: u16| tile != 0 && map::thin_wall(tile).is_none() && map::tile_heights(tile).is_opaque();
+
+        match self.raycast(from, to_target, to_target.len(), blocks_sight) {
+            Some(hit) => hit.dst >= to_target.len(),
+            None => self.tile_coords(from).is_some() && self.tile_coords(to).is_some(),
+        }
+    }
+
+    /// Whether a circle overlaps any tile that isn't walkable, everything
+    /// outside the map counting as solid
+    pub fn overlaps_circle(&self, center: Vec2f, radius: f32) -> bool {
+        if self.tile_coords(center).is_none() {
+            return radius > 0.0;
+        }
+
+        // With the centre on the map, a ring of tiles around it is all of the
+        // outside the circle can reach first
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let first_column = ((center.x() - radius).floor() as i64).max(-1);
+        let last_column = ((center.x() + radius).floor() as i64).min(width);
+        let first_row = ((center.y() - radius).floor() as i64).max(-1);
+        let last_row = ((center.y() + radius).floor() as i64).min(height);
+
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let walkable = column >= 0 && row >= 0 && self.is_walkable(column as usize, row as usize);
+                if !walkable && circle_overlaps_tile(center, radius, column, row) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Columns and rows of the tiles a circle overlaps, row by row
+    pub fn tiles_touching(&self, center: Vec2f, radius: f32) -> Vec<(usize, usize)> {
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let first_column = ((center.x() - radius).floor() as i64).max(0);
+        let last_column = ((center.x() + radius).floor() as i64).min(width - 1);
+        let first_row = ((center.y() - radius).floor() as i64).max(0);
+        let last_row = ((center.y() + radius).floor() as i64).min(height - 1);
+
+        let mut tiles = vec![];
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                if circle_overlaps_tile(center, radius, column, row) {
+                    tiles.push((column as usize, row as usize));
+                }
+            }
+        }
+
+        tiles
+    }
+
+    /// Columns and rows of the tiles whose centres are within `radius` of
+    /// `center`, row by row
+    pub fn tiles_within(&self, center: Vec2f, radius: f32) -> Vec<(usize, usize)> {
+        let first_column = (center.x() - radius).floor().max(0.0) as usize;
+        let first_row = (center.y() - radius).floor().max(0.0) as usize;
+        let last_column = (center.x() + radius).floor().min(self.width() as f32 - 1.0);
+        let last_row = (center.y() + radius).floor().min(self.height() as f32 - 1.0);
+        if last_column < 0.0 || last_row < 0.0 {
+            return vec![];
+        }
+
+        let mut tiles = vec![];
+        for row in first_row..=last_row as usize {
+            for column in first_column..=last_column as usize {
+                let tile_center = Vec2f::new(column as f32 + 0.5, row as f32 + 0.5);
+                if (tile_center - center).sqr_len() <= radius * radius {
+                    tiles.push((column, row));
+                }
+            }
+        }
+
+        tiles
+    }
+
+    /// Column and row of the walkable tile whose centre is closest to `pos`,
+    /// walls in between or not. None if the map has no walkable tiles.
+    pub fn nearest_walkable(&self, pos: Vec2f) -> Option<(usize, usize)> {
+        if pos.x().is_nan() || pos.y().is_nan() {
+            return None;
+        }
+
+        let pos = Vec2f::new(
+            pos.x().max(-MAX_COORD).min(MAX_COORD),
+            pos.y().max(-MAX_COORD).min(MAX_COORD),
+        );
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let (center_column, center_row) = (pos.x().floor() as i64, pos.y().floor() as i64);
+        // Rings of tiles around the position's tile, from the first one that
+        // reaches the map to the one covering all of it
+        let first_ring = 0.max(-center_column).max(center_column - width + 1).max(-center_row).max(center_row - height + 1);
+        let last_ring = center_column.max(width - 1 - center_column).max(center_row).max(height - 1 - center_row);
+
+        let mut nearest: Option<((usize, usize), f32)> = None;
+        for ring in first_ring..=last_ring {
+            // No tile further out can be closer than the nearest one found
+            if let Some((_, dst)) = nearest {
+                if dst < ring as f32 - 1.0 {
+                    break;
+                }
+            }
+
+            for row in (center_row - ring).max(0)..=(center_row + ring).min(height - 1) {
+                let columns = if (row - center_row).abs() == ring {
+                    ((center_column - ring).max(0)..=(center_column + ring).min(width - 1)).collect()
+                } else {
+                    vec![center_column - ring, center_column + ring]
+                };
+
+                for column in columns {
+                    if column < 0 || !self.is_walkable(column as usize, row as usize) {
+                        continue;
+                    }
+
+                    let tile_center = Vec2f::new(column as f32 + 0.5, row as f32 + 0.5);
+                    let dst = (tile_center - pos).len();
+                    if nearest.map_or(true, |(_, nearest_dst)| dst < nearest_dst) {
+                        nearest = Some(((column as usize, row as usize), dst));
+                    }
+                }
+            }
+        }
+
+        nearest.map(|(tile, _)| tile)
+    }
+}
+
+fn circle_overlaps_tile(center: Vec2f, radius: f32, column: i64, row: i64) -> bool {
+    let closest = Vec2f::new(
+        center.x().max(column as f32).min(column as f32 + 1.0),
+        center.y().max(row as f32).min(row as f32 + 1.0),
+    );
+
+    (closest - center).sqr_len() < radius * radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 6x4 tiles of walls around a room, with a wall tile in the middle
+    /// and a window on the east side
+    fn room() -> TileMap {
+        #[rustfmt::skip]
+        let tiles = vec![
+            1, 1, 1, 1, 1, 1,
+            1, 0, 0, 0, 5, 1,
+            1, 0, 1, 0, 0, 1,
+            1, 1, 1, 1, 1, 1,
+        ];
+
+        TileMap::new(6, 4, tiles, None)
+    }
+
+    #[test]
+    fn lookups_outside_the_map_are_none() {
+        let tile_map = room();
+
+        assert_eq!(tile_map.index(6, 0), None);
+        assert_eq!(tile_map.index(0, 4), None);
+        assert_eq!(tile_map.tile(usize::max_value(), 0), None);
+        assert!(!tile_map.is_walkable(9, 9));
+        for pos in [
+            Vec2f::new(-0.1, 1.0),
+            Vec2f::new(6.0, 1.0),
+            Vec2f::new(1.0, 4.0),
+            Vec2f::new(f32::NAN, 1.0),
+            Vec2f::new(1.0, f32::INFINITY),
+        ]
+        .iter()
+        {
+            assert_eq!(tile_map.tile_coords(*pos), None);
+            assert_eq!(tile_map.index_at(*pos), None);
+            assert_eq!(tile_map.tile_at(*pos), None);
+        }
+
+        assert_eq!(tile_map.index(5, 3), Some(23));
+        assert_eq!(tile_map.index_at(Vec2f::new(1.5, 2.5)), Some(13));
+        assert_eq!(tile_map.tile_at(Vec2f::new(4.9, 1.1)), Some(5));
+        assert!(tile_map.is_walkable(3, 2));
+    }
+
+    #[test]
+    fn raycast_stops_at_accepted_tiles() {
+        let tile_map = room();
+        let from = Vec2f::new(1.5, 1.5);
+        let east = Vec2f::new(1.0, 0.0);
+
+        let hit = tile_map.raycast(from, east, 10.0, |tile| tile != 0).unwrap();
+        assert_eq!((hit.column, hit.row, hit.tile, hit.dst), (4, 1, 5, 2.5));
+        assert_eq!(hit.pos, Vec2f::new(4.0, 1.5));
+        let hit = tile_map.raycast(from, east * 3.0, 10.0, |tile| map::tile_heights(tile).is_opaque());
+        assert_eq!(hit.map(|hit| (hit.column, hit.dst)), Some((5, 3.5)));
+        let hit = tile_map.raycast(from, Vec2f::new(-1.0, 0.0), 10.0, |tile| tile != 0);
+        assert_eq!(hit.map(|hit| (hit.column, hit.dst)), Some((0, 0.5)));
+    }
+
+    #[test]
+    fn raycast_is_none_past_the_map_or_max_distance() {
+        let tile_map = room();
+        let from = Vec2f::new(1.5, 1.5);
+
+        assert_eq!(tile_map.raycast(from, Vec2f::new(1.0, 0.0), 1.0, |tile| tile != 0), None);
+        assert_eq!(tile_map.raycast(from, Vec2f::new(1.0, 1.0), 100.0, |_| false), None);
+        assert_eq!(tile_map.raycast(from, Vec2f::new(0.0, 0.0), 100.0, |_| true), None);
+        assert_eq!(tile_map.raycast(Vec2f::new(-5.0, 1.5), Vec2f::new(1.0, 0.0), 100.0, |_| true), None);
+    }
+
+    #[test]
+    fn line_of_sight_is_blocked_by_opaque_walls() {
+        let tile_map = room();
+        let from = Vec2f::new(1.5, 1.5);
+
+        assert!(tile_map.line_of_sight(from, Vec2f::new(3.5, 1.5)));
+        assert!(tile_map.line_of_sight(Vec2f::new(3.5, 1.5), Vec2f::new(4.5, 1.5)));
+        assert!(!tile_map.line_of_sight(from, Vec2f::new(3.5, 2.5)));
+        assert!(!tile_map.line_of_sight(from, Vec2f::new(10.0, 1.5)));
+        assert!(!tile_map.line_of_sight(Vec2f::new(-1.0, -1.0), from));
+    }
+
+    #[test]
+    fn circles_overlap_walls_and_the_outside() {
+        let tile_map = room();
+
+        assert!(!tile_map.overlaps_circle(Vec2f::new(1.5, 1.5), 0.4));
+        assert!(tile_map.overlaps_circle(Vec2f::new(1.5, 1.5), 0.6));
+        assert!(!tile_map.overlaps_circle(Vec2f::new(3.5, 1.5), 0.45));
+        assert!(tile_map.overlaps_circle(Vec2f::new(3.5, 1.5), 0.55));
+        assert!(tile_map.overlaps_circle(Vec2f::new(1.5, 1.5), f32::INFINITY));
+        assert!(tile_map.overlaps_circle(Vec2f::new(-2.0, 1.0), 0.1));
+        assert!(tile_map.overlaps_circle(Vec2f::new(1.0e30, 1.0), 0.1));
+    }
+
+    #[test]
+    fn tiles_within_are_clipped_to_the_map() {
+        let tile_map = room();
+
+        assert_eq!(
+            tile_map.tiles_within(Vec2f::new(1.5, 1.5), 1.0),
+            vec![(1, 0), (0, 1), (1, 1), (2, 1), (1, 2)]
+        );
+        assert_eq!(tile_map.tiles_within(Vec2f::new(1.5, 1.5), f32::INFINITY).len(), 24);
+        assert_eq!(tile_map.tiles_within(Vec2f::new(100.0, 100.0), 1.0), vec![]);
+        assert_eq!(tile_map.tiles_within(Vec2f::new(-100.0, -100.0), 1.0), vec![]);
+        assert_eq!(tile_map.tiles_within(Vec2f::new(f32::NAN, 1.0), 1.0), vec![]);
+    }
+
+    #[test]
+    fn tiles_touching_are_the_ones_a_circle_overlaps() {
+        let tile_map = room();
+
+        assert_eq!(tile_map.tiles_touching(Vec2f::new(1.5, 1.5), 0.25), vec![(1, 1)]);
+        assert_eq!(tile_map.tiles_touching(Vec2f::new(1.8, 1.5), 0.25), vec![(1, 1), (2, 1)]);
+        assert_eq!(tile_map.tiles_touching(Vec2f::new(0.1, 0.1), 0.25), vec![(0, 0)]);
+        assert_eq!(tile_map.tiles_touching(Vec2f::new(-1.0, 1.5), 0.25), vec![]);
+        assert_eq!(tile_map.tiles_touching(Vec2f::new(f32::NAN, 1.5), 0.25), vec![]);
+    }
+
+    #[test]
+    fn nearest_walkable_finds_the_closest_floor() {
+        let tile_map = room();
+
+        assert_eq!(tile_map.nearest_walkable(Vec2f::new(1.5, 1.5)), Some((1, 1)));
+        assert_eq!(tile_map.nearest_walkable(Vec2f::new(2.5, 2.5)), Some((2, 1)));
+        assert_eq!(tile_map.nearest_walkable(Vec2f::new(0.2, 3.5)), Some((1, 2)));
+        assert_eq!(tile_map.nearest_walkable(Vec2f::new(-100.0, 1.5)), Some((1, 1)));
+    }
+
+    #[test]
+    fn nearest_walkable_handles_far_and_invalid_positions() {
+        let tile_map = room();
+
+        assert!(tile_map.nearest_walkable(Vec2f::new(1.0e30, 1.0e30)).is_some());
+        assert!(tile_map.nearest_walkable(Vec2f::new(f32::INFINITY, f32::NEG_INFINITY)).is_some());
+        assert!(tile_map.nearest_walkable(Vec2f::new(-f32::MAX, 2.0)).is_some());
+        assert_eq!(tile_map.nearest_walkable(Vec2f::new(f32::NAN, 1.0)), None);
+        assert_eq!(TileMap::new(2, 2, vec![1; 4], None).nearest_walkable(Vec2f::new(0.5, 0.5)), None);
+    }
+}
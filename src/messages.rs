@@ -0,0 +1,139 @@
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::surface::Surface;
+
+use crate::events::GameEvent;
+use crate::font;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::game::GameOpts;
+use crate::render_precedence::RenderPrecedence;
+use crate::textures::TRANSPARENT_COLOR;
+
+const MESSAGE_DURATION_US: i64 = 4_000_000;
+const ERROR_DURATION_US: i64 = 10_000_000;
+const TEXT_SCALE: u32 = 2;
+const MARGIN: u32 = 4;
+
+struct Message {
+    text: String,
+    color: Color,
+    remaining_us: i64,
+}
+
+/// Short lived text messages shown in the top left corner of the screen
+pub struct Messages {
+    screen_width: u32,
+    messages: Vec<Message>,
+    messages_surface: Surface<'static>,
+    redraw_needed: bool,
+}
+
+impl Messages {
+    pub fn new(game_opts: &GameOpts) -> Messages {
+        let mut messages_surface = Surface::new(
+            game_opts.screen_width,
+            game_opts.screen_height,
+            PixelFormatEnum::RGB24,
+        )
+        .unwrap();
+        messages_surface
+            .set_color_key(true, TRANSPARENT_COLOR)
+            .expect("Failed setting messages color key");
+
+        Messages {
+            screen_width: game_opts.screen_width,
+            messages: vec![],
+            messages_surface,
+            redraw_needed: false,
+        }
+    }
+
+    pub fn show(&mut self, text: &str) {
+        println!("\n{}", text);
+        self.push(text, Color::RGB(255, 255, 255), MESSAGE_DURATION_US);
+    }
+
+    pub fn show_error(&mut self, text: &str) {
+        println!("\nError: {}", text);
+        self.push(text, Color::RGB(255, 80, 80), ERROR_DURATION_US);
+    }
+
+    fn push(&mut self, text: &str, color: Color, duration_us: i64) {
+        // Wrap long messages so they fit on the screen
+        let chars_per_line = (self.screen_width - 2 * MARGIN) / (font::text_width("  ", TEXT_SCALE) / 2);
+        let chars: Vec<char> = text.chars().collect();
+        for line in chars.chunks(chars_per_line.max(1) as usize) {
+            self.messages.push(Message {
+                text: line.iter().collect(),
+                color,
+                remaining_us: duration_us,
+            });
+        }
+
+        self.redraw_needed = true;
+    }
+}
+
+impl GameComponent for Messages {
+    fn update(&mut self, _: &Game, dt: u32) {
+        for message in self.messages.iter_mut() {
+            message.remaining_us -= dt as i64;
+        }
+
+        let message_count = self.messages.len();
+        self.messages.retain(|message| message.remaining_us > 0);
+        if self.messages.len() != message_count {
+            self.redraw_needed = true;
+        }
+    }
+
+    fn handle_game_event(&mut self, _: &Game, event: &GameEvent) {
+        if let GameEvent::Message(text) = event {
+            self.show(text);
+        }
+    }
+
+    fn draw(&mut self, _: &Game, _: u32) -> Option<&Surface> {
+        if self.messages.is_empty() {
+            return None;
+        }
+
+        if self.redraw_needed {
+            self.messages_surface
+                .fill_rect(None, TRANSPARENT_COLOR)
+                .expect("Failed clearing messages");
+
+            let line_height = font::text_height(TEXT_SCALE) + TEXT_SCALE * 2;
+            for (line, message) in self.messages.iter().enumerate() {
+                let y = (MARGIN + line as u32 * line_height) as i32;
+                let backdrop = Rect::new(
+                    0,
+                    y - TEXT_SCALE as i32,
+                    font::text_width(&message.text, TEXT_SCALE) + 2 * MARGIN,
+                    line_height,
+                );
+                self.messages_surface
+                    .fill_rect(Some(backdrop), Color::RGB(0, 0, 0))
+                    .expect("Failed drawing message backdrop");
+                font::draw_text(
+                    &mut self.messages_surface,
+                    MARGIN as i32,
+                    y,
+                    &message.text,
+                    TEXT_SCALE,
+                    message.color,
+                );
+            }
+
+            self.redraw_needed = false;
+        }
+
+        Some(&self.messages_surface)
+    }
+
+    fn render_precendce(&self) -> RenderPrecedence {
+        RenderPrecedence::Gui
+    }
+}
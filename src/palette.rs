@@ -0,0 +1,190 @@
+use std::fs;
+use std::path::Path;
+
+use crate::assets::AssetError;
+use crate::textures::Texel;
+
+/// Optional palette in the asset root, 256 RGB triplets like Wolfenstein 3D's
+/// and Doom's palettes
+pub const PALETTE_FILE_NAME: &'static str = "palette.pal";
+pub const PALETTE_SIZE: usize = 256;
+/// Number of colormap tables, from full brightness to fully fogged
+const LIGHT_LEVELS: usize = 32;
+/// Bits per channel of the quantization lookup table
+const LOOKUP_BITS: usize = 5;
+
+#[derive(Clone)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// A 6x7x6 colour cube followed by a few extra greys
+    pub fn generated() -> Palette {
+        let mut colors = Vec::with_capacity(PALETTE_SIZE);
+        for r in 0..6 {
+            for g in 0..7 {
+                for b in 0..6 {
+                    colors.push([r * 51, (g as u32 * 255 / 6) as u8, b * 51]);
+                }
+            }
+        }
+        for grey in 1..=(PALETTE_SIZE - colors.len()) as u8 {
+            let level = grey * 51 - 25;
+            colors.push([level, level, level]);
+        }
+
+        Palette { colors }
+    }
+
+    pub fn load(path: &Path) -> Result<Palette, AssetError> {
+        let bytes = fs::read(path).map_err(|err| AssetError::Io(path.to_path_buf(), err))?;
+        if bytes.len() != PALETTE_SIZE * 3 {
+            return Err(AssetError::Config(
+                path.to_path_buf(),
+                format!("expected {} bytes of RGB triplets", PALETTE_SIZE * 3),
+            ));
+        }
+
+        // VGA palettes, like the ones extracted from Wolfenstein 3D, only use
+        // 6 bits per channel
+        let scale = if bytes.iter().all(|channel| *channel < 64) { 4 } else { 1 };
+        let colors = bytes
+            .chunks(3)
+            .map(|rgb| [rgb[0] * scale, rgb[1] * scale, rgb[2] * scale])
+            .collect();
+
+        Ok(Palette { colors })
+    }
+
+    pub fn color(&self, index: u8) -> [u8; 3] {
+        self.colors[index as usize]
+    }
+
+    /// Index of the closest colour, by squared RGB distance
+    pub fn nearest(&self, rgb: [u8; 3]) -> u8 {
+        let sqr_dst = |color: &[u8; 3]| {
+            (0..3)
+                .map(|channel| (color[channel] as i32 - rgb[channel] as i32).pow(2))
+                .sum::<i32>()
+        };
+
+        self.colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, color)| sqr_dst(color))
+            .map(|(index, _)| index as u8)
+            .unwrap()
+    }
+
+    /// The palette with every colour moved towards `tint` by `strength`,
+    /// 0.0 to 1.0
+    pub fn tinted(&self, tint: [u8; 3], strength: f32) -> Palette {
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * strength).round() as u8;
+        let colors = self
+            .colors
+            .iter()
+            .map(|color| {
+                [
+                    lerp(color[0], tint[0]),
+                    lerp(color[1], tint[1]),
+                    lerp(color[2], tint[2]),
+                ]
+            })
+            .collect();
+
+        Palette { colors }
+    }
+}
+
+/// Precomputed tables for drawing with a palette: a lookup from true colour
+/// to the nearest palette index, and Doom style colormaps that darken palette
+/// indices for fog
+pub struct Colormap {
+    palette: Palette,
+    lookup: Vec<u8>,
+    /// `LIGHT_LEVELS` tables of `PALETTE_SIZE` entries
+    light_tables: Vec<u8>,
+}
+
+impl Colormap {
+    pub fn new(palette: Palette) -> Colormap {
+        let lookup_size = 1 << LOOKUP_BITS;
+        let to_channel = |value: usize| ((value << (8 - LOOKUP_BITS)) + (1 << (7 - LOOKUP_BITS))) as u8;
+        let mut lookup = Vec::with_capacity(lookup_size * lookup_size * lookup_size);
+        for r in 0..lookup_size {
+            for g in 0..lookup_size {
+                for b in 0..lookup_size {
+                    lookup.push(palette.nearest([to_channel(r), to_channel(g), to_channel(b)]));
+                }
+            }
+        }
+
+        // Same fog as true colour rendering, channels darkened by `fogging`
+        let mut light_tables = Vec::with_capacity(LIGHT_LEVELS * PALETTE_SIZE);
+        for level in 0..LIGHT_LEVELS {
+            let fogging = (level * 256 / LIGHT_LEVELS) as i32;
+            for index in 0..PALETTE_SIZE {
+                let color = palette.color(index as u8);
+                let fog = |channel: u8| (channel as i32 - fogging).max(0) as u8;
+                light_tables.push(palette.nearest([fog(color[0]), fog(color[1]), fog(color[2])]));
+            }
+        }
+
+        Colormap {
+            palette,
+            lookup,
+            light_tables,
+        }
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Nearest palette index of the texel's colour
+    pub fn quantize(&self, texel: Texel) -> u8 {
+        let channel = |value: u8| (value >> (8 - LOOKUP_BITS)) as usize;
+        let index = (channel(texel[0]) << (2 * LOOKUP_BITS)) | (channel(texel[1]) << LOOKUP_BITS) | channel(texel[2]);
+
+        self.lookup[index]
+    }
+
+    /// The palette index darkened by `fogging`, 0 to 255
+    pub fn fog(&self, index: u8, fogging: i32) -> u8 {
+        let level = (fogging.max(0) as usize * LIGHT_LEVELS / 256).min(LIGHT_LEVELS - 1);
+
+        self.light_tables[level * PALETTE_SIZE + index as usize]
+    }
+}
+
+/// Short palette tint, Doom style, e.g. red when hurt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteFlash {
+    Damage,
+    Pickup,
+}
+
+impl PaletteFlash {
+    pub fn tint(&self) -> [u8; 3] {
+        match self {
+            PaletteFlash::Damage => [255, 0, 0],
+            PaletteFlash::Pickup => [255, 215, 0],
+        }
+    }
+
+    /// Tint strength at the start of the flash, it fades out from there
+    pub fn strength(&self) -> f32 {
+        match self {
+            PaletteFlash::Damage => 0.5,
+            PaletteFlash::Pickup => 0.25,
+        }
+    }
+
+    pub fn duration_us(&self) -> u32 {
+        match self {
+            PaletteFlash::Damage => 400_000,
+            PaletteFlash::Pickup => 250_000,
+        }
+    }
+}
@@ -1,35 +1,115 @@
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
+use crate::events::GameEvent;
 use crate::game::Game;
 use crate::game::GameComponent;
+use crate::game::HeadBobOpts;
+use crate::palette::PaletteFlash;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
 use crate::vector::Vec2f;
 
+/// Eye height when standing, in wall heights
+const STANDING_EYE_HEIGHT: f32 = 0.5;
+const CROUCHING_EYE_HEIGHT: f32 = 0.3;
+/// How fast the eye moves between standing and crouching height, units/s
+const CROUCH_SPEED: f32 = 2.0;
+const JUMP_SPEED: f32 = 1.6;
+const GRAVITY: f32 = 6.0;
+/// Pitch is the horizon's offset from the middle of the view, as a fraction
+/// of the view height
+const MAX_PITCH: f32 = 0.5;
+const PITCH_SPEED: f32 = 1.0;
+/// How fast head bob fades in and out when starting and stopping, 1/s
+const BOB_FADE_SPEED: f32 = 4.0;
+/// The player is a circle this wide as far as walls are concerned, in tiles
+pub const PLAYER_RADIUS: f32 = 0.2;
+/// Halvings of a blocked step to find how close to the wall it gets
+const COLLISION_STEPS: u32 = 8;
+pub const MAX_HEALTH: u32 = 100;
+/// Health an item restores
+const ITEM_HEALTH: u32 = 10;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Direction {
     Forward,
     Backward,
     Left,
     Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DirBuf {
+    Move,
+    Rotate,
+    Look,
+}
+
+/// What the player picked up, carried from level to level
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Inventory {
+    pub keys: u32,
+    pub treasure: u32,
 }
 
 pub struct Player {
     pos: Vec2f,
     dir: Vec2f,
+    pitch: f32,
+
+    crouch_height: f32,
+    jump_height: f32,
+    vertical_speed: f32,
+    crouching: bool,
+
+    head_bob_opts: HeadBobOpts,
+    distance_walked: f32,
+    bob_strength: f32,
+
     move_dir_buf: Vec<Direction>,
     rotate_dir_buf: Vec<Direction>,
+    look_dir_buf: Vec<Direction>,
+
+    health: u32,
+    inventory: Inventory,
+    use_requested: bool,
 }
 
 impl Player {
-    pub fn new(pos: Vec2f) -> Player {
+    pub fn new(pos: Vec2f, head_bob_opts: HeadBobOpts) -> Player {
         Player {
             pos,
             dir: Vec2f::new(-1.0, 0.0),
+            pitch: 0.0,
+
+            crouch_height: STANDING_EYE_HEIGHT,
+            jump_height: 0.0,
+            vertical_speed: 0.0,
+            crouching: false,
+
+            head_bob_opts,
+            distance_walked: 0.0,
+            bob_strength: 0.0,
+
             move_dir_buf: Vec::new(),
             rotate_dir_buf: Vec::new(),
+            look_dir_buf: Vec::new(),
+
+            health: MAX_HEALTH,
+            inventory: Inventory::default(),
+            use_requested: false,
         }
     }
 
+    /// Keeps the health and inventory the player had on the previous level
+    pub fn carry_over(&mut self, previous: &Player) {
+        self.health = previous.health;
+        self.inventory = previous.inventory;
+    }
+
     pub fn pos(&self) -> &Vec2f {
         &self.pos
     }
@@ -38,41 +118,127 @@ impl Player {
         &self.dir
     }
 
-    fn add_dir(&mut self, use_move_dir_buf: bool, dir: Direction) {
-        let buf = if use_move_dir_buf {
-            &mut self.move_dir_buf
+    /// Moves the player without walking there, e.g. when loading a game
+    pub fn place(&mut self, pos: Vec2f, dir: Vec2f) {
+        self.pos = pos;
+        self.dir = dir.normalize();
+    }
+
+    /// Step to the neighbouring tile the player faces, along the axis they
+    /// face the most
+    pub fn facing(&self) -> (isize, isize) {
+        if self.dir.x().abs() >= self.dir.y().abs() {
+            (self.dir.x().signum() as isize, 0)
+        } else {
+            (0, self.dir.y().signum() as isize)
+        }
+    }
+
+    pub fn health(&self) -> u32 {
+        self.health
+    }
+
+    pub fn inventory(&self) -> &Inventory {
+        &self.inventory
+    }
+
+    /// Doors and enemies can't be picked up, nothing happens for those
+    pub fn pick_up(&mut self, kind: ThingKind) {
+        match kind {
+            ThingKind::Key => self.inventory.keys += 1,
+            ThingKind::Item => {
+                self.inventory.treasure += 1;
+                self.health = (self.health + ITEM_HEALTH).min(MAX_HEALTH);
+            }
+            ThingKind::Door | ThingKind::Enemy => (),
+        }
+    }
+
+    /// Height of the player's eyes above the floor, in wall heights
+    pub fn eye_height(&self) -> f32 {
+        self.crouch_height + self.jump_height
+    }
+
+    /// Height the camera looks from, i.e. eye height with head bob applied
+    pub fn view_height(&self) -> f32 {
+        self.eye_height() + self.head_bob()
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    /// Vertical eye offset caused by walking
+    pub fn head_bob(&self) -> f32 {
+        if !self.head_bob_opts.enabled {
+            return 0.0;
+        }
+
+        self.head_bob_opts.amplitude * self.bob_strength * self.bob_phase().sin()
+    }
+
+    /// How far walking moves the view weapon from where it's held, sideways
+    /// and down, in wall heights. The weapon swings sideways once per two
+    /// head bobs, dipping at each side.
+    pub fn weapon_sway(&self) -> Vec2f {
+        if !self.head_bob_opts.enabled {
+            return Vec2f::new(0.0, 0.0);
+        }
+
+        let sway = self.head_bob_opts.weapon_sway * self.bob_strength;
+        let half_phase = self.bob_phase() / 2.0;
+        Vec2f::new(sway * half_phase.sin(), sway * half_phase.cos().abs())
+    }
+
+    fn bob_phase(&self) -> f32 {
+        self.distance_walked * self.head_bob_opts.frequency * 2.0 * std::f32::consts::PI
+    }
+
+    fn update_head_bob(&mut self, last_pos: Vec2f, dt_s: f32) {
+        let walked = (self.pos - last_pos).len();
+        self.distance_walked += walked;
+
+        // Bobbing mid-jump looks odd, so only walking on the ground counts
+        let target_strength = if walked > 0.0 && self.jump_height == 0.0 {
+            1.0
         } else {
-            &mut self.rotate_dir_buf
+            0.0
         };
-        if let Some(direction) = buf.last() {
+        let max_step = BOB_FADE_SPEED * dt_s;
+        self.bob_strength += (target_strength - self.bob_strength).max(-max_step).min(max_step);
+    }
+
+    fn dir_buf(&mut self, dir_buf: DirBuf) -> &mut Vec<Direction> {
+        match dir_buf {
+            DirBuf::Move => &mut self.move_dir_buf,
+            DirBuf::Rotate => &mut self.rotate_dir_buf,
+            DirBuf::Look => &mut self.look_dir_buf,
+        }
+    }
+
+    fn add_dir(&mut self, dir_buf: DirBuf, dir: Direction) {
+        if let Some(direction) = self.dir_buf(dir_buf).last() {
             if *direction == dir {
                 return;
             }
         }
 
-        self.remove_dir(use_move_dir_buf, dir);
-        // We need to shadow the first buf as the line above borrows self mutably for a second time
-        let buf = if use_move_dir_buf {
-            &mut self.move_dir_buf
-        } else {
-            &mut self.rotate_dir_buf
-        };
-        buf.push(dir);
+        self.remove_dir(dir_buf, dir);
+        self.dir_buf(dir_buf).push(dir);
     }
 
-    fn remove_dir(&mut self, use_move_dir_buf: bool, dir: Direction) {
-        let buf = if use_move_dir_buf {
-            &mut self.move_dir_buf
-        } else {
-            &mut self.rotate_dir_buf
-        };
+    fn remove_dir(&mut self, dir_buf: DirBuf, dir: Direction) {
+        let buf = self.dir_buf(dir_buf);
         *buf = buf.iter().filter(|d| **d != dir).copied().collect();
     }
-}
 
-impl GameComponent for Player {
-    fn update(&mut self, _: &Game, dt: u32) {
-        let dt_s = dt as f32 / 1_000_000.0;
+    fn jump(&mut self) {
+        if self.jump_height == 0.0 {
+            self.vertical_speed = JUMP_SPEED;
+        }
+    }
+
+    fn update_movement(&mut self, tile_map: &TileMap, dt_s: f32) {
         if let Some(dir) = self.rotate_dir_buf.last() {
             match dir {
                 Direction::Left => self.dir = self.dir.rotate(2.0 / 0.016 * dt_s),
@@ -82,15 +248,108 @@ impl GameComponent for Player {
         }
 
         if let Some(dir) = self.move_dir_buf.last() {
+            let step = match dir {
+                Direction::Forward => self.dir * 0.15 / 0.016 * dt_s,
+                Direction::Backward => self.dir * -0.15 / 0.016 * dt_s,
+                Direction::Left => self.dir.rotate(90.0) * 0.15 / 0.016 * dt_s,
+                Direction::Right => self.dir.rotate(-90.0) * 0.15 / 0.016 * dt_s,
+                _ => return,
+            };
+            self.pos = Player::collide(tile_map, self.pos, step);
+        }
+    }
+
+    /// Where a step from `from` ends with walls in the way. Moves along one
+    /// axis at a time so the player slides along walls instead of sticking to
+    /// them. A player already inside a wall, e.g. one spawned there, can move
+    /// freely until they're out.
+    fn collide(tile_map: &TileMap, from: Vec2f, step: Vec2f) -> Vec2f {
+        if tile_map.overlaps_circle(from, PLAYER_RADIUS) {
+            return from + step;
+        }
+
+        let mut pos = from;
+        for axis_step in [Vec2f::new(step.x(), 0.0), Vec2f::new(0.0, step.y())].iter() {
+            if !tile_map.overlaps_circle(pos + *axis_step, PLAYER_RADIUS) {
+                pos = pos + *axis_step;
+                continue;
+            }
+
+            // Closes in on the wall
+            let (mut free, mut blocked) = (0.0, 1.0);
+            for _ in 0..COLLISION_STEPS {
+                let middle = (free + blocked) / 2.0;
+                if tile_map.overlaps_circle(pos + *axis_step * middle, PLAYER_RADIUS) {
+                    blocked = middle;
+                } else {
+                    free = middle;
+                }
+            }
+            pos = pos + *axis_step * free;
+        }
+
+        pos
+    }
+
+    fn update_look(&mut self, dt_s: f32) {
+        if let Some(dir) = self.look_dir_buf.last() {
             match dir {
-                Direction::Forward => self.pos = self.pos + (self.dir * 0.15 / 0.016 * dt_s),
-                Direction::Backward => self.pos = self.pos - (self.dir * 0.15 / 0.016 * dt_s),
-                Direction::Left => self.pos = self.pos + (self.dir.rotate(90.0) * 0.15 / 0.016 * dt_s),
-                Direction::Right => self.pos = self.pos + (self.dir.rotate(-90.0) * 0.15 / 0.016 * dt_s),
+                Direction::Up => self.pitch += PITCH_SPEED * dt_s,
+                Direction::Down => self.pitch -= PITCH_SPEED * dt_s,
+                _ => (),
             };
+            self.pitch = self.pitch.max(-MAX_PITCH).min(MAX_PITCH);
+        }
+    }
+
+    fn update_vertical(&mut self, dt_s: f32) {
+        if self.jump_height > 0.0 || self.vertical_speed > 0.0 {
+            self.jump_height += self.vertical_speed * dt_s;
+            self.vertical_speed -= GRAVITY * dt_s;
+            if self.jump_height <= 0.0 {
+                self.jump_height = 0.0;
+                self.vertical_speed = 0.0;
+            }
+        }
+
+        let target_height = if self.crouching {
+            CROUCHING_EYE_HEIGHT
+        } else {
+            STANDING_EYE_HEIGHT
+        };
+        let max_step = CROUCH_SPEED * dt_s;
+        let step = (target_height - self.crouch_height).max(-max_step).min(max_step);
+        self.crouch_height += step;
+    }
+}
+
+impl GameComponent for Player {
+    fn handle_game_event(&mut self, game: &Game, event: &GameEvent) {
+        if let GameEvent::SetHealth(health) = event {
+            let health = (*health).min(MAX_HEALTH);
+            if health < self.health {
+                game.flash_palette(PaletteFlash::Damage);
+            }
+            self.health = health;
         }
     }
 
+    fn update(&mut self, game: &Game, dt: u32) {
+        let dt_s = dt as f32 / 1_000_000.0;
+        let last_pos = self.pos;
+        let use_requested = std::mem::take(&mut self.use_requested);
+        if !game.movement_paused() {
+            self.update_movement(&game.tile_map(), dt_s);
+            self.update_look(dt_s);
+            if use_requested {
+                game.post_event(GameEvent::Use);
+            }
+        }
+
+        self.update_vertical(dt_s);
+        self.update_head_bob(last_pos, dt_s);
+    }
+
     fn handle_event(&mut self, event: Event) -> Option<Event> {
         match event {
             Event::KeyDown {
@@ -98,28 +357,49 @@ impl GameComponent for Player {
                 ..
             } => match keycode {
                 Keycode::W => {
-                    self.add_dir(true, Direction::Forward);
+                    self.add_dir(DirBuf::Move, Direction::Forward);
                     None
                 }
                 Keycode::S => {
-                    self.add_dir(true, Direction::Backward);
+                    self.add_dir(DirBuf::Move, Direction::Backward);
                     None
                 }
                 Keycode::Q => {
-                    self.add_dir(true, Direction::Left);
+                    self.add_dir(DirBuf::Move, Direction::Left);
                     None
                 }
                 Keycode::E => {
-                    self.add_dir(true, Direction::Right);
+                    self.add_dir(DirBuf::Move, Direction::Right);
                     None
                 }
 
                 Keycode::A => {
-                    self.add_dir(false, Direction::Left);
+                    self.add_dir(DirBuf::Rotate, Direction::Left);
                     None
                 }
                 Keycode::D => {
-                    self.add_dir(false, Direction::Right);
+                    self.add_dir(DirBuf::Rotate, Direction::Right);
+                    None
+                }
+
+                Keycode::R => {
+                    self.add_dir(DirBuf::Look, Direction::Up);
+                    None
+                }
+                Keycode::F => {
+                    self.add_dir(DirBuf::Look, Direction::Down);
+                    None
+                }
+                Keycode::Space => {
+                    self.jump();
+                    None
+                }
+                Keycode::C => {
+                    self.crouching = true;
+                    None
+                }
+                Keycode::Return => {
+                    self.use_requested = true;
                     None
                 }
                 _ => Some(event),
@@ -129,28 +409,41 @@ impl GameComponent for Player {
                 ..
             } => match keycode {
                 Keycode::W => {
-                    self.remove_dir(true, Direction::Forward);
+                    self.remove_dir(DirBuf::Move, Direction::Forward);
                     None
                 }
                 Keycode::S => {
-                    self.remove_dir(true, Direction::Backward);
+                    self.remove_dir(DirBuf::Move, Direction::Backward);
                     None
                 }
                 Keycode::Q => {
-                    self.remove_dir(true, Direction::Left);
+                    self.remove_dir(DirBuf::Move, Direction::Left);
                     None
                 }
                 Keycode::E => {
-                    self.remove_dir(true, Direction::Right);
+                    self.remove_dir(DirBuf::Move, Direction::Right);
                     None
                 }
 
                 Keycode::A => {
-                    self.remove_dir(false, Direction::Left);
+                    self.remove_dir(DirBuf::Rotate, Direction::Left);
                     None
                 }
                 Keycode::D => {
-                    self.remove_dir(false, Direction::Right);
+                    self.remove_dir(DirBuf::Rotate, Direction::Right);
+                    None
+                }
+
+                Keycode::R => {
+                    self.remove_dir(DirBuf::Look, Direction::Up);
+                    None
+                }
+                Keycode::F => {
+                    self.remove_dir(DirBuf::Look, Direction::Down);
+                    None
+                }
+                Keycode::C => {
+                    self.crouching = false;
                     None
                 }
                 _ => Some(event),
@@ -159,3 +452,36 @@ impl GameComponent for Player {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collide_slides_along_walls() {
+        #[rustfmt::skip]
+        let tiles = vec![
+            1, 1, 1, 1,
+            1, 0, 0, 1,
+            1, 1, 1, 1,
+        ];
+        let tile_map = TileMap::new(4, 3, tiles, None);
+        let from = Vec2f::new(1.5, 1.5);
+
+        assert_eq!(
+            Player::collide(&tile_map, from, Vec2f::new(0.5, 0.0)),
+            Vec2f::new(2.0, 1.5)
+        );
+        let pos = Player::collide(&tile_map, from, Vec2f::new(0.2, 0.5));
+        assert_eq!(pos.x(), 1.7);
+        assert!(pos.y() <= 2.0 - PLAYER_RADIUS && pos.y() > 1.79);
+        let pos = Player::collide(&tile_map, from, Vec2f::new(-1.0, 0.0));
+        assert!(pos.x() >= 1.0 + PLAYER_RADIUS && pos.x() < 1.21);
+        // Nothing keeps a player stuck in a wall from getting out
+        let stuck = Vec2f::new(0.9, 1.5);
+        assert_eq!(
+            Player::collide(&tile_map, stuck, Vec2f::new(0.5, 0.0)),
+            Vec2f::new(1.4, 1.5)
+        );
+    }
+}
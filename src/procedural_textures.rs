@@ -0,0 +1,414 @@
+use std::f32::consts::PI;
+
+use crate::map::mix;
+use crate::textures::Texture;
+use crate::textures::TextureLoader;
+
+/// Procedural textures are registered under this prefix, e.g.
+/// "procedural/brick", for maps to reference with `tex` lines
+pub const NAME_PREFIX: &'static str = "procedural/";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// Staggered rows of bricks. `mortar` is the joint thickness as a
+    /// fraction of the texture size.
+    Brick { rows: u32, columns: u32, mortar: f32 },
+    /// Irregular stones, about `cells` across the texture
+    Stone { cells: u32 },
+    /// Vertical planks with wavy grain
+    Wood { planks: u32 },
+    /// Brushed panels with rivets in their corners, `panels` across
+    Metal { panels: u32 },
+    /// Fractal noise blending the two colours
+    Noise { scale: u32, octaves: u32 },
+    /// Crossed bars, `bars` across the texture, transparent in between
+    Grate { bars: u32 },
+    /// Streaky translucent pane in an opaque frame
+    Glass,
+}
+
+/// Everything a procedural texture is made from. The same parameters always
+/// give the same texture, texel for texel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureParams {
+    pub pattern: Pattern,
+    pub size: u32,
+    pub seed: u32,
+    pub color: [u8; 3],
+    /// Mortar, grain or rivet colour
+    pub accent: [u8; 3],
+}
+
+/// The textures `register` adds, without the name prefix
+pub fn library() -> Vec<(&'static str, TextureParams)> {
+    let params = |pattern, seed, color, accent| TextureParams {
+        pattern,
+        size: 64,
+        seed,
+        color,
+        accent,
+    };
+
+    vec![
+        (
+            "brick",
+            params(
+                Pattern::Brick {
+                    rows: 8,
+                    columns: 4,
+                    mortar: 1.0 / 64.0,
+                },
+                1,
+                [150, 60, 45],
+                [170, 165, 155],
+            ),
+        ),
+        ("stone", params(Pattern::Stone { cells: 5 }, 2, [120, 120, 115], [45, 45, 45])),
+        ("wood", params(Pattern::Wood { planks: 4 }, 3, [140, 95, 55], [85, 50, 25])),
+        ("metal", params(Pattern::Metal { panels: 2 }, 4, [130, 135, 140], [215, 215, 205])),
+        ("elevator", params(Pattern::Metal { panels: 1 }, 6, [70, 110, 85], [230, 200, 90])),
+        (
+            "noise",
+            params(Pattern::Noise { scale: 4, octaves: 4 }, 5, [70, 90, 60], [25, 35, 20]),
+        ),
+        ("grate", params(Pattern::Grate { bars: 4 }, 7, [95, 95, 100], [60, 55, 50])),
+        ("glass", params(Pattern::Glass, 8, [170, 210, 225], [80, 85, 90])),
+    ]
+}
+
+/// Adds the library's textures to the loader. Textures of the same name in
+/// the textures folder or asset pack take precedence.
+pub fn register(tex_loader: &mut TextureLoader) {
+    for (name, params) in library() {
+        let tex_name = format!("{}{}", NAME_PREFIX, name);
+        if tex_loader.texture(&tex_name).is_none() {
+            tex_loader.insert(&tex_name, generate(&params));
+        }
+    }
+}
+
+/// Generates a seamlessly tiling texture
+pub fn generate(params: &TextureParams) -> Texture {
+    let size = params.size.max(1);
+    let mut texels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let rgba = texel(params, (x as f32 + 0.5) / size as f32, (y as f32 + 0.5) / size as f32);
+            texels.extend_from_slice(&rgba);
+        }
+    }
+
+    Texture::from_rgba(size, size, &texels).expect("Generated texels don't match the texture size")
+}
+
+/// Colour and alpha at texture coordinates `u` and `v`, repeating every 1.0
+fn texel(params: &TextureParams, u: f32, v: f32) -> [u8; 4] {
+    let opaque = |rgb: [u8; 3]| [rgb[0], rgb[1], rgb[2], 255];
+
+    match params.pattern {
+        Pattern::Brick { rows, columns, mortar } => opaque(brick(params, u, v, rows, columns, mortar)),
+        Pattern::Stone { cells } => opaque(stone(params, u, v, cells)),
+        Pattern::Wood { planks } => opaque(wood(params, u, v, planks)),
+        Pattern::Metal { panels } => opaque(metal(params, u, v, panels)),
+        Pattern::Noise { scale, octaves } => {
+            opaque(mix(params.color, params.accent, fbm(u, v, scale, scale, octaves, params.seed)))
+        }
+        Pattern::Grate { bars } => grate(params, u, v, bars),
+        Pattern::Glass => glass(params, u, v),
+    }
+}
+
+fn brick(params: &TextureParams, u: f32, v: f32, rows: u32, columns: u32, mortar: f32) -> [u8; 3] {
+    let rows = rows.max(1);
+    let columns = columns.max(1);
+    let row = (v * rows as f32) as u32;
+    // Every other row is shifted by half a brick
+    let shifted_u = (u + if row % 2 == 1 { 0.5 / columns as f32 } else { 0.0 }).fract();
+    let column = (shifted_u * columns as f32) as u32;
+
+    let grit = fbm(u, v, 8, 8, 3, params.seed);
+    let is_mortar = (v * rows as f32).fract() < mortar * rows as f32
+        || (shifted_u * columns as f32).fract() < mortar * columns as f32;
+    if is_mortar {
+        return shade(params.accent, 0.8 + 0.3 * grit);
+    }
+
+    let brick_variation = hash_unit(column, row, params.seed);
+    shade(params.color, (0.8 + 0.3 * brick_variation) * (0.85 + 0.3 * grit))
+}
+
+fn stone(params: &TextureParams, u: f32, v: f32, cells: u32) -> [u8; 3] {
+    let (nearest, second_nearest, cell) = cellular(u, v, cells.max(1), params.seed);
+    let edge = second_nearest - nearest;
+    let grit = fbm(u, v, 8, 8, 4, params.seed);
+    if edge < 0.08 {
+        return shade(params.accent, 0.8 + 0.4 * grit);
+    }
+
+    let stone_variation = hash_unit(cell, cell.rotate_left(16), params.seed);
+    // Stones get darker towards their edges so they look rounded
+    let rounding = (edge * 4.0).min(1.0);
+    shade(params.color, (0.75 + 0.3 * stone_variation) * (0.7 + 0.3 * grit) * (0.75 + 0.25 * rounding))
+}
+
+fn wood(params: &TextureParams, u: f32, v: f32, planks: u32) -> [u8; 3] {
+    let planks = planks.max(1);
+    let plank = (u * planks as f32) as u32 % planks;
+    let plank_u = (u * planks as f32).fract();
+
+    // Whole numbers of waves across the texture keep the grain tiling
+    let warp = fbm(u, v, 2, 4, 3, params.seed.wrapping_add(plank)) * 4.0;
+    let grain = ((u * planks as f32 * 6.0 + warp + hash_unit(plank, 0, params.seed) * 10.0) * PI).sin() * 0.5 + 0.5;
+    let mut rgb = mix(params.color, params.accent, grain * 0.6);
+
+    let joint = hash_unit(plank, 1, params.seed);
+    if plank_u < 0.04 || (v - joint).abs() < 0.01 {
+        rgb = shade(rgb, 0.5);
+    }
+
+    rgb
+}
+
+fn metal(params: &TextureParams, u: f32, v: f32, panels: u32) -> [u8; 3] {
+    let panels = panels.max(1);
+    let panel_u = (u * panels as f32).fract();
+    let panel_v = (v * panels as f32).fract();
+
+    // Stretched noise looks brushed
+    let brushed = fbm(u, v, 2, 32, 3, params.seed);
+    let mut rgb = shade(params.color, 0.8 + 0.3 * brushed);
+
+    for (rivet_u, rivet_v) in [(0.1, 0.1), (0.9, 0.1), (0.1, 0.9), (0.9, 0.9)].iter() {
+        let sqr_dst = (panel_u - rivet_u).powi(2) + (panel_v - rivet_v).powi(2);
+        if sqr_dst < 0.04f32.powi(2) {
+            // Lit from the top left
+            let light = 1.0 - (panel_u - rivet_u + panel_v - rivet_v) * 8.0;
+            return shade(params.accent, light.max(0.4).min(1.2));
+        }
+    }
+
+    if panel_u < 0.03 || panel_v < 0.03 {
+        rgb = shade(rgb, 0.6);
+    } else if panel_u > 0.97 || panel_v > 0.97 {
+        rgb = shade(rgb, 1.2);
+    }
+
+    rgb
+}
+
+fn grate(params: &TextureParams, u: f32, v: f32, bars: u32) -> [u8; 4] {
+    const BAR_WIDTH: f32 = 0.25;
+
+    let bars = bars.max(1);
+    let bar_u = (u * bars as f32).fract();
+    let bar_v = (v * bars as f32).fract();
+    if bar_u >= BAR_WIDTH && bar_v >= BAR_WIDTH {
+        return [0, 0, 0, 0];
+    }
+
+    let rust = fbm(u, v, 4, 4, 3, params.seed);
+    let rgb = if bar_u < BAR_WIDTH && bar_v < BAR_WIDTH {
+        // Welded where the bars cross
+        shade(params.accent, 0.8 + 0.3 * rust)
+    } else {
+        // Bars are rounded, brightest along their middle
+        let across = if bar_u < BAR_WIDTH { bar_u } else { bar_v } / BAR_WIDTH;
+        let rounding = (across * PI).sin();
+        shade(mix(params.color, params.accent, rust * 0.5), 0.6 + 0.5 * rounding)
+    };
+    [rgb[0], rgb[1], rgb[2], 255]
+}
+
+fn glass(params: &TextureParams, u: f32, v: f32) -> [u8; 4] {
+    const FRAME_WIDTH: f32 = 0.04;
+
+    let (frame_u, frame_v) = (u.fract(), v.fract());
+    let edge = frame_u.min(1.0 - frame_u).min(frame_v).min(1.0 - frame_v);
+    if edge < FRAME_WIDTH {
+        let rgb = shade(params.accent, 0.8 + 0.3 * fbm(u, v, 8, 8, 2, params.seed));
+        return [rgb[0], rgb[1], rgb[2], 255];
+    }
+
+    // Diagonal streaks of reflected light
+    let streaks = fbm(u + v, u - v, 2, 2, 3, params.seed);
+    let rgb = shade(params.color, 0.85 + 0.3 * streaks);
+    [rgb[0], rgb[1], rgb[2], (60.0 + 70.0 * streaks) as u8]
+}
+
+fn shade(rgb: [u8; 3], factor: f32) -> [u8; 3] {
+    let channel = |value: u8| (value as f32 * factor).round().max(0.0).min(255.0) as u8;
+
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2])]
+}
+
+/// Integer hash of a lattice point, the source of all the randomness so the
+/// output doesn't depend on any random number generator's implementation
+fn hash(x: u32, y: u32, seed: u32) -> u32 {
+    let mut hash = x.wrapping_mul(0x8da6_b343) ^ y.wrapping_mul(0xd816_3841) ^ seed.wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+
+    hash ^ (hash >> 15)
+}
+
+/// `hash` mapped to 0.0 to 1.0
+fn hash_unit(x: u32, y: u32, seed: u32) -> f32 {
+    hash(x, y, seed) as f32 / u32::max_value() as f32
+}
+
+/// Smoothly interpolated random values on a lattice that repeats every
+/// `period_x` by `period_y` points, 0.0 to 1.0
+fn value_noise(x: f32, y: f32, period_x: u32, period_y: u32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+
+    let corner = |dx: i32, dy: i32| {
+        let lattice_x = (x0 as i32 + dx).rem_euclid(period_x as i32) as u32;
+        let lattice_y = (y0 as i32 + dy).rem_euclid(period_y as i32) as u32;
+        hash_unit(lattice_x, lattice_y, seed)
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    lerp(
+        lerp(corner(0, 0), corner(1, 0), tx),
+        lerp(corner(0, 1), corner(1, 1), tx),
+        ty,
+    )
+}
+
+/// Octaves of tiling value noise over texture coordinates, 0.0 to 1.0. The
+/// first octave has `scale_u` by `scale_v` lattice cells.
+fn fbm(u: f32, v: f32, scale_u: u32, scale_v: u32, octaves: u32, seed: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut total_amplitude = 0.0;
+    let mut amplitude = 1.0;
+    for octave in 0..octaves.max(1) {
+        let (period_u, period_v) = (scale_u.max(1) << octave, scale_v.max(1) << octave);
+        sum += amplitude
+            * value_noise(
+                u * period_u as f32,
+                v * period_v as f32,
+                period_u,
+                period_v,
+                seed.wrapping_add(octave),
+            );
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+    }
+
+    sum / total_amplitude
+}
+
+/// Worley noise that tiles every `cells` cells: distances to the nearest and
+/// second nearest feature points, in cells, and the nearest point's cell id
+fn cellular(u: f32, v: f32, cells: u32, seed: u32) -> (f32, f32, u32) {
+    let (x, y) = (u * cells as f32, v * cells as f32);
+    let (cell_x, cell_y) = (x.floor() as i32, y.floor() as i32);
+
+    let mut nearest = (f32::MAX, 0);
+    let mut second_nearest = f32::MAX;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let wrapped_x = (cell_x + dx).rem_euclid(cells as i32) as u32;
+            let wrapped_y = (cell_y + dy).rem_euclid(cells as i32) as u32;
+            let point_x = (cell_x + dx) as f32 + hash_unit(wrapped_x, wrapped_y, seed);
+            let point_y = (cell_y + dy) as f32 + hash_unit(wrapped_x, wrapped_y, seed.wrapping_add(1));
+
+            let dst = ((point_x - x).powi(2) + (point_y - y).powi(2)).sqrt();
+            if dst < nearest.0 {
+                second_nearest = nearest.0;
+                nearest = (dst, wrapped_y * cells + wrapped_x);
+            } else if dst < second_nearest {
+                second_nearest = dst;
+            }
+        }
+    }
+
+    (nearest.0, second_nearest, nearest.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map;
+
+    /// FNV-1a of the texels
+    fn checksum(tex: &Texture) -> u32 {
+        tex.to_rgba()
+            .iter()
+            .fold(0x811c9dc5u32, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+    }
+
+    #[test]
+    fn library_textures_are_stable() {
+        let checksums = [
+            ("brick", 0xbdd52c90),
+            ("stone", 0x0b928516),
+            ("wood", 0x778ae564),
+            ("metal", 0x190c6445),
+            ("elevator", 0xf45e7a0b),
+            ("noise", 0xd6c0f64d),
+            ("grate", 0xaf73719b),
+            ("glass", 0xe208b162),
+        ];
+
+        let library = library();
+        assert_eq!(library.len(), checksums.len());
+        for ((name, params), (expected_name, expected_checksum)) in library.iter().zip(checksums.iter()) {
+            assert_eq!(name, expected_name);
+            assert_eq!(checksum(&generate(params)), *expected_checksum, "{} changed", name);
+        }
+    }
+
+    #[test]
+    fn thin_wall_textures_are_see_through() {
+        let alphas = |name: &str| -> Vec<u8> {
+            let (_, params) = library().into_iter().find(|(library_name, _)| *library_name == name).unwrap();
+            generate(&params).to_rgba().chunks(4).map(|texel| texel[3]).collect()
+        };
+
+        for tile in [8, 9, 10].iter() {
+            let name = map::texture_name(*tile);
+            assert!(name.starts_with(NAME_PREFIX));
+            assert!(library().iter().any(|(library_name, _)| name[NAME_PREFIX.len()..] == **library_name));
+        }
+
+        let grate = alphas("grate");
+        assert!(grate.iter().all(|alpha| *alpha == 0 || *alpha == 255));
+        assert!(grate.contains(&0) && grate.contains(&255));
+        let glass = alphas("glass");
+        assert!(glass.iter().all(|alpha| *alpha > 0));
+        assert!(glass.iter().any(|alpha| *alpha < 128) && glass.contains(&255));
+    }
+
+    #[test]
+    fn library_textures_tile_seamlessly() {
+        for (name, params) in library() {
+            let size = params.size as f32;
+            let first = 0.5 / size;
+            // One texel past the last column or row, where the texture wraps
+            // around to the first one
+            let wrapped = 1.0 + first;
+            for texel_index in 0..params.size {
+                let across = (texel_index as f32 + 0.5) / size;
+                assert_eq!(
+                    texel(&params, wrapped, across),
+                    texel(&params, first, across),
+                    "{} doesn't wrap from column {} to column 0 in row {}",
+                    name,
+                    params.size - 1,
+                    texel_index
+                );
+                assert_eq!(
+                    texel(&params, across, wrapped),
+                    texel(&params, across, first),
+                    "{} doesn't wrap from row {} to row 0 in column {}",
+                    name,
+                    params.size - 1,
+                    texel_index
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::events::GameEvent;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::palette::PaletteFlash;
+use crate::player::PLAYER_RADIUS;
+use crate::tile_map::TileMap;
+use crate::vector::Vec2f;
+
+/// How long a push wall takes to slide one tile, µs
+const STEP_TIME: u32 = 600_000;
+/// How many tiles a push wall slides, if nothing is in the way
+const PUSH_DISTANCE: usize = 2;
+
+/// A push wall on its way
+struct SlidingWall {
+    tile: u16,
+    column: usize,
+    row: usize,
+    step: (isize, isize),
+    steps_left: usize,
+    step_time: u32,
+}
+
+/// Slides the push wall the player faces away when they use it, tile by
+/// tile so the raycaster always sees where it is. Every push wall hides a
+/// secret.
+pub struct PushWalls {
+    tile_map: Rc<RefCell<TileMap>>,
+    sliding: Vec<SlidingWall>,
+}
+
+impl PushWalls {
+    pub fn new(tile_map: Rc<RefCell<TileMap>>) -> PushWalls {
+        PushWalls {
+            tile_map,
+            sliding: vec![],
+        }
+    }
+
+    /// The tile next to (column, row) in the step direction, None outside the
+    /// map
+    fn next_tile(tile_map: &TileMap, column: usize, row: usize, step: (isize, isize)) -> Option<(usize, usize)> {
+        let column = column as isize + step.0;
+        let row = row as isize + step.1;
+        if column < 0 || row < 0 {
+            return None;
+        }
+
+        let (column, row) = (column as usize, row as usize);
+        tile_map.index(column, row).map(|_| (column, row))
+    }
+
+    /// Whether a wall can move onto the tile, which has to be empty and clear
+    /// of the player
+    fn can_enter(tile_map: &TileMap, column: usize, row: usize, player_pos: Vec2f) -> bool {
+        tile_map.is_walkable(column, row)
+            && !tile_map
+                .tiles_touching(player_pos, PLAYER_RADIUS)
+                .contains(&(column, row))
+    }
+
+    /// Starts sliding the push wall in front of a player at `player_pos`
+    /// facing `step`, returns whether there was one
+    fn push(&mut self, player_pos: Vec2f, step: (isize, isize)) -> bool {
+        let mut tile_map = self.tile_map.borrow_mut();
+        let (player_column, player_row) = match tile_map.tile_coords(player_pos) {
+            Some(player_tile) => player_tile,
+            None => return false,
+        };
+        let (column, row) = match PushWalls::next_tile(&tile_map, player_column, player_row, step) {
+            Some(wall) => wall,
+            None => return false,
+        };
+        let tile = match tile_map.tile(column, row) {
+            Some(tile) if tile != 0 && tile_map.push_walls().contains(&(column, row)) => tile,
+            _ => return false,
+        };
+        match PushWalls::next_tile(&tile_map, column, row, step) {
+            Some((next_column, next_row)) if PushWalls::can_enter(&tile_map, next_column, next_row, player_pos) => (),
+            _ => return false,
+        }
+
+        tile_map.take_push_wall(column, row);
+        self.sliding.push(SlidingWall {
+            tile,
+            column,
+            row,
+            step,
+            steps_left: PUSH_DISTANCE,
+            step_time: 0,
+        });
+
+        true
+    }
+
+    /// Moves the sliding walls on, keeping them out of the way of a player
+    /// at `player_pos`
+    fn slide(&mut self, dt: u32, player_pos: Vec2f) {
+        let mut tile_map = self.tile_map.borrow_mut();
+        for wall in self.sliding.iter_mut() {
+            wall.step_time += dt;
+            while wall.steps_left > 0 && wall.step_time >= STEP_TIME {
+                wall.step_time -= STEP_TIME;
+                let next = PushWalls::next_tile(&tile_map, wall.column, wall.row, wall.step);
+                let (column, row) = match next {
+                    Some((column, row)) if PushWalls::can_enter(&tile_map, column, row, player_pos) => (column, row),
+                    // Stops early when something got in the way
+                    _ => {
+                        wall.steps_left = 0;
+                        break;
+                    }
+                };
+
+                tile_map.set_tile(wall.column, wall.row, 0);
+                tile_map.set_tile(column, row, wall.tile);
+                wall.column = column;
+                wall.row = row;
+                wall.steps_left -= 1;
+            }
+        }
+        self.sliding.retain(|wall| wall.steps_left > 0);
+    }
+}
+
+impl GameComponent for PushWalls {
+    fn update(&mut self, game: &Game, dt: u32) {
+        self.slide(dt, *game.player().pos());
+    }
+
+    fn handle_game_event(&mut self, game: &Game, event: &GameEvent) {
+        if *event == GameEvent::Use && self.push(*game.player().pos(), game.player().facing()) {
+            game.level_stats_mut().secrets += 1;
+            game.flash_palette(PaletteFlash::Pickup);
+            game.show_message("You found a secret!");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 6x3 tiles of walls around a corridor, with push wall 5 in column 2
+    fn corridor() -> Rc<RefCell<TileMap>> {
+        #[rustfmt::skip]
+        let tiles = vec![
+            1, 1, 1, 1, 1, 1,
+            1, 0, 5, 0, 0, 1,
+            1, 1, 1, 1, 1, 1,
+        ];
+        let mut tile_map = TileMap::new(6, 3, tiles, None);
+        tile_map.add_push_wall(2, 1);
+
+        Rc::new(RefCell::new(tile_map))
+    }
+
+    #[test]
+    fn push_needs_a_push_wall_to_face() {
+        let tile_map = corridor();
+        let mut push_walls = PushWalls::new(Rc::clone(&tile_map));
+
+        assert!(!push_walls.push(Vec2f::new(1.5, 1.5), (0, 1)));
+        assert!(!push_walls.push(Vec2f::new(3.5, 1.5), (1, 0)));
+        assert!(!push_walls.push(Vec2f::new(-1.0, 1.5), (1, 0)));
+        assert!(push_walls.push(Vec2f::new(1.5, 1.5), (1, 0)));
+        assert!(tile_map.borrow().push_walls().is_empty());
+        assert!(!push_walls.push(Vec2f::new(1.5, 1.5), (1, 0)));
+    }
+
+    #[test]
+    fn slide_moves_the_wall_a_tile_per_step() {
+        let tile_map = corridor();
+        let mut push_walls = PushWalls::new(Rc::clone(&tile_map));
+        let player_pos = Vec2f::new(1.5, 1.5);
+        assert!(push_walls.push(player_pos, (1, 0)));
+
+        push_walls.slide(STEP_TIME - 1, player_pos);
+        assert_eq!(tile_map.borrow().tiles()[6..12], [1, 0, 5, 0, 0, 1]);
+        push_walls.slide(1, player_pos);
+        assert_eq!(tile_map.borrow().tiles()[6..12], [1, 0, 0, 5, 0, 1]);
+        push_walls.slide(10 * STEP_TIME, player_pos);
+        assert_eq!(tile_map.borrow().tiles()[6..12], [1, 0, 0, 0, 5, 1]);
+        assert!(push_walls.sliding.is_empty());
+    }
+
+    #[test]
+    fn slide_stops_short_of_the_player() {
+        let tile_map = corridor();
+        let mut push_walls = PushWalls::new(Rc::clone(&tile_map));
+        assert!(push_walls.push(Vec2f::new(1.5, 1.5), (1, 0)));
+
+        // The player ran around and stands where the wall is heading
+        push_walls.slide(10 * STEP_TIME, Vec2f::new(4.5, 1.5));
+        assert_eq!(tile_map.borrow().tiles()[6..12], [1, 0, 0, 5, 0, 1]);
+        assert!(push_walls.sliding.is_empty());
+    }
+}
@@ -27,21 +27,29 @@ impl Ray {
         Vec2f::new(x_dst, y_dst)
     }
 
-    fn to_partial_map_index(ray_dir: f32, ray_pos: f32) -> usize {
+    fn to_tile_coord(ray_dir: f32, ray_pos: f32) -> Option<usize> {
+        if ray_pos < 0.0 {
+            return None;
+        }
+
         if ray_pos == ray_pos.trunc() {
             if ray_dir > 0.0 {
-                ray_pos as usize
+                Some(ray_pos as usize)
             } else {
-                ray_pos as usize - 1
+                (ray_pos as usize).checked_sub(1)
             }
         } else {
-            ray_pos as usize
+            Some(ray_pos as usize)
         }
     }
 
-    pub fn to_map_index(&self, pos: &Vec2f, map_height: usize) -> usize {
-        Ray::to_partial_map_index(self.dir.x(), pos.x()) +
-            Ray::to_partial_map_index(self.dir.y(), pos.y()) * map_height
+    /// Column and row of the tile the ray is in at `pos`, the one it enters
+    /// when `pos` is on a grid line. None left of or above the map.
+    pub fn to_tile_coords(&self, pos: &Vec2f) -> Option<(usize, usize)> {
+        Some((
+            Ray::to_tile_coord(self.dir.x(), pos.x())?,
+            Ray::to_tile_coord(self.dir.y(), pos.y())?,
+        ))
     }
 }
 
@@ -1,15 +1,23 @@
 pub enum RenderPrecedence {
     Gui,
+    Intermission,
+    Editor,
+    Automap,
     EffectOverlay,
     Map,
+    ViewWeapon,
     CameraView,
 }
 
 pub fn to_numeric(render_recedence: RenderPrecedence) -> u32 {
     match render_recedence {
         RenderPrecedence::Gui => 0,
-        RenderPrecedence::EffectOverlay => 1,
-        RenderPrecedence::Map => 2,
-        RenderPrecedence::CameraView => 3,
+        RenderPrecedence::Intermission => 1,
+        RenderPrecedence::Editor => 2,
+        RenderPrecedence::Automap => 3,
+        RenderPrecedence::EffectOverlay => 4,
+        RenderPrecedence::Map => 5,
+        RenderPrecedence::ViewWeapon => 6,
+        RenderPrecedence::CameraView => 7,
     }
 }
@@ -0,0 +1,161 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::assets::AssetError;
+use crate::explored::ExploredTiles;
+use crate::vector::Vec2f;
+
+/// Where quick saves go, in the working directory
+pub const QUICKSAVE_FILE_NAME: &'static str = "quicksave.sav";
+const VERSION: u32 = 1;
+
+/// A snapshot of the game, saved as text:
+///
+///     version 1
+///     map src/map
+///     pos 9.5 18.5
+///     dir 0 -1
+///     explored 20 20
+///     ...##...
+///
+/// `explored` is followed by one line per map row, see `ExploredTiles::to_rows`
+pub struct SaveGame {
+    /// `TileMap::name` of the map the game was saved on
+    pub map_name: String,
+    pub player_pos: Vec2f,
+    pub player_dir: Vec2f,
+    pub explored: ExploredTiles,
+}
+
+impl SaveGame {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut text = format!(
+            "version {}\nmap {}\npos {} {}\ndir {} {}\nexplored {} {}\n",
+            VERSION,
+            self.map_name,
+            self.player_pos.x(),
+            self.player_pos.y(),
+            self.player_dir.x(),
+            self.player_dir.y(),
+            self.explored.width(),
+            self.explored.height(),
+        );
+        for row in self.explored.to_rows() {
+            text.push_str(&row);
+            text.push('\n');
+        }
+
+        fs::write(path, text)
+    }
+
+    pub fn read(path: &Path) -> Result<SaveGame, AssetError> {
+        let text = fs::read_to_string(path).map_err(|err| AssetError::Io(path.to_path_buf(), err))?;
+        let save_error = |err: &str| AssetError::Config(path.to_path_buf(), String::from(err));
+
+        let lines: Vec<&str> = text.lines().collect();
+        let value = |key: &str| {
+            lines
+                .iter()
+                .find_map(|line| line.strip_prefix(key).and_then(|rest| rest.strip_prefix(' ')))
+                .ok_or_else(|| save_error(&format!("missing \"{}\"", key)))
+        };
+        let numbers = |key: &str| -> Result<Vec<f32>, AssetError> {
+            value(key)?
+                .split_whitespace()
+                .map(|number| number.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| save_error(&format!("\"{}\" expects numbers", key)))
+        };
+        let vec2f = |key: &str| match numbers(key)?.as_slice() {
+            [x, y] => Ok(Vec2f::new(*x, *y)),
+            _ => Err(save_error(&format!("\"{}\" expects x and y", key))),
+        };
+
+        if value("version")? != VERSION.to_string() {
+            return Err(save_error("saved by an incompatible version"));
+        }
+
+        let explored_at = lines
+            .iter()
+            .position(|line| line.starts_with("explored "))
+            .ok_or_else(|| save_error("missing \"explored\""))?;
+        let size = value("explored")?
+            .split_whitespace()
+            .map(|number| number.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>();
+        let (width, height) = match size.as_ref().map(Vec::as_slice) {
+            Ok([width, height]) => (*width, *height),
+            _ => return Err(save_error("\"explored\" expects a width and a height")),
+        };
+        let first_row = explored_at + 1;
+        let rows = first_row
+            .checked_add(height)
+            .and_then(|rows_end| lines.get(first_row..rows_end))
+            .ok_or_else(|| save_error("explored tiles are cut short"))?;
+        let explored = ExploredTiles::from_rows(rows)
+            .filter(|explored| explored.width() == width)
+            .ok_or_else(|| save_error("explored tiles are corrupt"))?;
+
+        Ok(SaveGame {
+            map_name: String::from(value("map")?),
+            player_pos: vec2f("pos")?,
+            player_dir: vec2f("dir")?,
+            explored,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    fn read_text(name: &str, text: &str) -> Result<SaveGame, AssetError> {
+        let path = env::temp_dir().join(format!("rustic-fps-test-{}-{}.sav", process::id(), name));
+        fs::write(&path, text).unwrap();
+        let save = SaveGame::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        save
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut explored = ExploredTiles::new(3, 2);
+        explored.mark(1);
+        explored.mark(5);
+        let save = SaveGame {
+            map_name: String::from("levels/e1m1.map"),
+            player_pos: Vec2f::new(9.5, 18.25),
+            player_dir: Vec2f::new(0.0, -1.0),
+            explored,
+        };
+
+        let path = env::temp_dir().join(format!("rustic-fps-test-{}-round-trip.sav", process::id()));
+        save.write(&path).unwrap();
+        let read = SaveGame::read(&path);
+        fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!(read.map_name, save.map_name);
+        assert_eq!(read.player_pos, save.player_pos);
+        assert_eq!(read.player_dir, save.player_dir);
+        assert_eq!((read.explored.width(), read.explored.height()), (3, 2));
+        assert_eq!(read.explored.to_rows(), vec![".#.", "..#"]);
+    }
+
+    #[test]
+    fn read_rejects_bad_explored_sizes() {
+        let save = |explored: &str| format!("version 1\nmap m\npos 1 1\ndir 0 1\nexplored {}\n.#.\n..#\n", explored);
+
+        assert!(read_text("fits", &save("3 2")).is_ok());
+        assert!(read_text("huge", &save("20 1e30")).is_err());
+        assert!(read_text("max", &save(&format!("3 {}", usize::max_value()))).is_err());
+        assert!(read_text("fraction", &save("3 1.5")).is_err());
+        assert!(read_text("tall", &save("3 3")).is_err());
+        assert!(read_text("wide", &save("4 2")).is_err());
+    }
+}
@@ -0,0 +1,453 @@
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use rhai::Array;
+use rhai::CallFnOptions;
+use rhai::Dynamic;
+use rhai::Engine;
+use rhai::Map;
+use rhai::Scope;
+use rhai::AST;
+
+use crate::assets::AssetError;
+use crate::events::GameEvent;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
+use crate::vector::Vec2f;
+
+/// Keeps a runaway script from freezing the game
+const MAX_OPERATIONS: u64 = 1_000_000;
+/// Called once when the level starts, if the script defines it
+const START_FUNCTION: &'static str = "on_start";
+
+/// What scripts can see of the player, taken before every call
+#[derive(Clone, Copy, Default)]
+struct PlayerState {
+    x: f64,
+    y: f64,
+    health: i64,
+    keys: i64,
+    treasure: i64,
+}
+
+struct Timer {
+    function: String,
+    remaining_us: i64,
+    /// Set for timers that go off again and again
+    interval_us: Option<i64>,
+}
+
+/// Shared between the scripts component and the functions registered with
+/// the engine
+#[derive(Default)]
+struct ScriptContext {
+    player: PlayerState,
+    /// Posted on the event bus once the script returns
+    events: Vec<GameEvent>,
+    timers: Vec<Timer>,
+}
+
+/// Runs the level script named by the map's `script` line. Triggers call its
+/// functions with `script <function>` actions and the script gets to look at
+/// and change the player, tiles, doors and enemies, and start timers. Script
+/// functions can't see the script's variables, so they keep what they need
+/// between calls in `this`, which is the same object map for the whole level.
+pub struct Scripts {
+    engine: Engine,
+    /// None for levels without a script
+    ast: Option<AST>,
+    scope: Scope<'static>,
+    /// `this` of every script function call
+    state: Dynamic,
+    context: Rc<RefCell<ScriptContext>>,
+    started: bool,
+}
+
+impl Scripts {
+    pub fn new(tile_map: Rc<RefCell<TileMap>>) -> Result<Scripts, AssetError> {
+        let script_path = tile_map.borrow().script().map(|script| {
+            let map_dir = tile_map.borrow().path().and_then(Path::parent).map(Path::to_path_buf);
+            map_dir.unwrap_or_else(|| PathBuf::from(".")).join(script)
+        });
+
+        let context = Rc::new(RefCell::new(ScriptContext::default()));
+        let engine = new_engine(&context, &tile_map);
+
+        let ast = match script_path {
+            Some(script_path) => {
+                let text = fs::read_to_string(&script_path)
+                    .map_err(|err| AssetError::Io(script_path.clone(), err))?;
+                let ast = engine
+                    .compile(&text)
+                    .map_err(|err| AssetError::Config(script_path.clone(), err.to_string()))?;
+                Some(ast)
+            }
+            None => None,
+        };
+
+        Ok(Scripts {
+            engine,
+            ast,
+            scope: Scope::new(),
+            state: Dynamic::from_map(Map::new()),
+            context,
+            started: false,
+        })
+    }
+
+    fn defines(&self, function: &str) -> bool {
+        match &self.ast {
+            Some(ast) => ast.iter_functions().any(|script_fn| script_fn.name == function),
+            None => false,
+        }
+    }
+
+    /// Calls a function of the script and posts the events it asked for
+    fn call(&mut self, game: &Game, function: &str) {
+        let ast = match &self.ast {
+            Some(ast) => ast,
+            None => return game.show_error(&format!("No level script to call {} in", function)),
+        };
+
+        {
+            let player = game.player();
+            self.context.borrow_mut().player = PlayerState {
+                x: player.pos().x() as f64,
+                y: player.pos().y() as f64,
+                health: player.health() as i64,
+                keys: player.inventory().keys as i64,
+                treasure: player.inventory().treasure as i64,
+            };
+        }
+
+        // The top level statements only run once, when the level starts
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        let result = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut self.scope, ast, function, ());
+        if let Err(err) = result {
+            game.show_error(&format!("Script function {} failed: {}", function, err));
+        }
+        self.post_events(game);
+    }
+
+    fn post_events(&self, game: &Game) {
+        let events = std::mem::take(&mut self.context.borrow_mut().events);
+        for event in events {
+            game.post_event(event);
+        }
+    }
+
+    /// Runs the script's top level statements and its start function
+    fn start(&mut self, game: &Game) {
+        self.started = true;
+        let ast = match &self.ast {
+            Some(ast) => ast,
+            None => return,
+        };
+
+        if let Err(err) = self.engine.run_ast_with_scope(&mut self.scope, ast) {
+            game.show_error(&format!("Level script failed: {}", err));
+        }
+        self.post_events(game);
+        if self.defines(START_FUNCTION) {
+            self.call(game, START_FUNCTION);
+        }
+    }
+
+    fn run_timers(&mut self, game: &Game, dt: u32) {
+        let mut due = vec![];
+        {
+            let mut context = self.context.borrow_mut();
+            for timer in context.timers.iter_mut() {
+                timer.remaining_us -= dt as i64;
+                if timer.remaining_us <= 0 {
+                    due.push(timer.function.clone());
+                    if let Some(interval_us) = timer.interval_us {
+                        timer.remaining_us = interval_us.max(1);
+                    }
+                }
+            }
+            context.timers.retain(|timer| timer.remaining_us > 0);
+        }
+
+        for function in due {
+            self.call(game, &function);
+        }
+    }
+}
+
+impl GameComponent for Scripts {
+    fn update(&mut self, game: &Game, dt: u32) {
+        if !self.started {
+            self.start(game);
+        }
+        if !game.movement_paused() {
+            self.run_timers(game, dt);
+        }
+    }
+
+    fn handle_game_event(&mut self, game: &Game, event: &GameEvent) {
+        if let GameEvent::RunScript(function) = event {
+            self.call(game, function);
+        }
+    }
+}
+
+/// Column and row as map indices, None outside the map
+fn to_tile(tile_map: &TileMap, column: i64, row: i64) -> Option<(usize, usize)> {
+    let (column, row) = (usize::try_from(column).ok()?, usize::try_from(row).ok()?);
+    tile_map.index(column, row).map(|_| (column, row))
+}
+
+/// An engine with the operation limit and every function scripts can call
+fn new_engine(context: &Rc<RefCell<ScriptContext>>, tile_map: &Rc<RefCell<TileMap>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    register_functions(&mut engine, context, tile_map);
+    engine
+}
+
+fn register_functions(engine: &mut Engine, context: &Rc<RefCell<ScriptContext>>, tile_map: &Rc<RefCell<TileMap>>) {
+    let post = |context: &Rc<RefCell<ScriptContext>>| {
+        let context = Rc::clone(context);
+        move |event: GameEvent| context.borrow_mut().events.push(event)
+    };
+
+    let post_event = post(context);
+    engine.register_fn("message", move |text: &str| post_event(GameEvent::Message(String::from(text))));
+    let post_event = post(context);
+    engine.register_fn("end_level", move || post_event(GameEvent::EndLevel));
+    let post_event = post(context);
+    engine.register_fn("set_light", move |level: f64| {
+        post_event(GameEvent::SetLight((level as f32).max(0.0).min(1.0)))
+    });
+    let post_event = post(context);
+    engine.register_fn("open_door", move |column: i64, row: i64| {
+        if column >= 0 && row >= 0 {
+            post_event(GameEvent::OpenDoor {
+                column: column as usize,
+                row: row as usize,
+            });
+        }
+    });
+    let post_event = post(context);
+    engine.register_fn("spawn_enemy", move |column: i64, row: i64| {
+        if column >= 0 && row >= 0 {
+            post_event(GameEvent::SpawnEnemy {
+                column: column as usize,
+                row: row as usize,
+            });
+        }
+    });
+    let post_event = post(context);
+    engine.register_fn("kill_enemy", move |column: i64, row: i64| {
+        if column >= 0 && row >= 0 {
+            post_event(GameEvent::KillEnemy {
+                column: column as usize,
+                row: row as usize,
+            });
+        }
+    });
+    let post_event = post(context);
+    engine.register_fn("set_player_health", move |health: i64| {
+        post_event(GameEvent::SetHealth(health.max(0) as u32))
+    });
+
+    let player_context = Rc::clone(context);
+    engine.register_fn("player_x", move || player_context.borrow().player.x);
+    let player_context = Rc::clone(context);
+    engine.register_fn("player_y", move || player_context.borrow().player.y);
+    let player_context = Rc::clone(context);
+    engine.register_fn("player_health", move || player_context.borrow().player.health);
+    let player_context = Rc::clone(context);
+    engine.register_fn("player_keys", move || player_context.borrow().player.keys);
+    let player_context = Rc::clone(context);
+    engine.register_fn("player_treasure", move || player_context.borrow().player.treasure);
+
+    let script_tile_map = Rc::clone(tile_map);
+    engine.register_fn("tile", move |column: i64, row: i64| {
+        let tile_map = script_tile_map.borrow();
+        to_tile(&tile_map, column, row)
+            .and_then(|(column, row)| tile_map.tile(column, row))
+            .map_or(-1, i64::from)
+    });
+    let script_tile_map = Rc::clone(tile_map);
+    engine.register_fn("set_tile", move |column: i64, row: i64, tile: i64| {
+        let mut tile_map = script_tile_map.borrow_mut();
+        if let (Some((column, row)), Ok(tile)) = (to_tile(&tile_map, column, row), u16::try_from(tile)) {
+            tile_map.set_tile(column, row, tile);
+        }
+    });
+    let script_tile_map = Rc::clone(tile_map);
+    engine.register_fn("enemy_count", move || {
+        let tile_map = script_tile_map.borrow();
+        tile_map
+            .things()
+            .iter()
+            .filter(|thing| thing.kind == ThingKind::Enemy)
+            .count() as i64
+    });
+    let script_tile_map = Rc::clone(tile_map);
+    engine.register_fn("can_see", move |from_x: f64, from_y: f64, to_x: f64, to_y: f64| {
+        let from = Vec2f::new(from_x as f32, from_y as f32);
+        script_tile_map.borrow().line_of_sight(from, Vec2f::new(to_x as f32, to_y as f32))
+    });
+    let script_tile_map = Rc::clone(tile_map);
+    engine.register_fn("is_blocked", move |x: f64, y: f64, radius: f64| {
+        script_tile_map
+            .borrow()
+            .overlaps_circle(Vec2f::new(x as f32, y as f32), radius as f32)
+    });
+    let script_tile_map = Rc::clone(tile_map);
+    engine.register_fn("tiles_within", move |x: f64, y: f64, radius: f64| {
+        let tiles = script_tile_map
+            .borrow()
+            .tiles_within(Vec2f::new(x as f32, y as f32), radius as f32);
+        tiles
+            .into_iter()
+            .map(|(column, row)| {
+                let tile: Array = vec![Dynamic::from(column as i64), Dynamic::from(row as i64)];
+                Dynamic::from_array(tile)
+            })
+            .collect::<Array>()
+    });
+
+    let timer_context = Rc::clone(context);
+    engine.register_fn("after", move |seconds: f64, function: &str| {
+        timer_context.borrow_mut().timers.push(Timer {
+            function: String::from(function),
+            remaining_us: (seconds * 1_000_000.0) as i64,
+            interval_us: None,
+        });
+    });
+    let timer_context = Rc::clone(context);
+    engine.register_fn("every", move |seconds: f64, function: &str| {
+        let interval_us = (seconds * 1_000_000.0) as i64;
+        timer_context.borrow_mut().timers.push(Timer {
+            function: String::from(function),
+            remaining_us: interval_us,
+            interval_us: Some(interval_us),
+        });
+    });
+    let timer_context = Rc::clone(context);
+    engine.register_fn("stop_timers", move |function: &str| {
+        timer_context.borrow_mut().timers.retain(|timer| timer.function != function);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x3 tiles of walls around a single floor tile
+    fn cell() -> Rc<RefCell<TileMap>> {
+        #[rustfmt::skip]
+        let tiles = vec![
+            1, 1, 1,
+            1, 0, 1,
+            1, 1, 1,
+        ];
+
+        Rc::new(RefCell::new(TileMap::new(3, 3, tiles, None)))
+    }
+
+    fn setup() -> (Engine, Rc<RefCell<ScriptContext>>, Rc<RefCell<TileMap>>) {
+        let context = Rc::new(RefCell::new(ScriptContext::default()));
+        let tile_map = cell();
+        let engine = new_engine(&context, &tile_map);
+        (engine, context, tile_map)
+    }
+
+    #[test]
+    fn tiles_outside_the_map_are_ignored() {
+        let (engine, _, tile_map) = setup();
+
+        assert_eq!(engine.eval::<i64>("tile(1, 1)").unwrap(), 0);
+        assert_eq!(engine.eval::<i64>("tile(0, 1)").unwrap(), 1);
+        for outside in ["tile(3, 1)", "tile(1, 3)", "tile(-1, 1)", "tile(1, -1)"].iter() {
+            assert_eq!(engine.eval::<i64>(outside).unwrap(), -1);
+        }
+
+        engine.run("set_tile(3, 1, 2); set_tile(-1, 1, 2); set_tile(1, -1, 2)").unwrap();
+        engine.run("set_tile(1, 1, -2); set_tile(1, 1, 70000)").unwrap();
+        let tiles: Vec<_> = (0..9).map(|i| tile_map.borrow().tile(i % 3, i / 3)).collect();
+        assert_eq!(tiles, (0..9).map(|i| Some(if i == 4 { 0 } else { 1 })).collect::<Vec<_>>());
+
+        engine.run("set_tile(1, 1, 2)").unwrap();
+        assert_eq!(tile_map.borrow().tile(1, 1), Some(2));
+        assert_eq!(engine.eval::<i64>("tile(1, 1)").unwrap(), 2);
+    }
+
+    #[test]
+    fn timers_are_started_and_stopped() {
+        let (engine, context, _) = setup();
+
+        engine.run(r#"after(1.5, "open"); every(2.0, "spawn"); every(3.0, "open")"#).unwrap();
+        {
+            let context = context.borrow();
+            let timers: Vec<_> = context
+                .timers
+                .iter()
+                .map(|timer| (timer.function.as_str(), timer.remaining_us, timer.interval_us))
+                .collect();
+            assert_eq!(
+                timers,
+                vec![
+                    ("open", 1_500_000, None),
+                    ("spawn", 2_000_000, Some(2_000_000)),
+                    ("open", 3_000_000, Some(3_000_000)),
+                ]
+            );
+        }
+
+        engine.run(r#"stop_timers("open")"#).unwrap();
+        let context = context.borrow();
+        let functions: Vec<_> = context.timers.iter().map(|timer| timer.function.as_str()).collect();
+        assert_eq!(functions, vec!["spawn"]);
+    }
+
+    #[test]
+    fn events_are_queued_until_the_script_returns() {
+        let (engine, context, _) = setup();
+
+        engine.run(r#"message("Hello"); open_door(1, 2); open_door(-1, 2); set_player_health(-5)"#).unwrap();
+        let events = std::mem::take(&mut context.borrow_mut().events);
+        assert!(match events.as_slice() {
+            [GameEvent::Message(text), GameEvent::OpenDoor { column: 1, row: 2 }, GameEvent::SetHealth(0)] =>
+                text == "Hello",
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn this_is_kept_between_calls() {
+        let (engine, _, _) = setup();
+        let ast = engine.compile("fn count() { this.calls = (this.calls ?? 0) + 1; this.calls }").unwrap();
+        let mut scope = Scope::new();
+        let mut state = Dynamic::from_map(Map::new());
+
+        for expected in 1..=3 {
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut state);
+            let calls = engine
+                .call_fn_with_options::<i64>(options, &mut scope, &ast, "count", ())
+                .unwrap();
+            assert_eq!(calls, expected);
+        }
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let (engine, _, _) = setup();
+
+        let err = engine.run("loop { }").unwrap_err();
+        assert!(matches!(*err, rhai::EvalAltResult::ErrorTooManyOperations(_)));
+        assert!(engine.run("let x = 0; while x < 100 { x += 1; }").is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::assets::AssetError;
+
+/// Optional file in the textures folder that configures animations
+pub const ANIMATIONS_FILE_NAME: &'static str = "animations.cfg";
+const DEFAULT_FRAME_DURATION_US: u64 = 100_000;
+
+/// A texture made of frames that are shown one after another, looping
+pub struct Animation {
+    frames: Vec<String>,
+    /// How long each frame is shown for, in microseconds
+    durations: Vec<u64>,
+}
+
+impl Animation {
+    /// Name of the frame shown at the given time, in microseconds
+    pub fn frame_at(&self, clock_us: u64) -> &str {
+        let cycle_duration: u64 = self.durations.iter().sum();
+        if cycle_duration == 0 {
+            return &self.frames[0];
+        }
+
+        let mut time_in_cycle = clock_us % cycle_duration;
+        for (frame, duration) in self.frames.iter().zip(self.durations.iter()) {
+            if time_in_cycle < *duration {
+                return frame;
+            }
+            time_in_cycle -= duration;
+        }
+
+        self.frames.last().unwrap()
+    }
+}
+
+/// Texture coordinate offset that grows over time, e.g. for conveyor belts
+/// and waterfalls. Speeds are in texture repeats per second.
+pub struct Scroll {
+    pub u_speed: f32,
+    pub v_speed: f32,
+}
+
+impl Scroll {
+    /// Offset at the given time, in microseconds, wrapped to 0.0 to 1.0
+    pub fn offset_at(&self, clock_us: u64) -> (f32, f32) {
+        let clock_s = (clock_us as f64 / 1_000_000.0) as f32;
+
+        (
+            (self.u_speed * clock_s).rem_euclid(1.0),
+            (self.v_speed * clock_s).rem_euclid(1.0),
+        )
+    }
+}
+
+/// Finds frame sequences among the texture names: `lava_0`, `lava_1`...
+/// become an animation called `lava` with the default frame duration.
+/// Frames have to be numbered from 0 without gaps, and a single frame is not
+/// an animation.
+pub fn detect_animations<'a, I>(tex_names: I) -> HashMap<String, Animation>
+where
+    I: Iterator<Item = &'a String>,
+{
+    let mut frame_counts: HashMap<&str, Vec<u32>> = HashMap::new();
+    for tex_name in tex_names {
+        let split_at = match tex_name.rfind('_') {
+            Some(split_at) => split_at,
+            None => continue,
+        };
+        if let Ok(frame_index) = tex_name[split_at + 1..].parse::<u32>() {
+            frame_counts
+                .entry(&tex_name[..split_at])
+                .or_insert_with(Vec::new)
+                .push(frame_index);
+        }
+    }
+
+    let mut animations = HashMap::new();
+    for (name, mut frame_indices) in frame_counts {
+        frame_indices.sort();
+        let is_contiguous = frame_indices
+            .iter()
+            .enumerate()
+            .all(|(i, frame)| i as u32 == *frame);
+        if frame_indices.len() < 2 || !is_contiguous {
+            continue;
+        }
+
+        let frames: Vec<String> = frame_indices
+            .iter()
+            .map(|frame| format!("{}_{}", name, frame))
+            .collect();
+        let durations = vec![DEFAULT_FRAME_DURATION_US; frames.len()];
+        animations.insert(String::from(name), Animation { frames, durations });
+    }
+
+    animations
+}
+
+/// Applies the animation file to detected animations and returns the
+/// scrolling textures it defines. The file has one definition per line:
+///
+/// ```text
+/// # anim <name> <frame duration in ms>...
+/// anim lava 80 80 80 80 120 120 120 120
+/// # scroll <name> <u repeats/s> <v repeats/s>
+/// scroll conveyor 0.5 0
+/// ```
+///
+/// A single `anim` duration applies to all of the animation's frames.
+pub fn load_definitions(
+    path: &Path,
+    animations: &mut HashMap<String, Animation>,
+) -> Result<HashMap<String, Scroll>, AssetError> {
+    if !path.is_file() {
+        return Ok(HashMap::new());
+    }
+
+    let definitions =
+        fs::read_to_string(path).map_err(|err| AssetError::Io(path.to_path_buf(), err))?;
+    parse_definitions(path, &definitions, animations)
+}
+
+/// Same as `load_definitions`, for definitions that have already been read.
+/// `path` is only used for error reporting.
+pub fn parse_definitions(
+    path: &Path,
+    definitions: &str,
+    animations: &mut HashMap<String, Animation>,
+) -> Result<HashMap<String, Scroll>, AssetError> {
+    let mut scrolls = HashMap::new();
+    for (line_index, line) in definitions.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let config_error = |err: &str| {
+            AssetError::Config(path.to_path_buf(), format!("line {}: {}", line_index + 1, err))
+        };
+        let mut words = line.split_whitespace();
+        let kind = words.next().unwrap();
+        let name = words.next().ok_or_else(|| config_error("expected a texture name"))?;
+        let values: Vec<&str> = words.collect();
+
+        match kind {
+            "anim" => {
+                let animation = animations.get_mut(name).ok_or_else(|| {
+                    config_error(&format!("no frames named \"{}_0\", \"{}_1\"...", name, name))
+                })?;
+
+                let durations = values
+                    .iter()
+                    .map(|value| value.parse::<u64>().map(|ms| ms * 1000))
+                    .collect::<Result<Vec<u64>, _>>()
+                    .map_err(|_| config_error("frame durations have to be whole milliseconds"))?;
+
+                animation.durations = match durations.len() {
+                    1 => vec![durations[0]; animation.frames.len()],
+                    count if count == animation.frames.len() => durations,
+                    _ => {
+                        return Err(config_error(&format!(
+                            "expected 1 or {} frame durations",
+                            animation.frames.len()
+                        )))
+                    }
+                };
+            }
+            "scroll" => {
+                let speeds = values
+                    .iter()
+                    .map(|value| value.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|_| config_error("scroll speeds have to be numbers"))?;
+                if speeds.len() != 2 {
+                    return Err(config_error("expected u and v scroll speeds"));
+                }
+
+                scrolls.insert(
+                    String::from(name),
+                    Scroll {
+                        u_speed: speeds[0],
+                        v_speed: speeds[1],
+                    },
+                );
+            }
+            _ => return Err(config_error(&format!("unknown definition \"{}\"", kind))),
+        }
+    }
+
+    Ok(scrolls)
+}
@@ -1,141 +1,744 @@
 use std::collections::HashMap;
-use std::fs;
 use std::path::Path;
-use std::env;
+use std::path::PathBuf;
 
 use sdl2::image::LoadSurface;
 use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::surface::Surface;
 
+use crate::asset_pack::AssetPack;
+use crate::asset_pack::EntryKind;
+use crate::assets;
+use crate::assets::AssetError;
+use crate::assets::PACK_FILE_NAME;
+use crate::assets::TEXTURES_DIR;
+use crate::texture_animations;
+use crate::texture_animations::Animation;
+use crate::texture_animations::Scroll;
+
+/// Texels of this colour are treated as fully transparent
+pub const TRANSPARENT_COLOR: Color = Color {
+    r: 255,
+    g: 0,
+    b: 255,
+    a: 255,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+}
+
+/// Packed R, G, B, A texel
+pub type Texel = [u8; 4];
+
+/// One level of a texture's mip chain, each level half the size of the
+/// previous. Texels are stored column by column, as walls are drawn one
+/// texture column per screen column.
+struct MipLevel {
+    width: u32,
+    height: u32,
+    texels: Vec<Texel>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: i32, y: i32) -> Texel {
+        let y = y.rem_euclid(self.height as i32) as usize;
+
+        self.column(x)[y]
+    }
+
+    fn column(&self, x: i32) -> &[Texel] {
+        let x = x.rem_euclid(self.width as i32) as usize;
+        let height = self.height as usize;
+
+        &self.texels[x * height..(x + 1) * height]
+    }
+}
+
+/// Every texture is converted to this on load: 4 bytes per texel, in R, G, B,
+/// A order regardless of endianness
+pub const TEXTURE_FORMAT: PixelFormatEnum = PixelFormatEnum::RGBA32;
+const TEXTURE_BYTES_PER_PIXEL: usize = 4;
+
+const PLACEHOLDER_SIZE: u32 = 64;
+const PLACEHOLDER_CHECKER_SIZE: u32 = 8;
+
+/// A column of texels as returned by `Texture::column`
+pub struct TexColumn<'a> {
+    texels: &'a [Texel],
+    v_offset: f32,
+}
+
+impl<'a> TexColumn<'a> {
+    /// Texel at vertical texture coordinate `v`, which wraps around at 1.0
+    pub fn at(&self, v: f32) -> Texel {
+        let len = self.texels.len();
+        let y = ((v + self.v_offset).rem_euclid(1.0) * len as f32) as usize;
+
+        self.texels[y.min(len - 1)]
+    }
+}
+
 pub struct Texture {
-    surface: Surface<'static>   
+    surface: Surface<'static>,
+    mips: Vec<MipLevel>,
+    /// Texture coordinate offset of scrolling textures
+    uv_offset: (f32, f32),
 }
 
 impl Texture {
-    pub fn new(tex_path: &str) -> Option<Texture> {
-        let surface: Surface = LoadSurface::from_file(Path::new(tex_path)).ok()?;
+    pub fn new(tex_path: &Path) -> Result<Texture, AssetError> {
+        let surface: Surface = LoadSurface::from_file(tex_path)
+            .map_err(|err| AssetError::Texture(tex_path.to_path_buf(), err))?;
+
+        Texture::from_surface(surface)
+            .map_err(|err| AssetError::Texture(tex_path.to_path_buf(), err))
+    }
+
+    /// Converts a surface in any pixel format, paletted ones included, to
+    /// `TEXTURE_FORMAT`. Color keys, and `TRANSPARENT_COLOR` in formats
+    /// without an alpha channel, become transparent texels.
+    pub fn from_surface(surface: Surface) -> Result<Texture, String> {
+        let had_alpha = surface.pixel_format_enum().supports_alpha();
+        let mut surface = surface.convert_format(TEXTURE_FORMAT)?;
+
+        if !had_alpha {
+            let width = surface.width() as usize;
+            let height = surface.height() as usize;
+            let pitch = surface.pitch() as usize;
+            surface.with_lock_mut(|buf| {
+                for y in 0..height {
+                    for x in 0..width {
+                        let index = y * pitch + x * TEXTURE_BYTES_PER_PIXEL;
+                        let texel = &mut buf[index..index + TEXTURE_BYTES_PER_PIXEL];
+                        if texel[0] == TRANSPARENT_COLOR.r
+                            && texel[1] == TRANSPARENT_COLOR.g
+                            && texel[2] == TRANSPARENT_COLOR.b
+                        {
+                            texel[3] = 0;
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(Texture {
+            surface,
+            mips: vec![],
+            uv_offset: (0.0, 0.0),
+        })
+    }
+
+    /// Procedural stand-in for a texture that couldn't be loaded: a
+    /// checkerboard in a color derived from the texture's name, so different
+    /// missing textures are still told apart
+    pub fn placeholder(tex_name: &str) -> Texture {
+        let mut surface = Surface::new(
+            PLACEHOLDER_SIZE,
+            PLACEHOLDER_SIZE,
+            TEXTURE_FORMAT,
+        )
+        .unwrap();
+
+        // FNV-1a, so a name always gets the same color
+        let hash = tex_name.bytes().fold(0x811c9dc5u32, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x01000193)
+        });
+        // Keep the channels bright enough to be told apart from the dark squares
+        let color = [
+            (hash >> 16) as u8 | 0x40,
+            (hash >> 8) as u8 | 0x40,
+            hash as u8 | 0x40,
+        ];
+
+        let pitch = surface.pitch() as usize;
+        surface.with_lock_mut(|buf| {
+            for y in 0..PLACEHOLDER_SIZE {
+                for x in 0..PLACEHOLDER_SIZE {
+                    let is_dark = (x / PLACEHOLDER_CHECKER_SIZE + y / PLACEHOLDER_CHECKER_SIZE) % 2 == 1;
+                    let index = y as usize * pitch + x as usize * TEXTURE_BYTES_PER_PIXEL;
+                    for channel in 0..3 {
+                        buf[index + channel] = if is_dark { color[channel] / 4 } else { color[channel] };
+                    }
+                    buf[index + 3] = 255;
+                }
+            }
+        });
+
+        Texture {
+            surface,
+            mips: vec![],
+            uv_offset: (0.0, 0.0),
+        }
+    }
+
+    /// Creates a texture from `TEXTURE_FORMAT` texels, row by row without padding
+    pub fn from_rgba(width: u32, height: u32, texels: &[u8]) -> Result<Texture, String> {
+        let row_len = width as usize * TEXTURE_BYTES_PER_PIXEL;
+        if width == 0 || height == 0 || texels.len() != row_len * height as usize {
+            return Err(format!(
+                "{} bytes of texels don't make a {}x{} texture",
+                texels.len(),
+                width,
+                height
+            ));
+        }
+
+        let mut surface = Surface::new(width, height, TEXTURE_FORMAT)?;
+        let pitch = surface.pitch() as usize;
+        surface.with_lock_mut(|buf| {
+            for (y, row) in texels.chunks(row_len).enumerate() {
+                buf[y * pitch..y * pitch + row_len].copy_from_slice(row);
+            }
+        });
 
-        Some(Texture {
-            surface: surface
+        Ok(Texture {
+            surface,
+            mips: vec![],
+            uv_offset: (0.0, 0.0),
         })
     }
 
+    /// The texture's `TEXTURE_FORMAT` texels, row by row without padding
+    pub fn to_rgba(&self) -> Vec<u8> {
+        let row_len = self.surface.width() as usize * TEXTURE_BYTES_PER_PIXEL;
+        let pitch = self.surface.pitch() as usize;
+        let surface_buf = self.surface.without_lock().unwrap();
+
+        let mut texels = Vec::with_capacity(row_len * self.surface.height() as usize);
+        for y in 0..self.surface.height() as usize {
+            texels.extend_from_slice(&surface_buf[y * pitch..y * pitch + row_len]);
+        }
+
+        texels
+    }
+
     pub fn surface(&self) -> &Surface {
         &self.surface
     }
 
     pub fn pixel(&self, x: u32, y: u32) -> Color {
-        let pixel_index = 3 * (x + y * self.surface.height());
-        let pixel_index = pixel_index as usize;
+        debug_assert!(x < self.surface.width() && y < self.surface.height());
+        let pixel_index = y as usize * self.surface.pitch() as usize + x as usize * TEXTURE_BYTES_PER_PIXEL;
 
         let pixel_buf = self.surface.without_lock().unwrap();
-        Color::RGB (
+        Color::RGBA(
             pixel_buf[pixel_index],
             pixel_buf[pixel_index + 1],
             pixel_buf[pixel_index + 2],
+            pixel_buf[pixel_index + 3],
         )
     }
+
+    /// Picks the mip level whose texels are closest to one per screen pixel
+    /// when the texture's height is drawn `projected_height` pixels tall
+    pub fn mip_level_for(&self, projected_height: f32) -> usize {
+        let texels_per_pixel = self.surface.height() as f32 / projected_height;
+        if !(texels_per_pixel > 1.0) {
+            return 0;
+        }
+
+        let level = texels_per_pixel.log2() as usize;
+        level.min(self.mips.len().saturating_sub(1))
+    }
+
+    /// Alpha weighted average of the texels, the last level of the mip chain
+    pub fn average_color(&self) -> Texel {
+        self.mip(usize::max_value()).texel(0, 0)
+    }
+
+    fn mip(&self, mip_level: usize) -> &MipLevel {
+        self.mips
+            .get(mip_level)
+            .or_else(|| self.mips.last())
+            .expect("Texture has no mip chain")
+    }
+
+    /// Column of texels, top to bottom, at horizontal texture coordinate `u`,
+    /// which wraps around at 1.0. Cheap enough to call for every screen column.
+    pub fn column(&self, u: f32, mip_level: usize) -> TexColumn {
+        let mip = self.mip(mip_level);
+        let u = u + self.uv_offset.0;
+
+        TexColumn {
+            texels: mip.column((u * mip.width as f32).floor() as i32),
+            v_offset: self.uv_offset.1,
+        }
+    }
+
+    /// Samples the texture at texture coordinates `u` and `v`, both wrapping
+    /// around at 1.0
+    pub fn sample(&self, u: f32, v: f32, mip_level: usize, filter: TextureFilter) -> Texel {
+        let mip = self.mip(mip_level);
+
+        let x = (u + self.uv_offset.0) * mip.width as f32;
+        let y = (v + self.uv_offset.1) * mip.height as f32;
+        match filter {
+            TextureFilter::Nearest => mip.texel(x.floor() as i32, y.floor() as i32),
+            TextureFilter::Bilinear => {
+                // Texel centers sit at half coordinates
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i32, y0 as i32);
+
+                let top = lerp_texel(mip.texel(x0, y0), mip.texel(x0 + 1, y0), fx);
+                let bot = lerp_texel(mip.texel(x0, y0 + 1), mip.texel(x0 + 1, y0 + 1), fx);
+                lerp_texel(top, bot, fy)
+            }
+        }
+    }
+}
+
+fn lerp_texel(a: Texel, b: Texel, t: f32) -> Texel {
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+    [lerp(a[0], b[0]), lerp(a[1], b[1]), lerp(a[2], b[2]), lerp(a[3], b[3])]
+}
+
+/// Where the texture loader reads textures from
+enum TextureSource {
+    /// PNGs in the textures folder, handy during development
+    Directory(PathBuf),
+    Pack(AssetPack),
 }
 
 pub struct TextureLoader {
-    textures: HashMap<String, Texture>
+    source: TextureSource,
+    textures: HashMap<String, Texture>,
+    animations: HashMap<String, Animation>,
+    scrolls: HashMap<String, Scroll>,
+    /// Textures that were made in code rather than loaded, see `insert`
+    inserted: Vec<String>,
+    /// Game time animations are at, in microseconds
+    clock_us: u64,
 }
 
 impl TextureLoader {
-    pub fn new() -> Self {
-        TextureLoader {
-            textures: HashMap::new()
-        }
+    /// Reads textures from the asset root's textures folder, or from its asset
+    /// pack if there is no textures folder
+    pub fn new(asset_root: &Path) -> Result<Self, AssetError> {
+        let tex_dir = asset_root.join(TEXTURES_DIR);
+        let pack_path = asset_root.join(PACK_FILE_NAME);
+        let source = if !tex_dir.is_dir() && pack_path.is_file() {
+            TextureSource::Pack(AssetPack::open(&pack_path)?)
+        } else {
+            TextureSource::Directory(tex_dir)
+        };
+
+        Ok(TextureLoader {
+            source,
+            textures: HashMap::new(),
+            animations: HashMap::new(),
+            scrolls: HashMap::new(),
+            inserted: vec![],
+            clock_us: 0,
+        })
     }
     
-    /// Loads all the textures found in textures folder
-    pub fn new_eager() -> Self {
-        let mut tex_loader = TextureLoader {
-            textures: HashMap::new()
+    /// Loads all the textures found in textures folder and its subfolders, or
+    /// in the asset pack. Textures in subfolders are named after their path,
+    /// e.g. "walls/bluestone".
+    pub fn new_eager(asset_root: &Path) -> Result<Self, AssetError> {
+        let mut tex_loader = TextureLoader::new(asset_root)?;
+        tex_loader.load_all()?;
+
+        Ok(tex_loader)
+    }
+
+    /// Fails only if the textures folder can't be read, textures that fail to
+    /// load are reported and skipped. A missing textures folder is left for
+    /// `load_texture` to fill in with placeholders.
+    fn load_all(&mut self) -> Result<(), AssetError> {
+        let tex_names: Vec<String> = match &self.source {
+            TextureSource::Directory(tex_dir) if !tex_dir.is_dir() => vec![],
+            TextureSource::Directory(tex_dir) => assets::discover(tex_dir, "png")?
+                .into_iter()
+                .map(|(tex_name, _)| tex_name)
+                .collect(),
+            TextureSource::Pack(pack) => pack
+                .entries()
+                .iter()
+                .filter(|entry| entry.kind != EntryKind::File)
+                .map(|entry| entry.name.clone())
+                .collect(),
         };
-        tex_loader.load_all();
 
-        tex_loader
+        for tex_name in tex_names {
+            if let Err(err) = self.load(&tex_name) {
+                println!("{}", err);
+            }
+        }
+
+        let (animations, scrolls) = self.load_animations()?;
+        self.animations = animations;
+        self.scrolls = scrolls;
+        self.advance(0);
+
+        Ok(())
     }
 
-    // Returns an option purely to enable nice ? syntax
-    fn load_all(&mut self) {
-        let tex_paths = self.tex_paths();
-        for tex_path in tex_paths {
-            let tex_name = tex_path
-                .split('/')
-                .last()
-                .and_then(|s| Some(s.split('.')))
-                .and_then(|mut s| s.next());
+    /// Detects animations among the loaded textures and applies the
+    /// animation file to them
+    fn load_animations(&self) -> Result<(HashMap<String, Animation>, HashMap<String, Scroll>), AssetError> {
+        let mut animations = texture_animations::detect_animations(self.textures.keys());
+        let scrolls = match &self.source {
+            TextureSource::Directory(tex_dir) => texture_animations::load_definitions(
+                &tex_dir.join(texture_animations::ANIMATIONS_FILE_NAME),
+                &mut animations,
+            )?,
+            TextureSource::Pack(pack) => match pack.entry(texture_animations::ANIMATIONS_FILE_NAME) {
+                Some(entry) => {
+                    let definitions_path = pack.path().join(&entry.name);
+                    let definitions = String::from_utf8(pack.read(entry)?).map_err(|_| {
+                        AssetError::Config(definitions_path.clone(), String::from("not valid utf-8"))
+                    })?;
+                    texture_animations::parse_definitions(&definitions_path, &definitions, &mut animations)?
+                }
+                None => HashMap::new(),
+            },
+        };
 
-            if let None = tex_name {
-                continue;
+        Ok((animations, scrolls))
+    }
+
+    /// The textures folder, None when the textures come from an asset pack
+    pub fn directory(&self) -> Option<&Path> {
+        match &self.source {
+            TextureSource::Directory(tex_dir) => Some(tex_dir),
+            TextureSource::Pack(_) => None,
+        }
+    }
+
+    /// Files the textures are read from: every texture and the animation file
+    /// in the textures folder, or the asset pack
+    pub fn source_files(&self) -> Result<Vec<PathBuf>, AssetError> {
+        match &self.source {
+            TextureSource::Directory(tex_dir) if !tex_dir.is_dir() => Ok(vec![]),
+            TextureSource::Directory(tex_dir) => {
+                let mut files: Vec<PathBuf> = assets::discover(tex_dir, "png")?
+                    .into_iter()
+                    .map(|(_, tex_path)| tex_path)
+                    .collect();
+                files.push(tex_dir.join(texture_animations::ANIMATIONS_FILE_NAME));
+
+                Ok(files)
             }
+            TextureSource::Pack(pack) => Ok(vec![pack.path().to_path_buf()]),
+        }
+    }
 
-            let tex_name = String::from(tex_name.unwrap());
+    /// Reloads whatever was read from the changed source file. If that fails
+    /// the textures loaded before are kept.
+    pub fn reload_file(&mut self, path: &Path) -> Result<(), AssetError> {
+        let tex_dir = match &self.source {
+            TextureSource::Directory(tex_dir) => tex_dir.clone(),
+            TextureSource::Pack(_) => {
+                let mut reloaded = TextureLoader {
+                    source: TextureSource::Pack(AssetPack::open(path)?),
+                    textures: HashMap::new(),
+                    animations: HashMap::new(),
+                    scrolls: HashMap::new(),
+                    inserted: vec![],
+                    clock_us: self.clock_us,
+                };
+                reloaded.load_all()?;
+                for tex_name in self.inserted.drain(..) {
+                    if let Some(tex) = self.textures.remove(&tex_name) {
+                        reloaded.textures.insert(tex_name.clone(), tex);
+                        reloaded.inserted.push(tex_name);
+                    }
+                }
+                *self = reloaded;
 
-            self.load(&tex_path, &tex_name);
+                return Ok(());
+            }
+        };
+
+        if path == tex_dir.join(texture_animations::ANIMATIONS_FILE_NAME) {
+            let (animations, scrolls) = self.load_animations()?;
+            self.animations = animations;
+            self.scrolls = scrolls;
+            self.advance(0);
+
+            return Ok(());
         }
+
+        // Same naming as `assets::discover`
+        let relative_path = path.strip_prefix(&tex_dir).unwrap_or(path).with_extension("");
+        let tex_name = relative_path
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        self.load(&tex_name)?;
+        self.advance(0);
+
+        Ok(())
     }
 
-    fn load(&mut self, tex_path: &str, tex_name: &str) -> Option<&Texture> {
-        print!("Loading tex \"{}\" from {}... ", tex_name, tex_path);
-        
-        let texture = Texture::new(&tex_path); 
-        match texture {
-            Some(tex) => {
-                println!("Success");
-                self.textures.insert(String::from(tex_name), tex);
-                self.textures.get(tex_name)
-            },
-            None => {
-                println!("Fail");
-                None
+    /// Moves animated and scrolling textures forward by `dt` microseconds of
+    /// game time
+    pub fn advance(&mut self, dt: u32) {
+        self.clock_us += dt as u64;
+
+        for (tex_name, scroll) in self.scrolls.iter() {
+            if let Some(tex) = self.textures.get_mut(tex_name) {
+                tex.uv_offset = scroll.offset_at(self.clock_us);
             }
         }
     }
 
-    fn tex_paths(&self) -> Vec<String> {
-        // TODO: write a build script that moves assets somewhere near the binary
-        let mut tex_names = vec![];
+    /// Whether any of the textures change over time
+    pub fn is_animated(&self) -> bool {
+        !self.animations.is_empty() || !self.scrolls.is_empty()
+    }
+
+    fn load(&mut self, tex_name: &str) -> Result<&Texture, AssetError> {
+        let mut tex = match &self.source {
+            TextureSource::Directory(tex_dir) => {
+                let tex_path = tex_dir.join(tex_name.to_owned() + ".png");
+                println!("Loading tex \"{}\" from {}", tex_name, tex_path.display());
+
+                Texture::new(&tex_path)?
+            }
+            TextureSource::Pack(pack) => {
+                println!("Loading tex \"{}\" from {}", tex_name, pack.path().display());
+
+                let tex_error = |err: &str| AssetError::Texture(pack.path().join(tex_name), String::from(err));
+                let entry = pack
+                    .entry(tex_name)
+                    .ok_or_else(|| tex_error("not in the asset pack"))?;
+                let (width, height) = match entry.kind {
+                    EntryKind::Texture { width, height } => (width, height),
+                    EntryKind::File => return Err(tex_error("not a texture")),
+                };
+
+                Texture::from_rgba(width, height, &pack.read(entry)?).map_err(|err| tex_error(&err))?
+            }
+        };
+        tex.mips = TextureLoader::build_mip_chain(&tex);
+        self.textures.insert(String::from(tex_name), tex);
+
+        Ok(&self.textures[tex_name])
+    }
+
+    /// Builds the full mip chain down to 1x1 by averaging 2x2 texel blocks.
+    /// Colors are weighted by alpha so transparent texels don't bleed into
+    /// their neighbours.
+    fn build_mip_chain(tex: &Texture) -> Vec<MipLevel> {
+        let width = tex.surface.width();
+        let height = tex.surface.height();
+        let pitch = tex.surface.pitch() as usize;
+        let surface_buf = tex.surface.without_lock().unwrap();
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for x in 0..width as usize {
+            for y in 0..height as usize {
+                let index = y * pitch + x * TEXTURE_BYTES_PER_PIXEL;
+                let mut texel = [0; 4];
+                texel.copy_from_slice(&surface_buf[index..index + TEXTURE_BYTES_PER_PIXEL]);
+                texels.push(texel);
+            }
+        }
+
+        let mut mips = vec![MipLevel {
+            width,
+            height,
+            texels,
+        }];
+
+        loop {
+            let prev = mips.last().unwrap();
+            if prev.width == 1 && prev.height == 1 {
+                break;
+            }
 
-        let mut tex_dir_path = env::current_exe().unwrap();
-        tex_dir_path.pop();
-        tex_dir_path.pop();
-        tex_dir_path.pop();
-        tex_dir_path.push(Path::new("textures"));
+            let width = (prev.width / 2).max(1);
+            let height = (prev.height / 2).max(1);
+            let mut texels = Vec::with_capacity((width * height) as usize);
+            for x in 0..width {
+                for y in 0..height {
+                    let block = [
+                        prev.texel((x * 2) as i32, (y * 2) as i32),
+                        prev.texel((x * 2 + 1).min(prev.width - 1) as i32, (y * 2) as i32),
+                        prev.texel((x * 2) as i32, (y * 2 + 1).min(prev.height - 1) as i32),
+                        prev.texel(
+                            (x * 2 + 1).min(prev.width - 1) as i32,
+                            (y * 2 + 1).min(prev.height - 1) as i32,
+                        ),
+                    ];
 
-        for entry in fs::read_dir(tex_dir_path.into_boxed_path()).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            let entry_path = String::from(path.to_str().unwrap());
+                    let alpha: u32 = block.iter().map(|t| t[3] as u32).sum();
+                    let weighted = |channel: usize| {
+                        if alpha == 0 {
+                            return 0;
+                        }
+                        let sum: u32 = block.iter().map(|t| t[channel] as u32 * t[3] as u32).sum();
+                        (sum / alpha) as u8
+                    };
 
-            if fs::metadata(entry.path()).unwrap().is_file() &&
-               entry_path.ends_with(".png") {
-                tex_names.push(entry_path);
+                    texels.push([weighted(0), weighted(1), weighted(2), (alpha / 4) as u8]);
+                }
             }
+
+            mips.push(MipLevel {
+                width,
+                height,
+                texels,
+            });
         }
 
-        tex_names
+        mips
     }
 
-    pub fn load_texture(&mut self, tex_name: &str) -> Option<&Texture> {
-        if self.textures.contains_key(tex_name) {
-            return self.textures.get(tex_name)
+    /// Returns the named texture, loading it if it hasn't been yet. Textures
+    /// that can't be loaded are replaced with a placeholder, which is kept so
+    /// the failure is only reported once.
+    pub fn load_texture(&mut self, tex_name: &str) -> &Texture {
+        if self.animations.contains_key(tex_name) {
+            return self.texture(tex_name).unwrap();
         }
 
-        let mut tex_path = env::current_exe().unwrap();
-        tex_path.pop();
-        tex_path.pop();
-        tex_path.pop();
-        tex_path.push(Path::new("textures"));
-        let tex_filename = tex_name.to_owned() + ".png";
-        tex_path.push(Path::new(&tex_filename));
+        if !self.textures.contains_key(tex_name) {
+            if let Err(err) = self.load(tex_name) {
+                println!("Warning: {}, using a placeholder for \"{}\"", err, tex_name);
+                let mut tex = Texture::placeholder(tex_name);
+                tex.mips = TextureLoader::build_mip_chain(&tex);
+                self.textures.insert(String::from(tex_name), tex);
+            }
+        }
 
-        self.load(tex_path.to_str().unwrap(), tex_name)
+        &self.textures[tex_name]
     }
 
+    /// Adds a texture that didn't come from the textures folder or the asset
+    /// pack, replacing any loaded texture of the same name
+    pub fn insert(&mut self, tex_name: &str, mut tex: Texture) {
+        tex.mips = TextureLoader::build_mip_chain(&tex);
+        self.textures.insert(String::from(tex_name), tex);
+        if !self.inserted.iter().any(|inserted| inserted == tex_name) {
+            self.inserted.push(String::from(tex_name));
+        }
+    }
+
+    /// Looks up a loaded texture. Animations resolve to their current frame.
     pub fn texture(&self, tex_name: &str) -> Option<&Texture> {
+        let tex_name = match self.animations.get(tex_name) {
+            Some(animation) => animation.frame_at(self.clock_us),
+            None => tex_name,
+        };
+
         self.textures.get(tex_name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sdl2::pixels::Palette;
+
+    use super::*;
+
+    const RED: Color = Color::RGB(255, 0, 0);
+    const GREEN: Color = Color::RGB(0, 255, 0);
+    const BLUE: Color = Color::RGB(0, 0, 255);
+    const WHITE: Color = Color::RGB(255, 255, 255);
+    const BLACK: Color = Color::RGB(0, 0, 0);
+    /// Padding bytes at the end of the surface rows
+    const PAD: u8 = 0xee;
+
+    fn texel(color: Color) -> Texel {
+        [color.r, color.g, color.b, color.a]
+    }
+
+    fn pixels(tex: &Texture) -> Vec<Color> {
+        let (width, height) = (tex.surface().width(), tex.surface().height());
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| tex.pixel(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn converts_rgb24_with_padded_pitch() {
+        let mut data = vec![
+            255, 0, 0, 0, 255, 0, 255, 0, 255, PAD, PAD, PAD, //
+            0, 0, 255, 255, 255, 255, 0, 0, 0, PAD, PAD, PAD,
+        ];
+        let surface = Surface::from_data(&mut data, 3, 2, 12, PixelFormatEnum::RGB24).unwrap();
+        let tex = Texture::from_surface(surface).unwrap();
+
+        let clear = Color::RGBA(255, 0, 255, 0);
+        assert_eq!(pixels(&tex), vec![RED, GREEN, clear, BLUE, WHITE, BLACK]);
+    }
+
+    #[test]
+    fn converts_paletted_with_color_key() {
+        let mut data = vec![
+            0, 1, 2, PAD, //
+            2, 1, 0, PAD,
+        ];
+        let mut surface = Surface::from_data(&mut data, 3, 2, 4, PixelFormatEnum::Index8).unwrap();
+        let palette = Palette::with_colors(&[RED, GREEN, BLUE]).unwrap();
+        surface.set_palette(&palette).unwrap();
+        surface.set_color_key(true, GREEN).unwrap();
+        let tex = Texture::from_surface(surface).unwrap();
+
+        let pixels = pixels(&tex);
+        assert_eq!(pixels[0], RED);
+        assert_eq!(pixels[1].a, 0);
+        assert_eq!(pixels[2], BLUE);
+        assert_eq!(pixels[3], BLUE);
+        assert_eq!(pixels[4].a, 0);
+        assert_eq!(pixels[5], RED);
+    }
+
+    #[test]
+    fn converts_rgba32_keeping_alpha() {
+        let mut data = vec![
+            255, 0, 0, 255, 0, 255, 0, 255, PAD, PAD, PAD, PAD, //
+            0, 0, 255, 255, 255, 0, 0, 128, PAD, PAD, PAD, PAD, //
+            255, 0, 255, 255, 0, 0, 0, 255, PAD, PAD, PAD, PAD,
+        ];
+        let surface = Surface::from_data(&mut data, 2, 3, 12, PixelFormatEnum::RGBA32).unwrap();
+        let tex = Texture::from_surface(surface).unwrap();
+
+        // Opaque magenta stays opaque when the format has its own alpha
+        let half_red = Color::RGBA(255, 0, 0, 128);
+        assert_eq!(pixels(&tex), vec![RED, GREEN, BLUE, half_red, TRANSPARENT_COLOR, BLACK]);
+    }
+
+    #[test]
+    fn samples_wrap_around() {
+        let mut data = vec![
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, //
+            255, 255, 255, 255, 0, 0, 0, 255, 255, 0, 0, 255,
+        ];
+        let surface = Surface::from_data(&mut data, 3, 2, 12, PixelFormatEnum::RGBA32).unwrap();
+        let mut tex = Texture::from_surface(surface).unwrap();
+        tex.mips = TextureLoader::build_mip_chain(&tex);
+
+        assert_eq!(tex.sample(0.5, 0.25, 0, TextureFilter::Nearest), texel(GREEN));
+        assert_eq!(tex.sample(0.5, 0.75, 0, TextureFilter::Nearest), texel(BLACK));
+        assert_eq!(tex.sample(1.9, -0.25, 0, TextureFilter::Nearest), texel(RED));
+    }
+
+    #[test]
+    fn mips_are_stored_column_by_column() {
+        let mut data = vec![
+            255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, //
+            255, 255, 255, 255, 0, 0, 0, 255, 255, 0, 0, 255,
+        ];
+        let surface = Surface::from_data(&mut data, 3, 2, 12, PixelFormatEnum::RGBA32).unwrap();
+        let mut tex = Texture::from_surface(surface).unwrap();
+        tex.mips = TextureLoader::build_mip_chain(&tex);
+
+        let texels: Vec<Texel> = [RED, WHITE, GREEN, BLACK, BLUE, RED].iter().cloned().map(texel).collect();
+        assert_eq!(tex.mips[0].texels, texels);
+        assert_eq!(tex.mips[0].column(1), &texels[2..4]);
+        assert_eq!(tex.mips[0].column(-1), &texels[4..6]);
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::assets::AssetError;
+use crate::map;
+use crate::triggers::Trigger;
+use crate::vector::Vec2f;
+
+const BUILTIN_WIDTH: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThingKind {
+    Door,
+    Key,
+    Item,
+    Enemy,
+}
+
+impl ThingKind {
+    /// Name in map files
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThingKind::Door => "door",
+            ThingKind::Key => "key",
+            ThingKind::Item => "item",
+            ThingKind::Enemy => "enemy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ThingKind> {
+        match name {
+            "door" => Some(ThingKind::Door),
+            "key" => Some(ThingKind::Key),
+            "item" => Some(ThingKind::Item),
+            "enemy" => Some(ThingKind::Enemy),
+            _ => None,
+        }
+    }
+}
+
+/// Something on the map besides its walls. Keys and items are picked up by
+/// walking over them, there's nothing to open doors or fight with yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thing {
+    pub kind: ThingKind,
+    /// Center of the thing's tile
+    pub pos: Vec2f,
+}
+
+/// The level's tile grid. It starts out as the built in map or is read from
+/// a map file, which has one row of whitespace separated tile ids per line:
+///
+///     # Comments start with a hash
+///     1 1 1 1
+///     1 0 x 1
+///     1 1 1 1
+///
+/// An `x` marks the player's spawn on an empty tile. Lines like
+/// `tex 13 walls/wood` change the texture a tile id is drawn with and lines
+/// like `thing key 4 2` put a thing on the tile in column 4, row 2. Walking
+/// into a `map::EXIT_TILE` ends the level, lines like `exit 21` make other
+/// tile ids exits too. Lines like `push 4 0` make the wall in column 4, row 0
+/// a push wall hiding a secret. Lines starting with `trigger` set up
+/// triggers, see `Trigger`, and `script levels/vault.rhai` names the level
+/// script, relative to the map file.
+#[derive(Clone)]
+pub struct TileMap {
+    width: usize,
+    height: usize,
+    tiles: Vec<u16>,
+    spawn: Option<Vec2f>,
+    things: Vec<Thing>,
+    /// Tile ids that end the level when the player walks into them
+    exit_tiles: Vec<u16>,
+    /// Columns and rows of the walls that slide away when used
+    push_walls: Vec<(usize, usize)>,
+    triggers: Vec<Trigger>,
+    /// Level script as written in the map file, see `scripting::Scripts`
+    script: Option<String>,
+    /// Textures that replace the default ones of `map::texture_name`
+    textures: HashMap<u16, String>,
+    /// Tells maps apart, e.g. in save games
+    name: String,
+    /// File the map was read from, None for the built in map
+    path: Option<PathBuf>,
+    /// Bumped every time the tiles are replaced
+    revision: u32,
+}
+
+impl TileMap {
+    pub fn builtin() -> TileMap {
+        let mut tile_map = TileMap::new(BUILTIN_WIDTH, map::MAP.len() / BUILTIN_WIDTH, map::MAP.to_vec(), None);
+        tile_map.name = String::from("builtin");
+        // A secret alcove in the bottom left corner
+        tile_map.push_walls.push((3, 17));
+        tile_map.things.push(Thing {
+            kind: ThingKind::Item,
+            pos: Vec2f::new(2.5, 17.5),
+        });
+
+        tile_map
+    }
+
+    /// `tiles` are row by row, `width` tiles per row
+    pub fn new(width: usize, height: usize, tiles: Vec<u16>, spawn: Option<Vec2f>) -> TileMap {
+        assert_eq!(tiles.len(), width * height, "Tile count doesn't match the map size");
+
+        TileMap {
+            width,
+            height,
+            tiles,
+            spawn,
+            things: vec![],
+            exit_tiles: vec![map::EXIT_TILE],
+            push_walls: vec![],
+            triggers: vec![],
+            script: None,
+            textures: HashMap::new(),
+            name: String::new(),
+            path: None,
+            revision: 0,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<TileMap, AssetError> {
+        let text = fs::read_to_string(path).map_err(|err| AssetError::Io(path.to_path_buf(), err))?;
+        let mut tile_map = TileMap::parse(path, &text)?;
+        tile_map.path = Some(path.to_path_buf());
+        tile_map.name = path.display().to_string();
+
+        Ok(tile_map)
+    }
+
+    /// `path` is only used for error reporting
+    pub fn parse(path: &Path, text: &str) -> Result<TileMap, AssetError> {
+        let mut width = 0;
+        let mut tiles = vec![];
+        let mut spawn = None;
+        let mut textures = HashMap::new();
+        let mut things = vec![];
+        let mut exit_tiles = vec![map::EXIT_TILE];
+        let mut push_walls = vec![];
+        let mut triggers = vec![];
+        let mut script = None;
+        let mut height = 0;
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let config_error = |err: &str| {
+                AssetError::Config(path.to_path_buf(), format!("line {}: {}", line_index + 1, err))
+            };
+            if line.starts_with("tex ") {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() != 3 {
+                    return Err(config_error("expected \"tex <tile id> <texture name>\""));
+                }
+                let tile = words[1]
+                    .parse::<u16>()
+                    .map_err(|_| config_error(&format!("\"{}\" is not a tile id", words[1])))?;
+                textures.insert(tile, String::from(words[2]));
+                continue;
+            }
+            if line.starts_with("exit ") {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() != 2 {
+                    return Err(config_error("expected \"exit <tile id>\""));
+                }
+                let tile = words[1]
+                    .parse::<u16>()
+                    .map_err(|_| config_error(&format!("\"{}\" is not a tile id", words[1])))?;
+                exit_tiles.push(tile);
+                continue;
+            }
+            if line.starts_with("push ") {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() != 3 {
+                    return Err(config_error("expected \"push <column> <row>\""));
+                }
+                let coordinate = |word: &str| {
+                    word.parse::<usize>()
+                        .map_err(|_| config_error(&format!("\"{}\" is not a column or row", word)))
+                };
+                push_walls.push((coordinate(words[1])?, coordinate(words[2])?));
+                continue;
+            }
+            if line.starts_with("script ") {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() != 2 {
+                    return Err(config_error("expected \"script <path>\""));
+                }
+                script = Some(String::from(words[1]));
+                continue;
+            }
+            if line.starts_with("trigger ") {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                triggers.push(Trigger::parse(&words[1..]).map_err(|err| config_error(&err))?);
+                continue;
+            }
+            if line.starts_with("thing ") {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() != 4 {
+                    return Err(config_error("expected \"thing <kind> <column> <row>\""));
+                }
+                let kind = ThingKind::from_name(words[1])
+                    .ok_or_else(|| config_error(&format!("\"{}\" is not a kind of thing", words[1])))?;
+                let coordinate = |word: &str| {
+                    word.parse::<usize>()
+                        .map_err(|_| config_error(&format!("\"{}\" is not a column or row", word)))
+                };
+                let (column, row) = (coordinate(words[2])?, coordinate(words[3])?);
+                things.push(Thing {
+                    kind,
+                    pos: Vec2f::new(column as f32 + 0.5, row as f32 + 0.5),
+                });
+                continue;
+            }
+
+            let mut row = vec![];
+            for word in line.split_whitespace() {
+                if word == "x" {
+                    if spawn.is_some() {
+                        return Err(config_error("more than one spawn"));
+                    }
+                    spawn = Some(Vec2f::new(row.len() as f32 + 0.5, height as f32 + 0.5));
+                    row.push(0);
+                    continue;
+                }
+
+                let tile = word
+                    .parse::<u16>()
+                    .map_err(|_| config_error(&format!("\"{}\" is not a tile id", word)))?;
+                row.push(tile);
+            }
+
+            if height == 0 {
+                width = row.len();
+            } else if row.len() != width {
+                return Err(config_error(&format!("expected {} tiles, found {}", width, row.len())));
+            }
+            tiles.extend(row);
+            height += 1;
+        }
+
+        if tiles.is_empty() {
+            return Err(AssetError::Config(path.to_path_buf(), String::from("the map has no tiles")));
+        }
+        if things
+            .iter()
+            .any(|thing| thing.pos.x() >= width as f32 || thing.pos.y() >= height as f32)
+        {
+            return Err(AssetError::Config(path.to_path_buf(), String::from("a thing is outside the map")));
+        }
+        if push_walls.iter().any(|(column, row)| *column >= width || *row >= height) {
+            return Err(AssetError::Config(path.to_path_buf(), String::from("a push wall is outside the map")));
+        }
+        if triggers.iter().any(|trigger| !trigger.fits(width, height)) {
+            return Err(AssetError::Config(path.to_path_buf(), String::from("a trigger is outside the map")));
+        }
+
+        let mut tile_map = TileMap::new(width, height, tiles, spawn);
+        tile_map.textures = textures;
+        tile_map.things = things;
+        tile_map.exit_tiles = exit_tiles;
+        tile_map.push_walls = push_walls;
+        tile_map.triggers = triggers;
+        tile_map.script = script;
+
+        Ok(tile_map)
+    }
+
+    /// Reads the map file again. The current tiles are kept if that fails.
+    pub fn reload(&mut self) -> Result<(), AssetError> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+
+        self.replace(TileMap::load(&path)?);
+
+        Ok(())
+    }
+
+    /// Takes over the other map's tiles, spawn, things, exits, push walls,
+    /// triggers, script and textures but keeps this map's name and path
+    pub fn replace(&mut self, other: TileMap) {
+        self.width = other.width;
+        self.height = other.height;
+        self.tiles = other.tiles;
+        self.spawn = other.spawn;
+        self.things = other.things;
+        self.exit_tiles = other.exit_tiles;
+        self.push_walls = other.push_walls;
+        self.triggers = other.triggers;
+        self.script = other.script;
+        self.textures = other.textures;
+        self.revision += 1;
+    }
+
+    /// Writes the map in the map file format, see `to_text`
+    pub fn save(&self, path: &Path) -> Result<(), AssetError> {
+        fs::write(path, self.to_text()).map_err(|err| AssetError::Io(path.to_path_buf(), err))
+    }
+
+    /// The map in the format `parse` reads. The spawn is only written if its
+    /// tile is empty, as an `x` stands in for the tile id.
+    pub fn to_text(&self) -> String {
+        let spawn_index = self.spawn.map(|spawn| spawn.y() as usize * self.width + spawn.x() as usize);
+        let column_width = self.tiles.iter().map(|tile| tile.to_string().len()).max().unwrap_or(1);
+
+        let mut text = String::new();
+        for (row_index, row) in self.tiles.chunks(self.width).enumerate() {
+            let words: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(column, tile)| {
+                    let index = row_index * self.width + column;
+                    if spawn_index == Some(index) && *tile == 0 {
+                        format!("{:>width$}", "x", width = column_width)
+                    } else {
+                        format!("{:>width$}", tile, width = column_width)
+                    }
+                })
+                .collect();
+            text.push_str(words.join(" ").trim_end());
+            text.push('\n');
+        }
+
+        let mut textures: Vec<(&u16, &String)> = self.textures.iter().collect();
+        textures.sort();
+        for (tile, tex_name) in textures {
+            text.push_str(&format!("tex {} {}\n", tile, tex_name));
+        }
+        for tile in self.exit_tiles.iter().filter(|tile| **tile != map::EXIT_TILE) {
+            text.push_str(&format!("exit {}\n", tile));
+        }
+        for (column, row) in self.push_walls.iter() {
+            text.push_str(&format!("push {} {}\n", column, row));
+        }
+        if let Some(script) = &self.script {
+            text.push_str(&format!("script {}\n", script));
+        }
+        for trigger in self.triggers.iter() {
+            text.push_str(&trigger.to_text());
+            text.push('\n');
+        }
+        for thing in self.things.iter() {
+            text.push_str(&format!(
+                "thing {} {} {}\n",
+                thing.kind.name(),
+                thing.pos.x() as usize,
+                thing.pos.y() as usize
+            ));
+        }
+
+        text
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Tiles row by row, `width` tiles per row
+    pub fn tiles(&self) -> &[u16] {
+        &self.tiles
+    }
+
+    /// Changes a single tile, nothing happens outside the map
+    pub fn set_tile(&mut self, column: usize, row: usize, tile: u16) {
+        if column >= self.width || row >= self.height {
+            return;
+        }
+
+        let index = row * self.width + column;
+        if self.tiles[index] != tile {
+            self.tiles[index] = tile;
+            self.revision += 1;
+        }
+    }
+
+    /// Center of the spawn tile, if the map has one
+    pub fn spawn(&self) -> Option<Vec2f> {
+        self.spawn
+    }
+
+    pub fn set_spawn(&mut self, spawn: Option<Vec2f>) {
+        self.spawn = spawn;
+    }
+
+    /// Doors, keys, items and enemies on the map
+    pub fn things(&self) -> &[Thing] {
+        &self.things
+    }
+
+    pub fn add_thing(&mut self, thing: Thing) {
+        self.things.push(thing);
+    }
+
+    /// Removes the things on a tile, returns whether there were any
+    pub fn remove_things_at(&mut self, column: usize, row: usize) -> bool {
+        !self.take_things_at(column, row, |_| true).is_empty()
+    }
+
+    /// Removes and returns the things on a tile that `filter` accepts
+    pub fn take_things_at<F>(&mut self, column: usize, row: usize, filter: F) -> Vec<Thing>
+    where
+        F: Fn(&Thing) -> bool,
+    {
+        let (taken, kept) = self
+            .things
+            .iter()
+            .partition(|thing| thing.pos.x() as usize == column && thing.pos.y() as usize == row && filter(thing));
+        self.things = kept;
+
+        taken
+    }
+
+    pub fn is_exit(&self, tile: u16) -> bool {
+        self.exit_tiles.contains(&tile)
+    }
+
+    /// Makes walking into tiles with the id end the level, besides
+    /// `map::EXIT_TILE`
+    pub fn add_exit_tile(&mut self, tile: u16) {
+        if !self.exit_tiles.contains(&tile) {
+            self.exit_tiles.push(tile);
+        }
+    }
+
+    /// Columns and rows of the push walls that haven't been pushed yet
+    pub fn push_walls(&self) -> &[(usize, usize)] {
+        &self.push_walls
+    }
+
+    pub fn add_push_wall(&mut self, column: usize, row: usize) {
+        if !self.push_walls.contains(&(column, row)) {
+            self.push_walls.push((column, row));
+        }
+    }
+
+    /// Stops the wall from being a push wall, returns whether it was one
+    pub fn take_push_wall(&mut self, column: usize, row: usize) -> bool {
+        let count = self.push_walls.len();
+        self.push_walls.retain(|push_wall| *push_wall != (column, row));
+
+        self.push_walls.len() != count
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    pub fn add_trigger(&mut self, trigger: Trigger) {
+        self.triggers.push(trigger);
+    }
+
+    pub fn script(&self) -> Option<&str> {
+        self.script.as_deref()
+    }
+
+    /// Draws every tile with the given id with the named texture
+    pub fn set_texture(&mut self, tile: u16, tex_name: &str) {
+        self.textures.insert(tile, String::from(tex_name));
+    }
+
+    pub fn texture_name(&self, tile: u16) -> &str {
+        match self.textures.get(&tile) {
+            Some(tex_name) => tex_name,
+            None => map::texture_name(tile),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = String::from(name);
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn set_path(&mut self, path: &Path) {
+        self.path = Some(path.to_path_buf());
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+}
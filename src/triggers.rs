@@ -0,0 +1,283 @@
+use crate::events::GameEvent;
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::tile_map::ThingKind;
+
+/// What sets a trigger off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerKind {
+    /// The player steps into the trigger's tiles
+    Enter,
+    /// The player steps out of them
+    Exit,
+    /// The player uses one of them, standing on it or facing it
+    Use,
+    /// The last enemy on the map is gone
+    KillAll,
+}
+
+impl TriggerKind {
+    /// Name in map files
+    pub fn name(&self) -> &'static str {
+        match self {
+            TriggerKind::Enter => "enter",
+            TriggerKind::Exit => "exit",
+            TriggerKind::Use => "use",
+            TriggerKind::KillAll => "kill-all",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TriggerKind> {
+        match name {
+            "enter" => Some(TriggerKind::Enter),
+            "exit" => Some(TriggerKind::Exit),
+            "use" => Some(TriggerKind::Use),
+            "kill-all" => Some(TriggerKind::KillAll),
+            _ => None,
+        }
+    }
+}
+
+/// Posts its action on the game's event bus when set off, once per level.
+/// Map files define triggers with lines like
+///
+///     trigger enter 4 2 3 1 message Something moved
+///     trigger kill-all end-level
+///
+/// where the four numbers are the column, row, width and height of the
+/// trigger's tiles. Kill-all triggers don't cover any tiles.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trigger {
+    pub kind: TriggerKind,
+    /// Column, row, width and height of the tiles, None for kill-all
+    /// triggers
+    pub region: Option<(usize, usize, usize, usize)>,
+    pub action: GameEvent,
+}
+
+impl Trigger {
+    /// Reads the words following `trigger` on a map file line
+    pub fn parse(words: &[&str]) -> Result<Trigger, String> {
+        let kind = match words.first() {
+            Some(name) => {
+                TriggerKind::from_name(name).ok_or_else(|| format!("\"{}\" is not a kind of trigger", name))?
+            }
+            None => return Err(String::from("expected \"trigger <kind> <column> <row> <width> <height> <action>\"")),
+        };
+        if kind == TriggerKind::KillAll {
+            return Ok(Trigger {
+                kind,
+                region: None,
+                action: GameEvent::parse_action(&words[1..])?,
+            });
+        }
+
+        if words.len() < 6 {
+            return Err(String::from("expected \"trigger <kind> <column> <row> <width> <height> <action>\""));
+        }
+        let number = |word: &str| {
+            word.parse::<usize>()
+                .map_err(|_| format!("\"{}\" is not a column, row, width or height", word))
+        };
+        let region = (number(words[1])?, number(words[2])?, number(words[3])?, number(words[4])?);
+        if region.2 == 0 || region.3 == 0 {
+            return Err(String::from("a trigger needs a width and height of at least 1"));
+        }
+
+        Ok(Trigger {
+            kind,
+            region: Some(region),
+            action: GameEvent::parse_action(&words[5..])?,
+        })
+    }
+
+    /// The trigger in the format `parse` reads, `trigger` included
+    pub fn to_text(&self) -> String {
+        let action = self.action.to_action_text().unwrap_or_default();
+        match self.region {
+            Some((column, row, width, height)) => format!(
+                "trigger {} {} {} {} {} {}",
+                self.kind.name(),
+                column,
+                row,
+                width,
+                height,
+                action
+            ),
+            None => format!("trigger {} {}", self.kind.name(), action),
+        }
+    }
+
+    pub fn contains(&self, column: usize, row: usize) -> bool {
+        // Regions reaching past the largest index cover everything after
+        // their start
+        let within = |value: usize, start: usize, len: usize| {
+            start <= value && start.checked_add(len).map_or(true, |end| value < end)
+        };
+
+        match self.region {
+            Some((left, top, width, height)) => within(column, left, width) && within(row, top, height),
+            None => false,
+        }
+    }
+
+    /// Whether all of the trigger's tiles are on a map of the size
+    pub fn fits(&self, map_width: usize, map_height: usize) -> bool {
+        match self.region {
+            Some((left, top, width, height)) => {
+                left.checked_add(width).map_or(false, |right| right <= map_width)
+                    && top.checked_add(height).map_or(false, |bottom| bottom <= map_height)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Sets off the map's triggers
+pub struct Triggers {
+    /// Whether each of the map's triggers has fired already
+    fired: Vec<bool>,
+    /// Whether the player was on each trigger's tiles last frame
+    inside: Vec<bool>,
+    enemies_left: usize,
+}
+
+impl Triggers {
+    pub fn new() -> Triggers {
+        Triggers {
+            fired: vec![],
+            inside: vec![],
+            enemies_left: 0,
+        }
+    }
+
+    fn fire(&mut self, game: &Game, index: usize, trigger: &Trigger) {
+        if !self.fired[index] {
+            self.fired[index] = true;
+            game.post_event(trigger.action.clone());
+        }
+    }
+
+    /// Starts over when the map's triggers changed, e.g. after a reload
+    fn fit(&mut self, trigger_count: usize) {
+        if self.fired.len() != trigger_count {
+            self.fired = vec![false; trigger_count];
+            self.inside = vec![false; trigger_count];
+        }
+    }
+}
+
+impl GameComponent for Triggers {
+    fn update(&mut self, game: &Game, _: u32) {
+        let tile_map = game.tile_map();
+        self.fit(tile_map.triggers().len());
+
+        let enemies_left = tile_map
+            .things()
+            .iter()
+            .filter(|thing| thing.kind == ThingKind::Enemy)
+            .count();
+        let all_killed = self.enemies_left > 0 && enemies_left == 0;
+        self.enemies_left = enemies_left;
+
+        let player_tile = game.player_tile();
+        for (index, trigger) in tile_map.triggers().iter().enumerate() {
+            let inside = player_tile.map_or(false, |(column, row)| trigger.contains(column, row));
+            let fires = match trigger.kind {
+                TriggerKind::Enter => inside && !self.inside[index],
+                TriggerKind::Exit => !inside && self.inside[index],
+                TriggerKind::Use => false,
+                TriggerKind::KillAll => all_killed,
+            };
+            self.inside[index] = inside;
+            if fires {
+                self.fire(game, index, trigger);
+            }
+        }
+    }
+
+    fn handle_game_event(&mut self, game: &Game, event: &GameEvent) {
+        if *event != GameEvent::Use {
+            return;
+        }
+
+        let (column, row) = match game.player_tile() {
+            Some(player_tile) => player_tile,
+            None => return,
+        };
+        let (step_x, step_y) = game.player().facing();
+        let faced_column = column as isize + step_x;
+        let faced_row = row as isize + step_y;
+
+        let tile_map = game.tile_map();
+        self.fit(tile_map.triggers().len());
+        for (index, trigger) in tile_map.triggers().iter().enumerate() {
+            let faced = faced_column >= 0
+                && faced_row >= 0
+                && trigger.contains(faced_column as usize, faced_row as usize);
+            if trigger.kind == TriggerKind::Use && (trigger.contains(column, row) || faced) {
+                self.fire(game, index, trigger);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::tile_map::TileMap;
+
+    fn trigger(line: &str) -> Result<Trigger, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        Trigger::parse(&words)
+    }
+
+    #[test]
+    fn parses_and_writes_triggers() {
+        for line in [
+            "enter 4 2 3 1 message Something moved",
+            "use 9 9 1 1 kill-enemy 14 6",
+            "kill-all end-level",
+        ]
+        .iter()
+        {
+            assert_eq!(trigger(line).unwrap().to_text(), format!("trigger {}", line));
+        }
+
+        assert_eq!(
+            trigger("use 9 9 1 1 kill-enemy 14 6").unwrap().action,
+            GameEvent::KillEnemy { column: 14, row: 6 }
+        );
+        assert!(trigger("enter 4 2 0 1 end-level").is_err());
+        assert!(trigger("enter 4 2 1 end-level").is_err());
+    }
+
+    #[test]
+    fn contains_handles_huge_regions() {
+        let huge = trigger(&format!("enter 2 3 {} {} end-level", usize::max_value(), usize::max_value())).unwrap();
+
+        assert!(huge.contains(2, 3));
+        assert!(huge.contains(usize::max_value(), usize::max_value()));
+        assert!(!huge.contains(1, 3));
+        assert!(!huge.fits(100, 100));
+
+        let small = trigger("enter 2 3 2 1 end-level").unwrap();
+        assert!(small.contains(3, 3));
+        assert!(!small.contains(4, 3));
+        assert!(!small.contains(2, 4));
+        assert!(small.fits(4, 4));
+        assert!(!small.fits(3, 4));
+    }
+
+    #[test]
+    fn maps_reject_triggers_outside_them() {
+        let map = |trigger: &str| TileMap::parse(Path::new("test.map"), &format!("{}\n1 1 1\n1 x 1\n1 1 1\n", trigger));
+
+        assert!(map("trigger enter 0 0 3 3 end-level").is_ok());
+        assert!(map("trigger kill-all end-level").is_ok());
+        assert!(map("trigger enter 1 1 3 1 end-level").is_err());
+        assert!(map(&format!("trigger use 1 {} 1 1 end-level", usize::max_value())).is_err());
+    }
+}
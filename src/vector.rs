@@ -7,7 +7,7 @@ pub struct Vec2f {
 }
 
 impl Vec2f {
-    pub fn new(x: f32, y: f32) -> Vec2f {
+    pub const fn new(x: f32, y: f32) -> Vec2f {
         Vec2f { x, y }
     }
 
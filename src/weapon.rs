@@ -0,0 +1,115 @@
+use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::surface::Surface;
+
+use crate::game::Game;
+use crate::game::GameComponent;
+use crate::game::GameOpts;
+use crate::render_precedence::RenderPrecedence;
+use crate::textures::TRANSPARENT_COLOR;
+use crate::vector::Vec2f;
+
+const WEAPON_TEX_SIZE: u32 = 64;
+/// How far in front of the eyes the weapon is held, in wall heights
+const WEAPON_DISTANCE: f32 = 0.5;
+/// How far below the eyes the weapon's centre is held, in wall heights
+const WEAPON_DROP: f32 = 0.0625;
+/// Width and height of the weapon, in wall heights
+const WEAPON_SIZE: f32 = 0.125;
+
+/// The weapon held in front of the camera
+pub struct Weapon {
+    weapon_tex: Surface<'static>,
+    /// The projected weapon, the size of the screen
+    weapon_view: Surface<'static>,
+    sway: Vec2f,
+    /// Sway the weapon view was last drawn with
+    drawn_sway: Option<Vec2f>,
+}
+
+impl Weapon {
+    pub fn new(game_opts: &GameOpts) -> Weapon {
+        let mut weapon_tex = Surface::new(WEAPON_TEX_SIZE, WEAPON_TEX_SIZE, PixelFormatEnum::RGB24).unwrap();
+        draw_pistol(&mut weapon_tex);
+        weapon_tex
+            .set_color_key(true, TRANSPARENT_COLOR)
+            .expect("Failed setting weapon color key");
+
+        let mut weapon_view =
+            Surface::new(game_opts.screen_width, game_opts.screen_height, PixelFormatEnum::RGB24).unwrap();
+        weapon_view
+            .set_color_key(true, TRANSPARENT_COLOR)
+            .expect("Failed setting weapon color key");
+
+        Weapon {
+            weapon_tex,
+            weapon_view,
+            sway: Vec2f::new(0.0, 0.0),
+            drawn_sway: None,
+        }
+    }
+
+    /// Where the weapon ends up on screen. It's projected the way the camera
+    /// projects walls, from where it's held plus the sway. It's held still
+    /// in front of the eyes, so looking up or down doesn't shear it.
+    fn project(&self) -> Rect {
+        let width = self.weapon_view.width() as f32;
+        let height = self.weapon_view.height() as f32;
+        let pixels_per_unit = 2.0 * height / WEAPON_DISTANCE;
+
+        let center_x = width / 2.0 + self.sway.x() * pixels_per_unit;
+        let center_y = height / 2.0 + (WEAPON_DROP + self.sway.y()) * pixels_per_unit;
+        let size = WEAPON_SIZE * pixels_per_unit;
+
+        Rect::new(
+            (center_x - size / 2.0) as i32,
+            (center_y - size / 2.0) as i32,
+            size as u32,
+            size as u32,
+        )
+    }
+}
+
+fn draw_pistol(surface: &mut Surface) {
+    let parts = [
+        (Rect::new(0, 0, 64, 64), TRANSPARENT_COLOR),
+        // Barrel
+        (Rect::new(28, 8, 8, 24), Color::RGB(70, 70, 80)),
+        // Slide
+        (Rect::new(24, 24, 16, 24), Color::RGB(50, 50, 60)),
+        // Hand
+        (Rect::new(20, 44, 24, 20), Color::RGB(200, 150, 110)),
+    ];
+
+    for (rect, color) in parts.iter() {
+        surface
+            .fill_rect(Some(*rect), *color)
+            .expect("Failed drawing the weapon");
+    }
+}
+
+impl GameComponent for Weapon {
+    fn update(&mut self, game: &Game, _: u32) {
+        self.sway = game.player().weapon_sway();
+    }
+
+    fn draw(&mut self, _: &Game, _: u32) -> Option<&Surface> {
+        if self.drawn_sway != Some(self.sway) {
+            self.drawn_sway = Some(self.sway);
+            let weapon_rect = self.project();
+            self.weapon_view
+                .fill_rect(None, TRANSPARENT_COLOR)
+                .expect("Failed clearing the weapon view");
+            self.weapon_tex
+                .blit_scaled(None, &mut self.weapon_view, weapon_rect)
+                .expect("Failed drawing the weapon");
+        }
+
+        Some(&self.weapon_view)
+    }
+
+    fn render_precendce(&self) -> RenderPrecedence {
+        RenderPrecedence::ViewWeapon
+    }
+}
@@ -0,0 +1,622 @@
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::asset_pack::Reader;
+use crate::assets::AssetError;
+use crate::palette::Palette;
+use crate::palette::PALETTE_FILE_NAME;
+use crate::textures::Texture;
+use crate::textures::TextureLoader;
+use crate::tile_map::Thing;
+use crate::tile_map::ThingKind;
+use crate::tile_map::TileMap;
+use crate::vector::Vec2f;
+
+// Wolfenstein 3D data files, all numbers little endian:
+//
+//     MAPHEAD      RLEW tag u16, then 100 u32 offsets of map headers in
+//                  GAMEMAPS, 0 for no map
+//     GAMEMAPS     map headers and plane data, a map header is:
+//                      plane offsets  3 x u32
+//                      plane sizes    3 x u16, compressed sizes in bytes
+//                      width, height  2 x u16
+//                      name           16 bytes, nul terminated
+//     VSWAP        chunk count u16, first sprite chunk u16, first sound
+//                  chunk u16, chunk count x u32 offsets, chunk count x u16
+//                  sizes. Chunks before the sprites are 64x64 walls of
+//                  palette indices, stored column by column.
+//
+// Planes are Carmack compressed, the result of which is RLEW compressed.
+const MAP_COUNT: usize = 100;
+const PLANE_COUNT: usize = 3;
+const MAP_NAME_LEN: usize = 16;
+const WALL_SIZE: u32 = 64;
+
+/// Carmack compression back references: near ones go a byte's worth of words
+/// back from the end of the output, far ones to a word offset from its start
+const NEAR_TAG: u8 = 0xa7;
+const FAR_TAG: u8 = 0xa8;
+
+/// Wolfenstein wall tiles go from 1 to this
+const LAST_WALL_TILE: u16 = 63;
+/// Imported wall tiles are offset by this so they don't clash with our own
+/// tile ids
+pub const WALL_TILE_BASE: u16 = 100;
+/// The elevator switch wall, which ends the level
+const ELEVATOR_TILE: u16 = 21;
+/// Doors, which are left open as there's nothing to open them with
+const DOOR_TILES: (u16, u16) = (90, 101);
+/// Object plane ids of the player start, facing north, east, south and west
+const PLAYER_START_OBJECTS: (u16, u16) = (19, 22);
+/// Gold and silver key
+const KEY_OBJECTS: (u16, u16) = (43, 44);
+/// Dog food, which counts as food too
+const DOG_FOOD_OBJECT: u16 = 29;
+/// Food, first aid, ammo, weapons, treasure and extra lives
+const ITEM_OBJECTS: (u16, u16) = (47, 56);
+/// Marks the wall it's on as a push wall
+const PUSH_WALL_OBJECT: u16 = 98;
+/// Guards, dogs, officers, SS, mutants, ghosts and bosses, standing or
+/// patrolling on every difficulty
+const ENEMY_OBJECTS: (u16, u16) = (108, 227);
+
+/// Paths of a Wolfenstein 3D (or Spear of Destiny) data set, which all share
+/// an extension like WL6 or SOD
+pub struct WolfFiles {
+    pub maphead: PathBuf,
+    pub gamemaps: PathBuf,
+    pub vswap: PathBuf,
+}
+
+impl WolfFiles {
+    /// Finds the data files in the directory, whatever case their names are in
+    pub fn find(dir: &Path) -> Result<WolfFiles, AssetError> {
+        let entries = fs::read_dir(dir).map_err(|err| AssetError::Io(dir.to_path_buf(), err))?;
+        let mut file_names = vec![];
+        for entry in entries {
+            let entry = entry.map_err(|err| AssetError::Io(dir.to_path_buf(), err))?;
+            if let Some(file_name) = entry.file_name().to_str() {
+                file_names.push(String::from(file_name));
+            }
+        }
+        file_names.sort();
+
+        let find = |stem: &str, extension: &str| {
+            file_names
+                .iter()
+                .find(|file_name| file_name.eq_ignore_ascii_case(&format!("{}.{}", stem, extension)))
+                .map(|file_name| dir.join(file_name))
+        };
+
+        for file_name in file_names.iter() {
+            let extension = match file_name.split_once('.') {
+                Some((stem, extension)) if stem.eq_ignore_ascii_case("maphead") => extension,
+                _ => continue,
+            };
+
+            if let (Some(maphead), Some(gamemaps), Some(vswap)) = (
+                find("maphead", extension),
+                find("gamemaps", extension),
+                find("vswap", extension),
+            ) {
+                return Ok(WolfFiles {
+                    maphead,
+                    gamemaps,
+                    vswap,
+                });
+            }
+        }
+
+        Err(AssetError::Import(
+            dir.to_path_buf(),
+            String::from("no MAPHEAD, GAMEMAPS and VSWAP files with the same extension"),
+        ))
+    }
+}
+
+/// A decompressed map with its three planes: walls and floors, objects and
+/// an unused one
+pub struct WolfMap {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub planes: Vec<Vec<u16>>,
+}
+
+impl WolfMap {
+    /// Converts walls to tiles named `wolf/wall_<chunk>`, as imported by
+    /// `read_walls`, the player start object to the map's spawn and doors,
+    /// keys, pickups and enemies to things. The elevator is the exit.
+    /// Push wall markers make their walls push walls.
+    pub fn to_tile_map(&self) -> TileMap {
+        let mut used_walls = vec![];
+        let tiles = self.planes[0]
+            .iter()
+            .map(|tile| match *tile {
+                wall @ 1..=LAST_WALL_TILE => {
+                    if !used_walls.contains(&wall) {
+                        used_walls.push(wall);
+                    }
+                    WALL_TILE_BASE + wall
+                }
+                _ => 0,
+            })
+            .collect();
+
+        let spawn = self.planes[1]
+            .iter()
+            .position(|object| PLAYER_START_OBJECTS.0 <= *object && *object <= PLAYER_START_OBJECTS.1)
+            .map(|index| Vec2f::new((index % self.width) as f32 + 0.5, (index / self.width) as f32 + 0.5));
+
+        let mut tile_map = TileMap::new(self.width, self.height, tiles, spawn);
+        for wall in used_walls {
+            // Every wall has a light and a dark version, in that order
+            tile_map.set_texture(WALL_TILE_BASE + wall, &wall_texture_name((wall as usize - 1) * 2));
+        }
+        tile_map.add_exit_tile(WALL_TILE_BASE + ELEVATOR_TILE);
+        for (pos, object) in self.objects() {
+            if object == PUSH_WALL_OBJECT {
+                tile_map.add_push_wall(pos.x() as usize, pos.y() as usize);
+                continue;
+            }
+            let within = |ids: (u16, u16)| ids.0 <= object && object <= ids.1;
+            let kind = if within(DOOR_TILES) {
+                ThingKind::Door
+            } else if within(KEY_OBJECTS) {
+                ThingKind::Key
+            } else if object == DOG_FOOD_OBJECT || within(ITEM_OBJECTS) {
+                ThingKind::Item
+            } else if within(ENEMY_OBJECTS) {
+                ThingKind::Enemy
+            } else {
+                continue;
+            };
+            tile_map.add_thing(Thing { kind, pos });
+        }
+
+        tile_map
+    }
+
+    /// Positions of the objects on the object plane, with their ids. Doors
+    /// are left out of the tile map, so they are reported here too.
+    pub fn objects(&self) -> Vec<(Vec2f, u16)> {
+        let to_pos = |index: usize| Vec2f::new((index % self.width) as f32 + 0.5, (index / self.width) as f32 + 0.5);
+        let doors = self.planes[0]
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| DOOR_TILES.0 <= **tile && **tile <= DOOR_TILES.1)
+            .map(|(index, tile)| (to_pos(index), *tile));
+        let objects = self.planes[1]
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| **object != 0)
+            .map(|(index, object)| (to_pos(index), *object));
+
+        doors.chain(objects).collect()
+    }
+}
+
+/// Imports a level, 1 based, from the data files in `dir` and adds the walls
+/// to the texture loader. The walls' colours come from `palette.pal` in the
+/// same directory, as the palette isn't part of the data files.
+pub fn import_level(dir: &Path, level: usize, tex_loader: &mut TextureLoader) -> Result<TileMap, AssetError> {
+    let files = WolfFiles::find(dir)?;
+    let mut maps = read_maps(&files)?;
+    if level == 0 || level > maps.len() {
+        return Err(AssetError::Import(
+            files.gamemaps.clone(),
+            format!("there's no level {}, levels go from 1 to {}", level, maps.len()),
+        ));
+    }
+    let map = maps.swap_remove(level - 1).ok_or_else(|| {
+        AssetError::Import(files.gamemaps.clone(), format!("level {} is empty in this data set", level))
+    })?;
+    println!("Importing level {} \"{}\" from {}", level, map.name, files.gamemaps.display());
+
+    let palette_path = dir.join(PALETTE_FILE_NAME);
+    let palette = if palette_path.is_file() {
+        Palette::load(&palette_path)?
+    } else {
+        println!("Warning: no {}, wall colours will be wrong", palette_path.display());
+        Palette::generated()
+    };
+    for (chunk, wall) in read_walls(&files, &palette)?.into_iter().enumerate() {
+        if let Some(wall) = wall {
+            tex_loader.insert(&wall_texture_name(chunk), wall);
+        }
+    }
+
+    let mut tile_map = map.to_tile_map();
+    tile_map.set_name(&format!("{} level {}", files.gamemaps.display(), level));
+
+    Ok(tile_map)
+}
+
+pub fn wall_texture_name(chunk: usize) -> String {
+    format!("wolf/wall_{}", chunk)
+}
+
+/// Reads every map slot in the data set, in episode order. Slots without a
+/// map are None, so a level's number is always its slot.
+pub fn read_maps(files: &WolfFiles) -> Result<Vec<Option<WolfMap>>, AssetError> {
+    let maphead = fs::read(&files.maphead).map_err(|err| AssetError::Io(files.maphead.clone(), err))?;
+    let gamemaps = fs::read(&files.gamemaps).map_err(|err| AssetError::Io(files.gamemaps.clone(), err))?;
+
+    let import_error = |path: &Path, err: String| AssetError::Import(path.to_path_buf(), err);
+    let mut reader = Reader::new(&maphead, 0);
+    let rlew_tag = reader.u16().map_err(|err| import_error(&files.maphead, err))?;
+    let mut map_offsets = vec![];
+    for _ in 0..MAP_COUNT {
+        match reader.u32() {
+            Ok(0) | Ok(0xffff_ffff) => map_offsets.push(None),
+            Ok(offset) => map_offsets.push(Some(offset as usize)),
+            // Shareware and Spear of Destiny headers can be shorter
+            Err(_) => break,
+        }
+    }
+
+    map_offsets
+        .iter()
+        .enumerate()
+        .map(|(map_index, offset)| match offset {
+            Some(offset) => read_map(&gamemaps, *offset, rlew_tag)
+                .map(Some)
+                .map_err(|err| import_error(&files.gamemaps, format!("map {}: {}", map_index + 1, err))),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+fn read_map(gamemaps: &[u8], offset: usize, rlew_tag: u16) -> Result<WolfMap, String> {
+    let mut reader = Reader::new(gamemaps, offset);
+    let mut plane_offsets = [0; PLANE_COUNT];
+    for plane_offset in plane_offsets.iter_mut() {
+        *plane_offset = reader.u32()? as usize;
+    }
+    let mut plane_sizes = [0; PLANE_COUNT];
+    for plane_size in plane_sizes.iter_mut() {
+        *plane_size = reader.u16()? as usize;
+    }
+    let width = reader.u16()? as usize;
+    let height = reader.u16()? as usize;
+    let name = reader.bytes(MAP_NAME_LEN)?;
+    let name = String::from_utf8_lossy(name.split(|byte| *byte == 0).next().unwrap()).into_owned();
+
+    let mut planes = vec![];
+    for (plane_offset, plane_size) in plane_offsets.iter().zip(plane_sizes.iter()) {
+        let compressed = Reader::new(gamemaps, *plane_offset).bytes(*plane_size)?;
+        let plane = carmack_expand(compressed)
+            .and_then(|rlew| rlew_expand(&rlew, rlew_tag))
+            .ok_or_else(|| format!("\"{}\" has a corrupt plane", name))?;
+        if plane.len() < width * height {
+            return Err(format!("\"{}\" has a plane that's too small", name));
+        }
+
+        planes.push(plane[..width * height].to_vec());
+    }
+
+    Ok(WolfMap {
+        name,
+        width,
+        height,
+        planes,
+    })
+}
+
+/// Expands Carmack compressed data, which starts with its expanded size in
+/// bytes, to words
+fn carmack_expand(data: &[u8]) -> Option<Vec<u16>> {
+    let mut reader = Reader::new(data, 0);
+    let expanded_len = reader.u16().ok()? as usize / 2;
+    let mut expanded: Vec<u16> = Vec::with_capacity(expanded_len);
+    while expanded.len() < expanded_len {
+        let word = reader.u16().ok()?;
+        let (tag, count) = ((word >> 8) as u8, (word & 0xff) as usize);
+        if tag != NEAR_TAG && tag != FAR_TAG {
+            expanded.push(word);
+            continue;
+        }
+
+        // A zero count escapes a word whose high byte looks like a tag
+        if count == 0 {
+            expanded.push((word & 0xff00) | reader.u8().ok()? as u16);
+            continue;
+        }
+
+        let copy_from = if tag == NEAR_TAG {
+            expanded.len().checked_sub(reader.u8().ok()? as usize)?
+        } else {
+            reader.u16().ok()? as usize
+        };
+        // Copies can overlap the words they produce
+        for i in 0..count {
+            expanded.push(*expanded.get(copy_from + i)?);
+        }
+    }
+
+    expanded.truncate(expanded_len);
+    Some(expanded)
+}
+
+/// Expands RLEW compressed words, which start with their expanded size in
+/// bytes. Runs are the tag followed by a count and the repeated word.
+fn rlew_expand(words: &[u16], tag: u16) -> Option<Vec<u16>> {
+    let (expanded_len, words) = words.split_first()?;
+    let expanded_len = *expanded_len as usize / 2;
+    let mut words = words.iter();
+
+    let mut expanded = Vec::with_capacity(expanded_len);
+    while expanded.len() < expanded_len {
+        let word = *words.next()?;
+        if word != tag {
+            expanded.push(word);
+            continue;
+        }
+
+        let count = *words.next()? as usize;
+        let value = *words.next()?;
+        expanded.extend(std::iter::repeat(value).take(count));
+    }
+
+    expanded.truncate(expanded_len);
+    Some(expanded)
+}
+
+/// Reads the wall chunks as textures, with the palette turning their palette
+/// indices into colours. Missing chunks are None.
+pub fn read_walls(files: &WolfFiles, palette: &Palette) -> Result<Vec<Option<Texture>>, AssetError> {
+    let vswap = fs::read(&files.vswap).map_err(|err| AssetError::Io(files.vswap.clone(), err))?;
+    let import_error = |err: String| AssetError::Import(files.vswap.clone(), err);
+
+    let mut reader = Reader::new(&vswap, 0);
+    let chunk_count = reader.u16().map_err(import_error)? as usize;
+    let sprite_start = reader.u16().map_err(import_error)? as usize;
+    let _sound_start = reader.u16().map_err(import_error)?;
+    let mut chunk_offsets = Vec::with_capacity(chunk_count);
+    for _ in 0..chunk_count {
+        chunk_offsets.push(reader.u32().map_err(import_error)? as usize);
+    }
+
+    let wall_len = (WALL_SIZE * WALL_SIZE) as usize;
+    let mut walls = vec![];
+    for (chunk, offset) in chunk_offsets.iter().take(sprite_start).enumerate() {
+        // Chunks left out of this data set
+        if *offset == 0 {
+            walls.push(None);
+            continue;
+        }
+
+        let indices = Reader::new(&vswap, *offset)
+            .bytes(wall_len)
+            .map_err(|err| import_error(format!("wall {}: {}", chunk, err)))?;
+        let mut texels = vec![0; wall_len * 4];
+        for (index, palette_index) in indices.iter().enumerate() {
+            // Column major to row major
+            let (x, y) = (index / WALL_SIZE as usize, index % WALL_SIZE as usize);
+            let texel = (y * WALL_SIZE as usize + x) * 4;
+            texels[texel..texel + 3].copy_from_slice(&palette.color(*palette_index));
+            texels[texel + 3] = 255;
+        }
+
+        let tex = Texture::from_rgba(WALL_SIZE, WALL_SIZE, &texels)
+            .map_err(|err| import_error(format!("wall {}: {}", chunk, err)))?;
+        walls.push(Some(tex));
+    }
+
+    Ok(walls)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    const RLEW_TAG: u16 = 0xabcd;
+
+    fn bytes(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+    }
+
+    /// A plane RLEW and Carmack compressed without any runs or references
+    fn plane(tiles: &[u16]) -> Vec<u8> {
+        let mut rlew = vec![(tiles.len() * 2) as u16];
+        rlew.extend_from_slice(tiles);
+        let mut carmack = vec![(rlew.len() * 2) as u16];
+        carmack.extend_from_slice(&rlew);
+
+        bytes(&carmack)
+    }
+
+    /// GAMEMAPS with a map per name, each `width` x 1 tiles with every
+    /// plane's tiles counting up from its first tile
+    fn gamemaps(names: &[&str], width: u16) -> (Vec<u8>, Vec<u32>) {
+        let mut gamemaps = b"TED5v1.0".to_vec();
+        let mut map_offsets = vec![];
+        for (map_index, name) in names.iter().enumerate() {
+            let mut plane_offsets = vec![];
+            let mut plane_sizes = vec![];
+            for plane_index in 0..PLANE_COUNT as u16 {
+                let first = map_index as u16 * 10 + plane_index;
+                let plane = plane(&(first..first + width).collect::<Vec<u16>>());
+                plane_offsets.push(gamemaps.len() as u32);
+                plane_sizes.push(plane.len() as u16);
+                gamemaps.extend_from_slice(&plane);
+            }
+
+            map_offsets.push(gamemaps.len() as u32);
+            for plane_offset in plane_offsets {
+                gamemaps.extend_from_slice(&plane_offset.to_le_bytes());
+            }
+            gamemaps.extend_from_slice(&bytes(&plane_sizes));
+            gamemaps.extend_from_slice(&bytes(&[width, 1]));
+            let mut name_bytes = [0; MAP_NAME_LEN];
+            name_bytes[..name.len()].copy_from_slice(name.as_bytes());
+            gamemaps.extend_from_slice(&name_bytes);
+        }
+
+        (gamemaps, map_offsets)
+    }
+
+    fn maphead(map_offsets: &[u32]) -> Vec<u8> {
+        let mut maphead = RLEW_TAG.to_le_bytes().to_vec();
+        for offset in map_offsets {
+            maphead.extend_from_slice(&offset.to_le_bytes());
+        }
+
+        maphead
+    }
+
+    /// Writes the data files to a directory of their own
+    fn write_files(test: &str, maphead: &[u8], gamemaps: &[u8], vswap: &[u8]) -> WolfFiles {
+        let dir = env::temp_dir().join(format!("rustic-fps-wolf-{}-{}", process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        let files = WolfFiles {
+            maphead: dir.join("MAPHEAD.WL6"),
+            gamemaps: dir.join("GAMEMAPS.WL6"),
+            vswap: dir.join("VSWAP.WL6"),
+        };
+        fs::write(&files.maphead, maphead).unwrap();
+        fs::write(&files.gamemaps, gamemaps).unwrap();
+        fs::write(&files.vswap, vswap).unwrap();
+
+        files
+    }
+
+    fn remove_files(files: &WolfFiles) {
+        fs::remove_dir_all(files.maphead.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn carmack_expands_near_far_and_escaped_words() {
+        let mut data = bytes(&[18, 1, 2]);
+        // Near copy of 2 words from 2 words back
+        data.extend_from_slice(&[2, NEAR_TAG, 2]);
+        // Far copy of 3 words from word 1
+        data.extend_from_slice(&[3, FAR_TAG]);
+        data.extend_from_slice(&bytes(&[1]));
+        // Zero counts escape words with a tag as their high byte
+        data.extend_from_slice(&[0, NEAR_TAG, 0x12]);
+        data.extend_from_slice(&[0, FAR_TAG, 0x34]);
+
+        assert_eq!(
+            carmack_expand(&data),
+            Some(vec![1, 2, 1, 2, 2, 1, 2, 0xa712, 0xa834])
+        );
+    }
+
+    #[test]
+    fn carmack_copies_can_overlap_their_output() {
+        let mut data = bytes(&[10, 7]);
+        data.extend_from_slice(&[4, NEAR_TAG, 1]);
+
+        assert_eq!(carmack_expand(&data), Some(vec![7, 7, 7, 7, 7]));
+    }
+
+    #[test]
+    fn carmack_rejects_bad_references_and_truncated_data() {
+        let mut before_start = bytes(&[6, 1]);
+        before_start.extend_from_slice(&[2, NEAR_TAG, 3]);
+        let mut past_end = bytes(&[6, 1]);
+        past_end.extend_from_slice(&[2, FAR_TAG]);
+        past_end.extend_from_slice(&bytes(&[4]));
+
+        assert_eq!(carmack_expand(&before_start), None);
+        assert_eq!(carmack_expand(&past_end), None);
+        assert_eq!(carmack_expand(&bytes(&[6, 1, 2])), None);
+        assert_eq!(carmack_expand(&[0, NEAR_TAG, 1]), None);
+        assert_eq!(carmack_expand(&[6]), None);
+    }
+
+    #[test]
+    fn rlew_expands_runs() {
+        let words = [12, 5, RLEW_TAG, 3, 9, RLEW_TAG, 0, 1, 6, RLEW_TAG, 1, RLEW_TAG];
+
+        assert_eq!(rlew_expand(&words, RLEW_TAG), Some(vec![5, 9, 9, 9, 6, RLEW_TAG]));
+        assert_eq!(rlew_expand(&[6, RLEW_TAG, 3], RLEW_TAG), None);
+        assert_eq!(rlew_expand(&[], RLEW_TAG), None);
+    }
+
+    #[test]
+    fn read_maps_keeps_level_numbers_of_empty_slots() {
+        let (gamemaps, map_offsets) = gamemaps(&["First", "Third"], 3);
+        let maphead = maphead(&[map_offsets[0], 0, map_offsets[1], 0xffff_ffff]);
+        let files = write_files("slots", &maphead, &gamemaps, &[]);
+        let maps = read_maps(&files);
+        remove_files(&files);
+        let maps = maps.unwrap();
+
+        assert_eq!(maps.len(), 4);
+        assert!(maps[1].is_none() && maps[3].is_none());
+        let first = maps[0].as_ref().unwrap();
+        assert_eq!((first.name.as_str(), first.width, first.height), ("First", 3, 1));
+        assert_eq!(first.planes, vec![vec![0, 1, 2], vec![1, 2, 3], vec![2, 3, 4]]);
+        let third = maps[2].as_ref().unwrap();
+        assert_eq!(third.name, "Third");
+        assert_eq!(third.planes[0], vec![10, 11, 12]);
+    }
+
+    #[test]
+    fn read_maps_fails_on_truncated_files() {
+        let (gamemaps, map_offsets) = gamemaps(&["First"], 3);
+        let maphead = maphead(&map_offsets);
+
+        let truncated_gamemaps = write_files("truncated-gamemaps", &maphead, &gamemaps[..20], &[]);
+        let truncated = read_maps(&truncated_gamemaps);
+        remove_files(&truncated_gamemaps);
+        assert!(truncated.is_err());
+
+        let truncated_maphead = write_files("truncated-maphead", &maphead[..1], &gamemaps, &[]);
+        let truncated = read_maps(&truncated_maphead);
+        remove_files(&truncated_maphead);
+        assert!(truncated.is_err());
+    }
+
+    /// VSWAP with a wall chunk, a missing one and a sprite chunk
+    fn vswap(wall: &[u8]) -> Vec<u8> {
+        let header_len = 6 + 3 * 4 + 3 * 2;
+        let mut vswap = bytes(&[3, 2, 3]);
+        for offset in [header_len, 0, header_len + wall.len() as u32].iter() {
+            vswap.extend_from_slice(&offset.to_le_bytes());
+        }
+        vswap.extend_from_slice(&bytes(&[wall.len() as u16, 0, 0]));
+        vswap.extend_from_slice(wall);
+
+        vswap
+    }
+
+    #[test]
+    fn read_walls_converts_columns_to_texels() {
+        let wall: Vec<u8> = (0..WALL_SIZE * WALL_SIZE).map(|index| (index % 251) as u8).collect();
+        let files = write_files("walls", &[], &[], &vswap(&wall));
+        let walls = read_walls(&files, &Palette::generated());
+        remove_files(&files);
+        let walls = walls.unwrap();
+
+        assert_eq!(walls.len(), 2);
+        assert!(walls[1].is_none());
+        let tex = walls[0].as_ref().unwrap();
+        let palette = Palette::generated();
+        // Wall data goes down the first column before the second one
+        for &(x, y, index) in [(0, 1, 1), (1, 0, WALL_SIZE as u8), (2, 3, 131)].iter() {
+            let color = tex.pixel(x, y);
+            assert_eq!([color.r, color.g, color.b], palette.color(index));
+            assert_eq!(color.a, 255);
+        }
+    }
+
+    #[test]
+    fn read_walls_fails_on_truncated_files() {
+        let wall = vec![0; (WALL_SIZE * WALL_SIZE) as usize];
+        let vswap = vswap(&wall);
+
+        for len in [3, 10, vswap.len() - 1].iter() {
+            let files = write_files(&format!("truncated-vswap-{}", len), &[], &[], &vswap[..*len]);
+            let walls = read_walls(&files, &Palette::generated());
+            remove_files(&files);
+            assert!(walls.is_err());
+        }
+    }
+}